@@ -0,0 +1,156 @@
+/// Declare an action enum along with the name used in the command line and
+/// the config file, and the description shown on the help screen
+macro_rules! actions {
+    (
+        $(#[$meta:meta])*
+        pub enum $enum:ident {
+            $($variant:ident => ($name:literal, $description:literal),)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $enum {
+            $($variant,)*
+        }
+
+        impl $enum {
+            pub const ALL: &[$enum] = &[$($enum::$variant,)*];
+
+            /// Name of the action in the command line and the config file
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($enum::$variant => $name,)*
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self {
+                    $($enum::$variant => $description,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|action| action.name() == name)
+            }
+        }
+    };
+}
+
+actions! {
+    /// Actions of normal mode, bound to keys and reachable by name from the
+    /// command line
+    pub enum Action {
+        Quit => ("quit", "Quit application"),
+        Accept => ("accept", "Accept results and quit"),
+        ToggleDetail => ("detail", "Toggle detail view"),
+        ToggleRendered => ("rendered", "Toggle rendered Markdown view"),
+        EnterQuery => ("query", "Enter query mode"),
+        EnterCommand => ("command", "Open the command line"),
+        Help => ("help", "Show this help"),
+        TreeView => ("tree", "Enter tree view"),
+        Inspect => ("inspect", "Inspect the selected result"),
+        NextFile => ("next-file", "Next file"),
+        PreviousFile => ("prev-file", "Previous file"),
+        ToggleFileList => ("files", "Toggle file list"),
+        ToggleAllFiles => ("all-files", "Query all files"),
+        ToggleSidebar => ("sidebar", "Toggle header sidebar"),
+        Down => ("down", "Move down"),
+        Up => ("up", "Move up"),
+        ScrollLeft => ("left", "Scroll wide tables left"),
+        ScrollRight => ("right", "Scroll wide tables right"),
+        PageDown => ("page-down", "Page down"),
+        PageUp => ("page-up", "Page up"),
+        First => ("first", "Jump to first item"),
+        Last => ("last", "Jump to last item"),
+        ClearQuery => ("clear", "Clear query"),
+        Copy => ("copy", "Copy result to clipboard"),
+        Reload => ("reload", "Reload file from disk"),
+        Open => ("open", "Open another file"),
+        Write => ("write", "Write results to a file"),
+    }
+}
+
+actions! {
+    /// Actions of query mode. Unbound printable keys insert their character.
+    pub enum QueryAction {
+        Submit => ("submit", "Execute query (newline in multi-line)"),
+        Execute => ("execute", "Execute query"),
+        Exit => ("exit", "Exit query mode"),
+        Accept => ("accept", "Accept results and quit"),
+        SearchHistory => ("search-history", "Search query history"),
+        Complete => ("complete", "Complete selectors and functions"),
+        Up => ("up", "Previous line or history entry"),
+        Down => ("down", "Next line or history entry"),
+        ToggleMultiline => ("toggle-multiline", "Toggle multi-line editing"),
+        Newline => ("newline", "Insert a newline"),
+        ShrinkEditor => ("shrink-editor", "Shrink the multi-line editor"),
+        GrowEditor => ("grow-editor", "Grow the multi-line editor"),
+        Left => ("left", "Move left"),
+        Right => ("right", "Move right"),
+        Home => ("home", "Jump to start of query"),
+        End => ("end", "Jump to end of query"),
+        LineStart => ("line-start", "Move to start of line"),
+        LineEnd => ("line-end", "Move to end of line"),
+        WordLeft => ("word-left", "Move back a word"),
+        WordRight => ("word-right", "Move forward a word"),
+        Backspace => ("backspace", "Delete previous character"),
+        Delete => ("delete", "Delete next character"),
+        KillWhitespaceWord => ("kill-whitespace-word", "Kill previous word"),
+        KillWordBackward => ("kill-word-backward", "Kill previous word part"),
+        KillWordForward => ("kill-word-forward", "Kill next word"),
+        KillToLineStart => ("kill-to-line-start", "Kill to start of line"),
+        KillToLineEnd => ("kill-to-line-end", "Kill to end of line"),
+        Yank => ("yank", "Yank killed text"),
+        YankPop => ("yank-pop", "Cycle through older kills"),
+        Undo => ("undo", "Undo"),
+        Redo => ("redo", "Redo"),
+    }
+}
+
+actions! {
+    /// Actions of tree view mode
+    pub enum TreeAction {
+        Exit => ("exit", "Exit tree view"),
+        Quit => ("quit", "Quit application"),
+        Down => ("down", "Move down in tree"),
+        Up => ("up", "Move up in tree"),
+        Toggle => ("toggle", "Expand/collapse node"),
+        Help => ("help", "Show this help"),
+    }
+}
+
+actions! {
+    /// Actions of inspect mode, which moves through the detail view
+    pub enum InspectAction {
+        Exit => ("exit", "Back to the results"),
+        Quit => ("quit", "Quit application"),
+        Down => ("down", "Move down in detail view"),
+        Up => ("up", "Move up in detail view"),
+        PageDown => ("page-down", "Page down in detail view"),
+        PageUp => ("page-up", "Page up in detail view"),
+        Toggle => ("toggle", "Expand/collapse child"),
+        Help => ("help", "Show this help"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_names_roundtrip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(*action));
+        }
+        for action in QueryAction::ALL {
+            assert_eq!(QueryAction::from_name(action.name()), Some(*action));
+        }
+        for action in TreeAction::ALL {
+            assert_eq!(TreeAction::from_name(action.name()), Some(*action));
+        }
+        for action in InspectAction::ALL {
+            assert_eq!(InspectAction::from_name(action.name()), Some(*action));
+        }
+        assert!(Action::from_name("unknown").is_none());
+    }
+}
@@ -1,19 +1,36 @@
-use arboard::Clipboard;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use miette::IntoDiagnostic;
-use mq_lang::Engine;
-use mq_markdown::Markdown;
+use miette::{IntoDiagnostic, miette};
 use ratatui::prelude::*;
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     fmt::Display,
-    io::Stdout,
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::JoinHandle,
     time::{Duration, Instant},
 };
 
 use crate::{
+    action::{Action, InspectAction, QueryAction, TreeAction},
+    clipboard::{self, ClipboardMode, Copied},
+    command::{self, Command, CommandLine, Setting},
+    completion::{self, Completion},
+    document::{self, Document},
+    editor::{KillRing, Snapshot, UndoStack},
+    eval::{self, EvalError, Evaluator, ParsedDocuments},
     event::{EventHandler, EventHandlerExt},
-    ui::{draw_ui, treeview::TreeView},
-    util,
+    export::{self, ExportFormat},
+    history::{self, History, HistorySearch},
+    keymap::Keymap,
+    picker::{FilePicker, SavePrompt},
+    text,
+    theme::{self, Theme},
+    ui::{ResultsCache, draw_ui, inspector::Inspector, treeview::TreeView},
+    util::{self, TerminalWriter},
+    worker::{self, JobDocument, QueryJob, QueryLimits, QueryOutcome, QueryWorker},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +39,35 @@ pub enum Mode {
     Query,
     Help,
     TreeView,
+    /// Command line for app commands such as `write` or `set`
+    Command,
+    /// Moving through the detail view of the selected result
+    Inspect,
+}
+
+/// How often files are checked for changes in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long messages stay in the status line
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+/// Default number of visible lines in the multi-line query editor
+const DEFAULT_EDITOR_HEIGHT: u16 = 5;
+/// Minimum number of visible lines in the multi-line query editor
+const MIN_EDITOR_HEIGHT: u16 = 2;
+/// Maximum number of visible lines in the multi-line query editor
+const MAX_EDITOR_HEIGHT: u16 = 30;
+
+/// Columns wide tables in the results scroll by
+const TABLE_SCROLL_STEP: usize = 8;
+
+/// What to print to stdout when the app exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintTarget {
+    /// The query results as Markdown
+    Results,
+    /// The query string
+    Query,
 }
 
 impl Display for Mode {
@@ -31,41 +77,115 @@ impl Display for Mode {
             Mode::Query => write!(f, "QUERY"),
             Mode::Help => write!(f, "HELP"),
             Mode::TreeView => write!(f, "TREE VIEW"),
+            Mode::Command => write!(f, "COMMAND"),
+            Mode::Inspect => write!(f, "INSPECT"),
         }
     }
 }
 
 pub struct App {
-    /// The Markdown content to process
-    content: String,
+    /// The loaded Markdown documents
+    documents: Vec<Document>,
+    /// Index of the document currently being viewed
+    active_document: usize,
+    /// Run the query over every loaded document
+    all_files_mode: bool,
+    /// Show the file list pane
+    show_file_list: bool,
     /// The query to run on the Markdown content
     query: String,
     /// The current results from the query
     results: Vec<mq_markdown::Node>,
+    /// Index of the document each result came from (all files mode only)
+    result_sources: Vec<usize>,
     /// Currently selected result index
     selected_idx: usize,
     /// Last query execution time
     last_exec_time: Duration,
+    /// Time spent parsing documents for the last query (`None` if all were cached)
+    last_parse_time: Option<Duration>,
+    /// Parsed documents, reused across queries
+    parsed: ParsedDocuments,
+    /// Query engine used when no background worker is running
+    evaluator: Evaluator,
+    /// Background thread evaluating queries (started by `run`)
+    worker: Option<QueryWorker>,
+    /// Threads of abandoned queries, which run until the query ends
+    abandoned_workers: Vec<JoinHandle<()>>,
+    /// Id of the last submitted query
+    next_query_id: u64,
+    /// Id and start time of the query running on the worker
+    running_query: Option<(u64, Instant)>,
+    /// Timeout and result cap for queries
+    limits: QueryLimits,
+    /// Message flashed in the status line and when it was set
+    status_message: Option<(String, Instant)>,
+    /// Reload documents when their files change on disk
+    watch: bool,
+    /// When the files were last checked for changes
+    last_watch_check: Instant,
     /// Last query execution timestamp
     last_exec: Instant,
     /// Should the application exit
     should_quit: bool,
+    /// Whether the user accepted the results before exiting
+    accepted: bool,
+    /// What to print to stdout on exit, regardless of how the app was quit
+    print_on_exit: Option<PrintTarget>,
     /// Error message if the query fails
     error_msg: Option<String>,
+    /// Byte range in the query that the last query error points at
+    error_span: Option<Range<usize>>,
     /// Current app mode
     mode: Mode,
     /// Show detailed view of selected item
     show_detail: bool,
+    /// Show results rendered instead of as Markdown source
+    rendered_view: bool,
+    /// Horizontal scroll offset of tables in the results
+    table_scroll: usize,
     /// History of executed queries
-    query_history: Vec<String>,
+    query_history: History,
     /// Current position in query history
     history_position: Option<usize>,
+    /// Reverse-incremental history search popup state (query mode only)
+    history_search: Option<HistorySearch>,
+    /// Text deleted by kill commands, for yanking back
+    kill_ring: KillRing,
+    /// Undo/redo stacks for the query
+    undo_stack: UndoStack,
+    /// Last edit in the query editor, for joining kills, typing and yanks
+    last_edit: LastEdit,
+    /// Completion popup state (query mode only)
+    completion: Option<Completion>,
+    /// File picker popup state (normal mode only)
+    file_picker: Option<FilePicker>,
+    /// Prompt for the file to write the results to (normal mode only)
+    save_prompt: Option<SavePrompt>,
+    /// Command line state (command mode only)
+    command_line: CommandLine,
+    /// Key bindings of each mode
+    keymap: Keymap,
+    /// Styles used to draw the UI
+    theme: Theme,
+    /// Lines of the results list, cleared when the results or their looks change
+    results_cache: RefCell<ResultsCache>,
+    /// User-defined themes from the config file, for `set theme`
+    user_themes: HashMap<String, HashMap<String, String>>,
+    /// How results are copied to the clipboard
+    clipboard_mode: ClipboardMode,
+    /// OSC 52 sequence waiting to be written to the terminal
+    clipboard_output: Option<String>,
     /// Current cursor position in query string
     cursor_position: usize,
-    /// Filename (if loaded from a file)
-    filename: Option<String>,
+    /// Multi-line query editing mode
+    multiline: bool,
+    /// Number of visible lines in the multi-line query editor
+    editor_height: u16,
     /// Tree view component
     tree_view: Option<TreeView>,
+    /// Detail view of the selected result in inspect mode
+    inspector: Option<Inspector>,
     /// Show tree sidebar in Normal mode
     show_tree_sidebar: bool,
     /// Sidebar tree view (headers only)
@@ -78,24 +198,87 @@ pub struct App {
     debounce_duration: Duration,
 }
 
+/// Kind of the last edit made in the query editor
+#[derive(Debug, Clone, Default)]
+enum LastEdit {
+    #[default]
+    Other,
+    /// Typed a character
+    Insert,
+    /// Deleted text into the kill ring
+    Kill,
+    /// Inserted text from the kill ring at the given byte range
+    Yank(Range<usize>),
+}
+
 impl App {
     pub fn new(content: String) -> Self {
+        Self::with_documents(vec![Document::new(content)])
+    }
+
+    pub fn with_file(content: String, filename: String) -> Self {
+        Self::with_documents(vec![Document::with_file(content, filename)])
+    }
+
+    pub fn with_documents(documents: Vec<Document>) -> Self {
+        let documents = if documents.is_empty() {
+            vec![Document::new(String::new())]
+        } else {
+            documents
+        };
+
         let mut app = Self {
-            content: content.clone(),
+            documents,
+            active_document: 0,
+            all_files_mode: false,
+            show_file_list: false,
             query: String::new(),
             results: Vec::new(),
+            result_sources: Vec::new(),
             selected_idx: 0,
             last_exec_time: Duration::from_millis(0),
+            last_parse_time: None,
+            parsed: ParsedDocuments::default(),
+            evaluator: Evaluator::default(),
+            worker: None,
+            abandoned_workers: Vec::new(),
+            next_query_id: 0,
+            running_query: None,
+            limits: QueryLimits::default(),
+            status_message: None,
+            watch: false,
+            last_watch_check: Instant::now(),
             last_exec: Instant::now(),
             should_quit: false,
+            accepted: false,
+            print_on_exit: None,
             error_msg: None,
+            error_span: None,
             mode: Mode::Normal,
             show_detail: false,
-            query_history: Vec::new(),
+            rendered_view: false,
+            table_scroll: 0,
+            query_history: History::disabled(),
             history_position: None,
+            history_search: None,
+            kill_ring: KillRing::default(),
+            undo_stack: UndoStack::default(),
+            last_edit: LastEdit::Other,
+            completion: None,
+            file_picker: None,
+            save_prompt: None,
+            command_line: CommandLine::default(),
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            results_cache: RefCell::default(),
+            user_themes: HashMap::new(),
+            clipboard_mode: ClipboardMode::default(),
+            clipboard_output: None,
             cursor_position: 0,
-            filename: None,
+            multiline: false,
+            editor_height: DEFAULT_EDITOR_HEIGHT,
             tree_view: None,
+            inspector: None,
             show_tree_sidebar: false,
             sidebar_tree_view: None,
             all_nodes: Vec::new(),
@@ -106,16 +289,11 @@ impl App {
         app
     }
 
-    pub fn with_file(content: String, filename: String) -> Self {
-        let mut app = Self::new(content);
-        app.filename = Some(filename);
-        app
-    }
-
     pub fn run(&mut self) -> miette::Result<()> {
         let mut terminal = util::setup_terminal()?;
         let events = EventHandler::new(Duration::from_millis(100));
 
+        self.start_query_worker();
         self.exec_query();
 
         while !self.should_quit {
@@ -123,6 +301,14 @@ impl App {
 
             if let Some(event) = events.next()? {
                 self.handle_event(event)?;
+                self.flush_clipboard(terminal.backend_mut())?;
+            }
+
+            self.poll_query();
+
+            if self.watch && self.last_watch_check.elapsed() >= WATCH_INTERVAL {
+                self.last_watch_check = Instant::now();
+                self.reload_changed_documents();
             }
 
             // Check if we should execute a pending query (debounce)
@@ -133,10 +319,13 @@ impl App {
 
         util::restore_terminal()?;
 
-        Ok(())
+        self.query_history.save()
     }
 
-    fn draw(&self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> miette::Result<()> {
+    fn draw(
+        &self,
+        terminal: &mut Terminal<CrosstermBackend<TerminalWriter>>,
+    ) -> miette::Result<()> {
         terminal
             .draw(|frame| draw_ui(frame, self))
             .into_diagnostic()?;
@@ -145,123 +334,265 @@ impl App {
 
     pub fn handle_event(&mut self, event: Event) -> miette::Result<()> {
         self.error_msg = None;
+
+        // Esc or Ctrl+C cancels a running query, unless they close a popup first
+        if self.running_query.is_some()
+            && !self.has_popup()
+            && let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            }) = event
+            && (code == KeyCode::Esc
+                || (code == KeyCode::Char('c') && modifiers == KeyModifiers::CONTROL))
+        {
+            self.cancel_query();
+            return Ok(());
+        }
+
         match self.mode {
             Mode::Normal => self.handle_normal_mode_event(event),
             Mode::Query => self.handle_query_mode_event(event),
             Mode::Help => self.handle_help_mode_event(event),
             Mode::TreeView => self.handle_tree_view_mode_event(event),
+            Mode::Command => self.handle_command_mode_event(event),
+            Mode::Inspect => self.handle_inspect_mode_event(event),
         }
     }
 
+    /// Whether a popup or sub-mode is open that Esc would close
+    fn has_popup(&self) -> bool {
+        !matches!(self.mode, Mode::Normal | Mode::Query)
+            || self.completion.is_some()
+            || self.history_search.is_some()
+            || self.file_picker.is_some()
+            || self.save_prompt.is_some()
+    }
+
     fn handle_normal_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if self.file_picker.is_some() {
+            return self.handle_file_picker_event(event);
+        }
+
+        if self.save_prompt.is_some() {
+            return self.handle_save_prompt_event(event);
+        }
+
         if let Event::Key(KeyEvent {
             code,
             modifiers,
             kind: KeyEventKind::Press,
             ..
         }) = event
+            && let Some(action) = self.keymap.normal.action(code, modifiers)
         {
-            match (code, modifiers) {
-                // Quit on Escape or q
-                (KeyCode::Char('q'), _) | (KeyCode::Esc, _) => {
-                    self.should_quit = true;
-                }
-                // Toggle detailed view
-                (KeyCode::Char('d'), _) => {
-                    self.show_detail = !self.show_detail;
-                }
-                // Enter query mode
-                (KeyCode::Char(':'), _) => {
-                    self.mode = Mode::Query;
-                    self.cursor_position = self.query.len();
+            self.perform_action(action);
+        }
+
+        Ok(())
+    }
+
+    /// Run a normal mode action, whether bound to a key or invoked by name
+    pub fn perform_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => {
+                self.quit();
+            }
+            Action::Accept => {
+                self.accept();
+            }
+            Action::ToggleDetail => {
+                self.show_detail = !self.show_detail;
+            }
+            Action::ToggleRendered => {
+                self.rendered_view = !self.rendered_view;
+                self.results_cache.get_mut().clear();
+            }
+            Action::EnterQuery => {
+                self.mode = Mode::Query;
+                self.cursor_position = self.query.len();
+            }
+            Action::EnterCommand => {
+                self.mode = Mode::Command;
+                self.command_line = CommandLine::default();
+            }
+            Action::Help => {
+                self.mode = Mode::Help;
+            }
+            Action::TreeView => {
+                self.mode = Mode::TreeView;
+                self.init_tree_view();
+            }
+            Action::Inspect => {
+                if let Some(node) = self.results.get(self.selected_idx) {
+                    self.inspector = Some(Inspector::new(node.clone()));
+                    self.show_detail = true;
+                    self.mode = Mode::Inspect;
+                } else {
+                    self.set_status_message("No result to inspect");
                 }
-                // Show help
-                (KeyCode::Char('?'), _) | (KeyCode::F(1), _) => {
-                    self.mode = Mode::Help;
+            }
+            Action::NextFile => {
+                self.next_document();
+            }
+            Action::PreviousFile => {
+                self.previous_document();
+            }
+            Action::ToggleFileList => {
+                self.toggle_file_list();
+            }
+            Action::ToggleAllFiles => {
+                self.toggle_all_files_mode();
+            }
+            Action::ToggleSidebar => {
+                self.toggle_tree_sidebar();
+                // Update selection when sidebar is shown
+                if self.show_tree_sidebar {
+                    self.update_sidebar_selection();
                 }
-                // Toggle tree view
-                (KeyCode::Char('t'), _) => {
-                    self.mode = Mode::TreeView;
-                    self.init_tree_view();
-                }
-                // Toggle tree sidebar
-                (KeyCode::Char('s'), _) => {
-                    self.toggle_tree_sidebar();
-                    // Update selection when sidebar is shown
-                    if self.show_tree_sidebar {
-                        self.update_sidebar_selection();
+            }
+            Action::Down => {
+                if self.show_tree_sidebar && self.sidebar_tree_view.is_some() {
+                    if let Some(sidebar) = &mut self.sidebar_tree_view {
+                        sidebar.move_down();
                     }
+                    self.update_sidebar_selection();
+                } else if !self.results.is_empty() {
+                    self.selected_idx = (self.selected_idx + 1) % self.results.len();
                 }
-                // Navigate results or sidebar
-                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                    if self.show_tree_sidebar && self.sidebar_tree_view.is_some() {
-                        if let Some(sidebar) = &mut self.sidebar_tree_view {
-                            sidebar.move_down();
-                        }
-                        self.update_sidebar_selection();
-                    } else if !self.results.is_empty() {
-                        self.selected_idx = (self.selected_idx + 1) % self.results.len();
+            }
+            Action::Up => {
+                if self.show_tree_sidebar && self.sidebar_tree_view.is_some() {
+                    if let Some(sidebar) = &mut self.sidebar_tree_view {
+                        sidebar.move_up();
                     }
+                    self.update_sidebar_selection();
+                } else if !self.results.is_empty() {
+                    self.selected_idx = if self.selected_idx > 0 {
+                        self.selected_idx - 1
+                    } else {
+                        self.results.len() - 1
+                    };
                 }
-                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                    if self.show_tree_sidebar && self.sidebar_tree_view.is_some() {
-                        if let Some(sidebar) = &mut self.sidebar_tree_view {
-                            sidebar.move_up();
-                        }
-                        self.update_sidebar_selection();
-                    } else if !self.results.is_empty() {
-                        self.selected_idx = if self.selected_idx > 0 {
-                            self.selected_idx - 1
-                        } else {
-                            self.results.len() - 1
-                        };
-                    }
+            }
+            Action::ScrollLeft => {
+                self.table_scroll = self.table_scroll.saturating_sub(TABLE_SCROLL_STEP);
+            }
+            Action::ScrollRight => {
+                // Stop where drawing stops, at the widest table's right edge
+                let max_scroll = self.results_cache.get_mut().max_table_scroll();
+                self.table_scroll = (self.table_scroll + TABLE_SCROLL_STEP).min(max_scroll);
+            }
+            Action::PageDown => {
+                if !self.results.is_empty() {
+                    self.selected_idx = (self.selected_idx + 10).min(self.results.len() - 1);
                 }
-                // Select header in sidebar (Enter key - content already updated by navigation)
-                (KeyCode::Enter, _) => {
-                    // Content is already updated by update_sidebar_selection()
-                    // Enter key can be used for future actions if needed
+            }
+            Action::PageUp => {
+                if !self.results.is_empty() {
+                    self.selected_idx = self.selected_idx.saturating_sub(10);
                 }
-                (KeyCode::PageDown, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = (self.selected_idx + 10).min(self.results.len() - 1);
-                    }
+            }
+            Action::First => {
+                if !self.results.is_empty() {
+                    self.selected_idx = 0;
                 }
-                (KeyCode::PageUp, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = self.selected_idx.saturating_sub(10);
-                    }
+            }
+            Action::Last => {
+                if !self.results.is_empty() {
+                    self.selected_idx = self.results.len() - 1;
                 }
-                (KeyCode::Home, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = 0;
-                    }
+            }
+            Action::ClearQuery => {
+                self.query.clear();
+                self.cursor_position = 0;
+                self.exec_query();
+            }
+            Action::Copy => {
+                self.copy_results();
+            }
+            Action::Reload => {
+                self.reload_document();
+            }
+            Action::Open => {
+                self.open_file_picker();
+            }
+            Action::Write => {
+                if self.results.is_empty() {
+                    self.set_status_message("No results to write");
+                } else {
+                    self.save_prompt = Some(SavePrompt::default());
                 }
-                (KeyCode::End, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = self.results.len() - 1;
-                    }
+            }
+        }
+    }
+
+    /// Copy the results as Markdown, with the system clipboard or OSC 52
+    fn copy_results(&mut self) {
+        if self.results.is_empty() {
+            return;
+        }
+
+        let result_text = mq_markdown::Markdown::new(self.results.clone()).to_string();
+        let tmux = std::env::var_os("TMUX").is_some();
+        match clipboard::copy(&result_text, self.clipboard_mode, tmux) {
+            Ok(Copied::System) => {
+                self.set_status_message(format!("Copied {} results", self.results.len()));
+            }
+            Ok(Copied::Osc52(sequence)) => {
+                self.clipboard_output = Some(sequence);
+                self.set_status_message(format!("Copied {} results (OSC 52)", self.results.len()));
+            }
+            Err(err) => {
+                self.error_msg = Some(err);
+            }
+        }
+    }
+
+    /// Write a pending OSC 52 sequence to the terminal
+    pub fn flush_clipboard(&mut self, writer: &mut impl Write) -> miette::Result<()> {
+        if let Some(sequence) = self.clipboard_output.take() {
+            writer.write_all(sequence.as_bytes()).into_diagnostic()?;
+            writer.flush().into_diagnostic()?;
+        }
+        Ok(())
+    }
+
+    fn handle_command_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.mode = Mode::Normal;
                 }
-                // Clear query with Ctrl+L
-                (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
-                    self.query.clear();
-                    self.cursor_position = 0;
-                    self.exec_query();
+                (KeyCode::Enter, _) => {
+                    let input = std::mem::take(&mut self.command_line).input;
+                    self.mode = Mode::Normal;
+                    self.run_command(&input);
                 }
-                (KeyCode::Char('y'), _) => {
-                    if !self.results.is_empty() {
-                        let result_text =
-                            mq_markdown::Markdown::new(self.results.clone()).to_string();
-                        if let Ok(mut clipboard) = Clipboard::new() {
-                            if clipboard.set_text(result_text).is_ok() {
-                            } else {
-                                self.error_msg =
-                                    Some("Error: Could not copy to clipboard".to_string());
-                            }
-                        } else {
-                            self.error_msg = Some("Error: Could not access clipboard".to_string());
-                        }
-                    }
+                (KeyCode::Tab, _) => {
+                    let themes = self.theme_names();
+                    self.command_line.complete(true, &themes);
+                }
+                (KeyCode::BackTab, _) => {
+                    let themes = self.theme_names();
+                    self.command_line.complete(false, &themes);
+                }
+                // Deleting past the prompt leaves the command line
+                (KeyCode::Backspace, _) if self.command_line.input.is_empty() => {
+                    self.mode = Mode::Normal;
+                }
+                (KeyCode::Backspace, _) => {
+                    self.command_line.pop();
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.command_line.push(c);
                 }
                 _ => {}
             }
@@ -270,7 +601,80 @@ impl App {
         Ok(())
     }
 
+    /// Parse and run a command line such as `write out.md`, showing errors in
+    /// the error popup
+    pub fn run_command(&mut self, input: &str) {
+        let result = command::parse(input)
+            .map_err(|msg| miette!(msg))
+            .and_then(|command| self.execute_command(command));
+
+        self.error_msg = result.err().map(|err| err.to_string());
+    }
+
+    fn execute_command(&mut self, command: Command) -> miette::Result<()> {
+        match command {
+            Command::Action(action) => self.perform_action(action),
+            Command::Write { path, overwrite } => {
+                self.export_results(ExportFormat::Markdown, &path, overwrite)?;
+            }
+            Command::Export {
+                format,
+                path,
+                overwrite,
+            } => {
+                self.export_results(format, &path, overwrite)?;
+            }
+            Command::Open(path) => {
+                self.open_file(&path)
+                    .map_err(|err| miette!("Failed to open {}: {}", path.display(), err))?;
+            }
+            Command::Set(Setting::Timeout(timeout)) => {
+                self.limits.timeout = timeout;
+                self.set_status_message(format!("timeout = {}s", timeout.as_secs_f64()));
+            }
+            Command::Set(Setting::MaxResults(max_results)) => {
+                self.limits.max_results = max_results;
+                self.set_status_message(format!("max_results = {}", max_results));
+            }
+            Command::Set(Setting::Theme(name)) => {
+                self.set_theme(Theme::load(&name, &self.user_themes)?);
+                self.set_status_message(format!("theme = {}", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the current results to `path` in the given format
+    fn export_results(
+        &mut self,
+        format: ExportFormat,
+        path: &Path,
+        overwrite: bool,
+    ) -> miette::Result<()> {
+        let sources = (0..self.result_sources.len())
+            .filter_map(|idx| self.result_source(idx))
+            .collect::<Vec<_>>();
+        let content = export::export_results(format, &self.results, &sources);
+        export::write_file(path, &content, overwrite)?;
+
+        self.set_status_message(format!(
+            "Wrote {} results to {}",
+            self.results.len(),
+            path.display()
+        ));
+        Ok(())
+    }
+
     fn handle_query_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if self.history_search.is_some() {
+            return self.handle_history_search_event(event);
+        }
+
+        if self.completion.is_some() && self.handle_completion_event(&event) {
+            return Ok(());
+        }
+
         if let Event::Key(KeyEvent {
             code,
             modifiers,
@@ -278,90 +682,240 @@ impl App {
             ..
         }) = event
         {
-            match (code, modifiers) {
-                // Exit query mode on Escape
-                (KeyCode::Esc, _) => {
+            let last_edit = std::mem::take(&mut self.last_edit);
+
+            let Some(action) = self.keymap.query.action(code, modifiers) else {
+                match (code, modifiers) {
+                    // Edit query
+                    (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                        // Typing is undone a word at a time
+                        let starts_word = !c.is_whitespace()
+                            && self.query[..self.cursor_position]
+                                .chars()
+                                .next_back()
+                                .is_some_and(char::is_whitespace);
+                        self.record_undo(matches!(last_edit, LastEdit::Insert) && !starts_word);
+                        self.insert_str(c.encode_utf8(&mut [0; 4]));
+                        self.last_edit = LastEdit::Insert;
+                    }
+                    // AltGr is reported as Ctrl+Alt on some platforms
+                    (KeyCode::Char(c), modifiers)
+                        if modifiers == KeyModifiers::CONTROL | KeyModifiers::ALT =>
+                    {
+                        self.record_undo(false);
+                        self.insert_str(c.encode_utf8(&mut [0; 4]));
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            };
+
+            match action {
+                // Accept results and quit
+                QueryAction::Accept => {
+                    self.submit_query();
+                    self.accept();
+                }
+                // Search history
+                QueryAction::SearchHistory => {
+                    self.completion = None;
+                    self.history_search = Some(HistorySearch::default());
+                }
+                // Complete the word under the cursor
+                QueryAction::Complete => {
+                    self.completion =
+                        completion::complete(&self.query, self.cursor_position, &self.all_nodes);
+
+                    // Insert a single candidate directly
+                    if self
+                        .completion
+                        .as_ref()
+                        .is_some_and(|completion| completion.candidates.len() == 1)
+                    {
+                        self.apply_completion();
+                    }
+                }
+                // Exit query mode without executing
+                QueryAction::Exit => {
                     self.mode = Mode::Normal;
                     self.history_position = None;
                 }
-                // Execute query on Enter
-                (KeyCode::Enter, _) => {
-                    self.mode = Mode::Normal;
-                    if !self.query.is_empty() {
-                        // Add query to history if it's not a duplicate
-                        if self.query_history.is_empty()
-                            || self.query_history.last() != Some(&self.query)
-                        {
-                            self.query_history.push(self.query.clone());
-                        }
+                // Toggle multi-line editing
+                QueryAction::ToggleMultiline => {
+                    self.multiline = !self.multiline;
+                }
+                // Insert a newline and switch to multi-line editing
+                QueryAction::Newline => {
+                    self.multiline = true;
+                    self.record_undo(false);
+                    self.insert_str("\n");
+                }
+                // Execute query, also in multi-line mode
+                QueryAction::Execute => {
+                    self.submit_query();
+                }
+                // Enter inserts a newline in multi-line mode
+                QueryAction::Submit if self.multiline => {
+                    self.record_undo(false);
+                    self.insert_str("\n");
+                }
+                QueryAction::Submit => {
+                    self.submit_query();
+                }
+                // Resize the multi-line editor
+                QueryAction::ShrinkEditor => {
+                    self.editor_height =
+                        self.editor_height.saturating_sub(1).max(MIN_EDITOR_HEIGHT);
+                }
+                QueryAction::GrowEditor => {
+                    self.editor_height = (self.editor_height + 1).min(MAX_EDITOR_HEIGHT);
+                }
+                // Readline-style motion
+                QueryAction::LineStart => {
+                    self.cursor_position = self.line_start(self.cursor_position);
+                }
+                QueryAction::LineEnd => {
+                    self.cursor_position = self.line_end(self.cursor_position);
+                }
+                QueryAction::WordLeft => {
+                    self.cursor_position = text::prev_word_start(&self.query, self.cursor_position);
+                }
+                QueryAction::WordRight => {
+                    self.cursor_position = text::next_word_end(&self.query, self.cursor_position);
+                }
+                // Kill text into the kill ring
+                QueryAction::KillWhitespaceWord => {
+                    let start = text::prev_whitespace_word_start(&self.query, self.cursor_position);
+                    self.kill(start..self.cursor_position, true, &last_edit);
+                }
+                QueryAction::KillWordBackward => {
+                    let start = text::prev_word_start(&self.query, self.cursor_position);
+                    self.kill(start..self.cursor_position, true, &last_edit);
+                }
+                QueryAction::KillWordForward => {
+                    let end = text::next_word_end(&self.query, self.cursor_position);
+                    self.kill(self.cursor_position..end, false, &last_edit);
+                }
+                QueryAction::KillToLineStart => {
+                    let start = self.line_start(self.cursor_position);
+                    self.kill(start..self.cursor_position, true, &last_edit);
+                }
+                QueryAction::KillToLineEnd => {
+                    let mut end = self.line_end(self.cursor_position);
+                    // At the end of a line, kill the line break instead
+                    if end == self.cursor_position && end < self.query.len() {
+                        end += 1;
                     }
-                    self.history_position = None;
-                    self.query_pending = false;
-                    self.exec_query();
+                    self.kill(self.cursor_position..end, false, &last_edit);
                 }
-                // Edit query
-                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
-                    self.query.insert(self.cursor_position, c);
-                    self.cursor_position += 1;
-                    self.last_exec = Instant::now();
-                    self.query_pending = true;
+                // Yank killed text back
+                QueryAction::Yank => {
+                    if let Some(killed) = self.kill_ring.yank().map(str::to_string) {
+                        let start = self.cursor_position;
+                        self.record_undo(false);
+                        self.insert_str(&killed);
+                        self.last_edit = LastEdit::Yank(start..self.cursor_position);
+                    }
                 }
-                (KeyCode::Backspace, _) => {
-                    if self.cursor_position > 0 {
-                        self.query.remove(self.cursor_position - 1);
-                        self.cursor_position -= 1;
-                        self.last_exec = Instant::now();
-                        self.query_pending = true;
+                // Replace the text just yanked with an older kill
+                QueryAction::YankPop => {
+                    if let LastEdit::Yank(range) = last_edit
+                        && let Some(killed) = self.kill_ring.yank_pop().map(str::to_string)
+                    {
+                        self.record_undo(false);
+                        self.query.replace_range(range.clone(), &killed);
+                        self.cursor_position = range.start + killed.len();
+                        self.mark_query_changed();
+                        self.last_edit = LastEdit::Yank(range.start..self.cursor_position);
                     }
                 }
-                (KeyCode::Delete, _) => {
-                    if self.cursor_position < self.query.len() {
-                        self.query.remove(self.cursor_position);
-                        self.last_exec = Instant::now();
-                        self.query_pending = true;
+                QueryAction::Undo => {
+                    if let Some(snapshot) = self.undo_stack.undo(self.snapshot()) {
+                        self.restore(snapshot);
+                    }
+                }
+                QueryAction::Redo => {
+                    if let Some(snapshot) = self.undo_stack.redo(self.snapshot()) {
+                        self.restore(snapshot);
                     }
                 }
-                // Move cursor
-                (KeyCode::Left, _) => {
+                QueryAction::Backspace => {
                     if self.cursor_position > 0 {
-                        self.cursor_position -= 1;
+                        self.record_undo(false);
+                        let start = text::prev_grapheme_boundary(&self.query, self.cursor_position);
+                        self.query.replace_range(start..self.cursor_position, "");
+                        self.cursor_position = start;
+                        self.mark_query_changed();
+                    }
+                }
+                QueryAction::Delete => {
+                    if self.cursor_position < self.query.len() {
+                        self.record_undo(false);
+                        let end = text::next_grapheme_boundary(&self.query, self.cursor_position);
+                        self.query.replace_range(self.cursor_position..end, "");
+                        self.mark_query_changed();
                     }
                 }
-                (KeyCode::Right, _) => {
+                // Move cursor by grapheme cluster
+                QueryAction::Left => {
+                    self.cursor_position =
+                        text::prev_grapheme_boundary(&self.query, self.cursor_position);
+                }
+                QueryAction::Right => {
                     if self.cursor_position < self.query.len() {
-                        self.cursor_position += 1;
+                        self.cursor_position =
+                            text::next_grapheme_boundary(&self.query, self.cursor_position);
                     }
                 }
-                (KeyCode::Home, _) => {
+                QueryAction::Home if self.multiline => {
+                    self.cursor_position = self.line_start(self.cursor_position);
+                }
+                QueryAction::End if self.multiline => {
+                    self.cursor_position = self.line_end(self.cursor_position);
+                }
+                QueryAction::Home => {
                     self.cursor_position = 0;
                 }
-                (KeyCode::End, _) => {
+                QueryAction::End => {
                     self.cursor_position = self.query.len();
                 }
+                // Move between lines in multi-line mode
+                QueryAction::Up if self.multiline => {
+                    self.move_cursor_up();
+                }
+                QueryAction::Down if self.multiline => {
+                    self.move_cursor_down();
+                }
                 // Navigate history
-                (KeyCode::Up, _) => {
+                QueryAction::Up => {
                     if !self.query_history.is_empty() {
+                        self.record_undo(false);
                         match self.history_position {
                             None => {
                                 self.history_position = Some(self.query_history.len() - 1);
-                                self.query =
-                                    self.query_history[self.history_position.unwrap()].clone();
+                                self.query = self.query_history.entries()
+                                    [self.history_position.unwrap()]
+                                .clone();
                             }
                             Some(pos) if pos > 0 => {
                                 self.history_position = Some(pos - 1);
-                                self.query =
-                                    self.query_history[self.history_position.unwrap()].clone();
+                                self.query = self.query_history.entries()
+                                    [self.history_position.unwrap()]
+                                .clone();
                             }
                             _ => {}
                         }
                         self.cursor_position = self.query.len();
                     }
                 }
-                (KeyCode::Down, _) => {
+                QueryAction::Down => {
                     if let Some(pos) = self.history_position {
+                        self.record_undo(false);
                         if pos < self.query_history.len() - 1 {
                             self.history_position = Some(pos + 1);
-                            self.query = self.query_history[self.history_position.unwrap()].clone();
+                            self.query = self.query_history.entries()
+                                [self.history_position.unwrap()]
+                            .clone();
                         } else {
                             self.history_position = None;
                             self.query.clear();
@@ -369,65 +923,503 @@ impl App {
                         self.cursor_position = self.query.len();
                     }
                 }
-
-                _ => {}
             }
         }
 
         Ok(())
     }
 
-    fn handle_help_mode_event(&mut self, event: Event) -> miette::Result<()> {
-        if let Event::Key(KeyEvent {
+    /// Insert text at the cursor and schedule the query for execution
+    fn insert_str(&mut self, s: &str) {
+        self.query.insert_str(self.cursor_position, s);
+        self.cursor_position += s.len();
+        self.mark_query_changed();
+    }
+
+    /// Delete `range` from the query into the kill ring.
+    ///
+    /// Consecutive kills are joined into a single kill ring entry.
+    fn kill(&mut self, range: Range<usize>, backward: bool, last_edit: &LastEdit) {
+        if range.is_empty() {
+            self.last_edit = last_edit.clone();
+            return;
+        }
+
+        let killed = self.query[range.clone()].to_string();
+        if matches!(last_edit, LastEdit::Kill) {
+            self.kill_ring.extend(&killed, backward);
+        } else {
+            self.kill_ring.push(killed);
+        }
+
+        self.record_undo(false);
+        self.query.replace_range(range.clone(), "");
+        self.cursor_position = range.start;
+        self.mark_query_changed();
+        self.last_edit = LastEdit::Kill;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.query.clone(),
+            cursor: self.cursor_position,
+        }
+    }
+
+    /// Save the current query so the next edit can be undone
+    fn record_undo(&mut self, coalesce: bool) {
+        let snapshot = self.snapshot();
+        self.undo_stack.record(snapshot, coalesce);
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.query = snapshot.text;
+        self.cursor_position = snapshot.cursor.min(self.query.len());
+        self.mark_query_changed();
+    }
+
+    /// Schedule the changed query for execution after the debounce delay
+    fn mark_query_changed(&mut self) {
+        self.last_exec = Instant::now();
+        self.query_pending = true;
+    }
+
+    /// Byte offset of the start of the line containing `pos`
+    fn line_start(&self, pos: usize) -> usize {
+        self.query[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Byte offset of the end of the line containing `pos`
+    fn line_end(&self, pos: usize) -> usize {
+        self.query[pos..]
+            .find('\n')
+            .map_or(self.query.len(), |i| pos + i)
+    }
+
+    /// Move the cursor to the same column on the previous line
+    fn move_cursor_up(&mut self) {
+        let start = self.line_start(self.cursor_position);
+        if start == 0 {
+            return;
+        }
+
+        let column = text::display_width(&self.query[start..self.cursor_position]);
+        let prev_start = self.line_start(start - 1);
+        self.cursor_position =
+            prev_start + text::byte_offset_at_column(&self.query[prev_start..start - 1], column);
+    }
+
+    /// Move the cursor to the same column on the next line
+    fn move_cursor_down(&mut self) {
+        let end = self.line_end(self.cursor_position);
+        if end == self.query.len() {
+            return;
+        }
+
+        let start = self.line_start(self.cursor_position);
+        let column = text::display_width(&self.query[start..self.cursor_position]);
+        let next_end = self.line_end(end + 1);
+        self.cursor_position =
+            end + 1 + text::byte_offset_at_column(&self.query[end + 1..next_end], column);
+    }
+
+    /// Handle keys for the open completion popup. Returns `true` if the event was consumed.
+    fn handle_completion_event(&mut self, event: &Event) -> bool {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
             kind: KeyEventKind::Press,
             ..
         }) = event
-        {
-            self.mode = Mode::Normal;
+        else {
+            return false;
+        };
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+
+        match (code, *modifiers) {
+            (KeyCode::Tab, _) | (KeyCode::Down, _) => {
+                completion.move_down();
+            }
+            (KeyCode::BackTab, _) | (KeyCode::Up, _) => {
+                completion.move_up();
+            }
+            (KeyCode::Enter, _) => {
+                self.apply_completion();
+            }
+            (KeyCode::Esc, _) => {
+                self.completion = None;
+            }
+            // Keep editing; candidates are refreshed afterwards
+            (KeyCode::Char(_), KeyModifiers::NONE | KeyModifiers::SHIFT)
+            | (KeyCode::Backspace, _) => {
+                self.completion = None;
+                if let Err(err) = self.handle_query_mode_event(event.clone()) {
+                    self.error_msg = Some(err.to_string());
+                }
+                self.completion =
+                    completion::complete(&self.query, self.cursor_position, &self.all_nodes);
+            }
+            _ => {
+                self.completion = None;
+                return false;
+            }
         }
 
-        Ok(())
+        true
     }
 
-    fn handle_tree_view_mode_event(&mut self, event: Event) -> miette::Result<()> {
+    /// Replace the word under the cursor with the selected completion candidate
+    fn apply_completion(&mut self) {
+        if let Some(completion) = self.completion.take()
+            && let Some((query, cursor_position)) =
+                completion.apply(&self.query, self.cursor_position)
+        {
+            self.record_undo(false);
+            self.query = query;
+            self.cursor_position = cursor_position;
+            self.mark_query_changed();
+        }
+    }
+
+    fn handle_history_search_event(&mut self, event: Event) -> miette::Result<()> {
         if let Event::Key(KeyEvent {
             code,
             modifiers,
             kind: KeyEventKind::Press,
             ..
         }) = event
+            && let Some(search) = &mut self.history_search
         {
             match (code, modifiers) {
+                // Cancel search and keep the current query
+                (KeyCode::Esc, _) | (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                    self.history_search = None;
+                }
+                // Load the selected query into the editor
+                (KeyCode::Enter, _) => {
+                    if let Some(query) = search.selected_match(&self.query_history) {
+                        let query = query.to_string();
+                        self.record_undo(false);
+                        self.query = query;
+                        self.cursor_position = self.query.len();
+                        self.history_position = None;
+                        self.last_exec = Instant::now();
+                        self.query_pending = true;
+                    }
+                    self.history_search = None;
+                }
+                // Move to an older match
+                (KeyCode::Down, _) | (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                    let match_count = search.matches(&self.query_history).len();
+                    search.move_down(match_count);
+                }
+                // Move to a newer match
+                (KeyCode::Up, _) | (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                    search.move_up();
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    search.input.push(c);
+                    search.selected = 0;
+                }
+                (KeyCode::Backspace, _) => {
+                    search.input.pop();
+                    search.selected = 0;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_file_picker_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+            && let Some(picker) = &mut self.file_picker
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.file_picker = None;
+                }
+                // Open the selected file, or the typed path
+                (KeyCode::Enter, _) => {
+                    if let Some(path) = picker.selected_path() {
+                        self.file_picker = None;
+                        if let Err(err) = self.open_file(&path) {
+                            self.error_msg =
+                                Some(format!("Failed to open {}: {}", path.display(), err));
+                        }
+                    }
+                }
+                (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                    let match_count = picker.matches().len();
+                    picker.move_down(match_count);
+                }
+                (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                    picker.move_up();
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    picker.input.push(c);
+                    picker.selected = 0;
+                }
+                (KeyCode::Backspace, _) => {
+                    picker.input.pop();
+                    picker.selected = 0;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_save_prompt_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+            && let Some(prompt) = &mut self.save_prompt
+        {
+            if prompt.confirm_overwrite {
+                match code {
+                    KeyCode::Char('y' | 'Y') => self.write_prompted_results(true),
+                    // Back to editing the path
+                    KeyCode::Char('n' | 'N') | KeyCode::Esc => prompt.confirm_overwrite = false,
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.save_prompt = None;
+                }
+                (KeyCode::Enter, _) if prompt.input.trim().is_empty() => {}
+                (KeyCode::Enter, _) => {
+                    if Path::new(prompt.input.trim()).exists() {
+                        prompt.confirm_overwrite = true;
+                    } else {
+                        self.write_prompted_results(false);
+                    }
+                }
+                (KeyCode::Tab, _) => {
+                    prompt.complete();
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    prompt.input.push(c);
+                }
+                (KeyCode::Backspace, _) => {
+                    prompt.input.pop();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the results as Markdown to the path typed into the save prompt
+    /// and close the prompt
+    fn write_prompted_results(&mut self, overwrite: bool) {
+        let Some(prompt) = self.save_prompt.take() else {
+            return;
+        };
+
+        let path = PathBuf::from(prompt.input.trim());
+        if let Err(err) = self.export_results(ExportFormat::Markdown, &path, overwrite) {
+            self.error_msg = Some(err.to_string());
+        }
+    }
+
+    /// Show the file picker listing the Markdown files below the working directory
+    fn open_file_picker(&mut self) {
+        let (files, truncated) = document::find_markdown_files(Path::new("."));
+        let files = files
+            .into_iter()
+            .map(|file| match file.strip_prefix(".") {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => file,
+            })
+            .collect();
+        self.file_picker = Some(FilePicker::new(files));
+        if truncated {
+            self.set_status_message("Too many files, type a path to open others");
+        }
+    }
+
+    /// Leave query mode, record the query in history and execute it
+    fn submit_query(&mut self) {
+        self.mode = Mode::Normal;
+        if !self.query.is_empty() {
+            // Add query to history, dropping any older duplicate
+            self.query_history.push(&self.query);
+        }
+        self.history_position = None;
+        self.query_pending = false;
+        self.exec_query();
+    }
+
+    /// Quit, finishing the query first if its results are printed on exit
+    fn quit(&mut self) {
+        if self.print_on_exit == Some(PrintTarget::Results) {
+            self.finish_query();
+        }
+        self.should_quit = true;
+    }
+
+    /// Accept the current results and quit
+    fn accept(&mut self) {
+        self.finish_query();
+        self.accepted = true;
+        self.should_quit = true;
+    }
+
+    /// Run the pending query and wait for the running one, so the results
+    /// match the query
+    fn finish_query(&mut self) {
+        if self.query_pending {
+            self.exec_query();
+        }
+
+        while let Some((id, started)) = self.running_query {
+            let remaining = self.limits.timeout.saturating_sub(started.elapsed());
+            match self
+                .worker
+                .as_ref()
+                .and_then(|worker| worker.recv_timeout(remaining))
+            {
+                Some(outcome) if outcome.id == id => {
+                    self.running_query = None;
+                    self.apply_query_outcome(outcome);
+                }
+                // Results of a superseded query
+                Some(_) => {}
+                None => self.abandon_query(id, started),
+            }
+        }
+    }
+
+    /// Text to print to stdout after the terminal has been restored, if any
+    pub fn exit_output(&self) -> Option<String> {
+        let target = self
+            .print_on_exit
+            .or(self.accepted.then_some(PrintTarget::Results))?;
+
+        Some(match target {
+            PrintTarget::Results => mq_markdown::Markdown::new(self.results.clone()).to_string(),
+            PrintTarget::Query => self.query.clone(),
+        })
+    }
+
+    fn handle_help_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        {
+            self.mode = Mode::Normal;
+        }
+
+        Ok(())
+    }
+
+    fn handle_tree_view_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+            && let Some(action) = self.keymap.tree_view.action(code, modifiers)
+        {
+            match action {
                 // Exit tree view mode
-                (KeyCode::Esc, _) | (KeyCode::Char('t'), _) => {
+                TreeAction::Exit => {
                     self.mode = Mode::Normal;
                 }
-                // Quit
-                (KeyCode::Char('q'), _) => {
-                    self.should_quit = true;
+                TreeAction::Quit => {
+                    self.quit();
                 }
                 // Navigation
-                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                TreeAction::Down => {
                     if let Some(tree_view) = &mut self.tree_view {
                         tree_view.move_down();
                     }
                 }
-                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                TreeAction::Up => {
                     if let Some(tree_view) = &mut self.tree_view {
                         tree_view.move_up();
                     }
                 }
                 // Toggle expand/collapse
-                (KeyCode::Enter, _) | (KeyCode::Char(' '), _) => {
+                TreeAction::Toggle => {
                     if let Some(tree_view) = &mut self.tree_view {
                         tree_view.toggle_expand();
                     }
                 }
-                // Show help
-                (KeyCode::Char('?'), _) | (KeyCode::F(1), _) => {
+                TreeAction::Help => {
+                    self.mode = Mode::Help;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_inspect_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+            && let Some(action) = self.keymap.inspect.action(code, modifiers)
+        {
+            let theme = &self.theme;
+            match action {
+                InspectAction::Exit => {
+                    self.mode = Mode::Normal;
+                    self.inspector = None;
+                }
+                InspectAction::Quit => {
+                    self.quit();
+                }
+                InspectAction::Down => {
+                    if let Some(inspector) = &mut self.inspector {
+                        inspector.move_down(theme);
+                    }
+                }
+                InspectAction::Up => {
+                    if let Some(inspector) = &mut self.inspector {
+                        inspector.move_up(theme);
+                    }
+                }
+                InspectAction::PageDown => {
+                    if let Some(inspector) = &mut self.inspector {
+                        inspector.page_down(theme);
+                    }
+                }
+                InspectAction::PageUp => {
+                    if let Some(inspector) = &mut self.inspector {
+                        inspector.page_up(theme);
+                    }
+                }
+                InspectAction::Toggle => {
+                    if let Some(inspector) = &mut self.inspector {
+                        inspector.toggle(theme);
+                    }
+                }
+                InspectAction::Help => {
                     self.mode = Mode::Help;
                 }
-                _ => {}
             }
         }
 
@@ -435,12 +1427,11 @@ impl App {
     }
 
     fn init_tree_view(&mut self) {
-        let markdown_result = Markdown::from_markdown_str(&self.content);
-        match markdown_result {
-            Ok(markdown) => {
-                self.tree_view = Some(TreeView::new(markdown.nodes));
+        match self.active_nodes() {
+            Some(nodes) => {
+                self.tree_view = Some(TreeView::new(nodes));
             }
-            Err(_) => {
+            None => {
                 self.error_msg = Some("Failed to parse markdown for tree view".to_string());
             }
         }
@@ -470,6 +1461,8 @@ impl App {
                         selected_node.value()
                     );
                     self.results = section_content;
+                    self.results_cache.get_mut().clear();
+                    self.result_sources.clear();
                     self.selected_idx = 0;
                     self.cursor_position = self.query.len();
                 }
@@ -512,15 +1505,16 @@ impl App {
     }
 
     fn init_sidebar_tree_view(&mut self) {
-        let markdown_result = Markdown::from_markdown_str(&self.content);
-        match markdown_result {
-            Ok(markdown) => {
+        self.sidebar_tree_view = None;
+        self.all_nodes.clear();
+
+        match self.active_nodes() {
+            Some(nodes) => {
                 // Store all nodes for section extraction
-                self.all_nodes = markdown.nodes.clone();
+                self.all_nodes = nodes.clone();
 
                 // Extract only heading nodes for the sidebar
-                let headers: Vec<mq_markdown::Node> = markdown
-                    .nodes
+                let headers: Vec<mq_markdown::Node> = nodes
                     .into_iter()
                     .filter(|node| matches!(node, mq_markdown::Node::Heading(_)))
                     .collect();
@@ -531,7 +1525,7 @@ impl App {
                     self.sidebar_tree_view = Some(tree_view);
                 }
             }
-            Err(_) => {
+            None => {
                 // Silently fail for sidebar initialization
             }
         }
@@ -539,44 +1533,166 @@ impl App {
 
     pub fn exec_query(&mut self) {
         self.query_pending = false;
-        let mut engine: Engine = Engine::default();
-        engine.load_builtin_module();
-        let start = Instant::now();
-        let markdown_result = Markdown::from_markdown_str(&self.content);
-        match markdown_result {
-            Ok(markdown) => {
-                if !self.query.is_empty() {
-                    let md_nodes = markdown
-                        .nodes
-                        .into_iter()
-                        .map(mq_lang::RuntimeValue::from)
-                        .collect::<Vec<_>>();
-
-                    match engine.eval(&self.query, md_nodes.into_iter()) {
-                        Ok(results) => {
-                            self.results = results
-                                .into_iter()
-                                .map(|runtime_value| match runtime_value {
-                                    mq_lang::RuntimeValue::Markdown(node, _) => node.clone(),
-                                    _ => runtime_value.to_string().into(),
-                                })
-                                .collect();
-                            self.error_msg = None;
-                        }
-                        Err(err) => {
-                            self.error_msg = Some(format!("Query error: {}", err));
-                            // Keep previous results
-                        }
-                    }
-                } else {
-                    // Show all nodes when query is empty
-                    self.results = markdown.nodes;
-                    self.error_msg = None;
+        self.last_exec = Instant::now();
+        self.next_query_id += 1;
+        let id = self.next_query_id;
+
+        let targets = if self.all_files_mode {
+            (0..self.documents.len()).collect::<Vec<_>>()
+        } else {
+            vec![self.active_document]
+        };
+
+        let mut documents = Vec::new();
+        let mut parse_time = None;
+
+        for idx in targets {
+            let name = self
+                .all_files_mode
+                .then(|| self.documents[idx].name().to_string());
+
+            match self.parse_document(idx, &mut parse_time) {
+                Ok(nodes) => documents.push(JobDocument { idx, name, nodes }),
+                Err(err) => {
+                    self.last_parse_time = parse_time;
+                    self.running_query = None;
+                    self.apply_query_outcome(QueryOutcome {
+                        id,
+                        result: Err(match name {
+                            Some(name) => err.with_source(&name),
+                            None => err,
+                        }),
+                        eval_time: Duration::ZERO,
+                    });
+                    return;
                 }
             }
-            Err(err) => {
-                self.error_msg = Some(format!("Markdown parse error: {}", err));
+        }
+
+        self.last_parse_time = parse_time;
+        let job = QueryJob {
+            id,
+            query: self.query.clone(),
+            documents,
+            max_results: self.limits.max_results,
+        };
+
+        // A worker may be free again once an abandoned query ended
+        if self.worker.is_none() && !self.abandoned_workers.is_empty() {
+            self.start_query_worker();
+        }
+
+        match &self.worker {
+            Some(worker) => {
+                worker.submit(job);
+                // Supersedes any query still running
+                self.running_query = Some((id, Instant::now()));
+            }
+            None if !self.abandoned_workers.is_empty() => {
+                self.error_msg = Some(format!(
+                    "{} abandoned queries are still running, run the query again once they end",
+                    self.abandoned_workers.len()
+                ));
+                self.error_span = None;
+            }
+            None => {
+                let outcome = worker::run_job(&mut self.evaluator, eval::new_engine(), job);
+                self.apply_query_outcome(outcome);
+            }
+        }
+    }
+
+    /// Apply the results of queries finished by the background worker.
+    ///
+    /// Results of superseded or cancelled queries are discarded.
+    pub fn poll_query(&mut self) {
+        let Some(worker) = &self.worker else {
+            return;
+        };
+
+        let outcomes = std::iter::from_fn(|| worker.try_recv()).collect::<Vec<_>>();
+        for outcome in outcomes {
+            if self.running_query.is_some_and(|(id, _)| id == outcome.id) {
+                self.running_query = None;
+                self.apply_query_outcome(outcome);
+            }
+        }
+
+        // Abandon queries that run for too long
+        if let Some((id, started)) = self.running_query
+            && started.elapsed() >= self.limits.timeout
+        {
+            self.abandon_query(id, started);
+        }
+    }
+
+    /// Give up on a query that hit the timeout and start a fresh worker
+    fn abandon_query(&mut self, id: u64, started: Instant) {
+        self.running_query = None;
+        self.restart_query_worker();
+        self.apply_query_outcome(QueryOutcome {
+            id,
+            result: Err(EvalError::LimitExceeded),
+            eval_time: started.elapsed(),
+        });
+    }
+
+    /// Evaluate queries in the background so slow ones don't freeze the UI.
+    ///
+    /// No worker is started while too many abandoned queries are still running.
+    fn start_query_worker(&mut self) {
+        self.abandoned_workers
+            .retain(|thread| !thread.is_finished());
+        self.worker =
+            (self.abandoned_workers.len() < worker::MAX_ABANDONED_WORKERS).then(QueryWorker::spawn);
+    }
+
+    /// Abandon the worker with the running query and start a fresh one
+    fn restart_query_worker(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.abandoned_workers.push(worker.abandon());
+        }
+        self.start_query_worker();
+    }
+
+    /// Abandon the running query and start a fresh worker for the next one
+    pub fn cancel_query(&mut self) {
+        if self.running_query.take().is_some() {
+            self.restart_query_worker();
+            self.set_status_message("Query cancelled");
+        }
+    }
+
+    fn apply_query_outcome(&mut self, outcome: QueryOutcome) {
+        match outcome.result {
+            Ok((results, result_sources)) => {
+                self.results = results;
+                self.results_cache.get_mut().clear();
+                self.table_scroll = 0;
+                self.result_sources = if self.all_files_mode {
+                    result_sources
+                } else {
+                    Vec::new()
+                };
+                self.error_msg = None;
+                self.error_span = None;
+            }
+            Err(EvalError::Query(msg, span)) => {
+                self.error_msg = Some(format!("Query error: {}", msg));
+                self.error_span = span;
+                // Keep previous results
+            }
+            Err(EvalError::LimitExceeded) => {
+                self.error_msg = Some(self.limits.exceeded_message());
+                self.error_span = None;
+                // Keep previous results
+            }
+            Err(EvalError::Parse(msg)) => {
+                self.error_msg = Some(format!("Markdown parse error: {}", msg));
+                self.error_span = None;
                 self.results = Vec::new();
+                self.results_cache.get_mut().clear();
+                self.result_sources = Vec::new();
             }
         }
 
@@ -588,127 +1704,976 @@ impl App {
                 self.results.len() - 1
             };
         }
+        self.refresh_inspector();
 
-        self.last_exec_time = start.elapsed();
-        self.last_exec = Instant::now();
+        self.last_exec_time = outcome.eval_time;
     }
 
-    /// Get the current query string
-    pub fn query(&self) -> &str {
-        &self.query
+    /// Keep inspecting the selected result after the results changed. The
+    /// inspector starts over when the node differs, and inspect mode ends
+    /// when no result is left.
+    fn refresh_inspector(&mut self) {
+        let Some(inspector) = &self.inspector else {
+            return;
+        };
+
+        match self.results.get(self.selected_idx) {
+            Some(node) if node == inspector.node() => {}
+            Some(node) => self.inspector = Some(Inspector::new(node.clone())),
+            None => {
+                self.inspector = None;
+                if self.mode == Mode::Inspect {
+                    self.mode = Mode::Normal;
+                }
+            }
+        }
+    }
+
+    /// Parse the document at `idx` unless it's cached, adding the time spent to `parse_time`
+    fn parse_document(
+        &mut self,
+        idx: usize,
+        parse_time: &mut Option<Duration>,
+    ) -> Result<Arc<Vec<mq_markdown::Node>>, EvalError> {
+        let cached = self.parsed.is_parsed(idx);
+        let start = Instant::now();
+        let result = self.parsed.parse(idx, &self.documents[idx].content);
+
+        if !cached {
+            *parse_time = Some(parse_time.unwrap_or_default() + start.elapsed());
+        }
+
+        result
+    }
+
+    /// Parsed nodes of the active document, parsing it if needed
+    fn active_nodes(&mut self) -> Option<Vec<mq_markdown::Node>> {
+        let idx = self.active_document;
+        self.parse_document(idx, &mut None)
+            .ok()
+            .map(|nodes| nodes.to_vec())
+    }
+
+    /// Show a message in the status line for a few seconds
+    fn set_status_message(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
+    }
+
+    /// Switch to the next loaded document
+    pub fn next_document(&mut self) {
+        let next = (self.active_document + 1) % self.documents.len();
+        self.set_active_document(next);
+    }
+
+    /// Switch to the previous loaded document
+    pub fn previous_document(&mut self) {
+        let previous = if self.active_document > 0 {
+            self.active_document - 1
+        } else {
+            self.documents.len() - 1
+        };
+        self.set_active_document(previous);
+    }
+
+    /// Make the document at `idx` the active one and re-run the query
+    pub fn set_active_document(&mut self, idx: usize) {
+        if idx >= self.documents.len() || idx == self.active_document {
+            return;
+        }
+
+        self.active_document = idx;
+        self.selected_idx = 0;
+        self.tree_view = None;
+        self.init_sidebar_tree_view();
+        if self.mode == Mode::TreeView {
+            self.init_tree_view();
+        }
+        self.exec_query();
+    }
+
+    /// Replace the active document with the file at `path`.
+    ///
+    /// The query and history are kept and the query is re-run on the new document.
+    pub fn open_file(&mut self, path: &Path) -> miette::Result<()> {
+        let document = Document::from_path(path, path.display().to_string())?;
+        let message = format!("Opened {}", document.name());
+
+        self.documents[self.active_document] = document;
+        self.parsed.invalidate(self.active_document);
+        self.selected_idx = 0;
+        self.tree_view = None;
+        self.init_sidebar_tree_view();
+        if self.mode == Mode::TreeView {
+            self.init_tree_view();
+        }
+        self.exec_query();
+        self.set_status_message(message);
+        Ok(())
     }
 
-    /// Get the current results
-    pub fn results(&self) -> &[mq_markdown::Node] {
-        &self.results
+    /// Re-read the active document from disk and re-run the query
+    pub fn reload_document(&mut self) {
+        let idx = self.active_document;
+        if self.documents[idx].path.is_none() {
+            self.error_msg = Some("The document was not read from a file".to_string());
+            return;
+        }
+
+        if let Err(err) = self.documents[idx].reload() {
+            self.error_msg = Some(format!(
+                "Failed to reload {}: {}",
+                self.documents[idx].name(),
+                err
+            ));
+            return;
+        }
+
+        self.documents_changed(&[idx]);
+        self.set_status_message("reloaded");
+    }
+
+    /// Reload documents whose files changed on disk and re-run the query
+    pub fn reload_changed_documents(&mut self) {
+        let changed = (0..self.documents.len())
+            .filter(|&idx| self.documents[idx].changed_on_disk())
+            .collect::<Vec<_>>();
+
+        if changed.is_empty() {
+            return;
+        }
+
+        let mut reloaded = Vec::new();
+        let mut errors = Vec::new();
+        for idx in changed {
+            match self.documents[idx].reload() {
+                Ok(()) => reloaded.push(idx),
+                Err(err) => errors.push(format!(
+                    "Failed to reload {}: {}",
+                    self.documents[idx].name(),
+                    err
+                )),
+            }
+        }
+
+        // Documents that failed keep their old content and are retried
+        if !reloaded.is_empty() {
+            self.documents_changed(&reloaded);
+            self.set_status_message("reloaded");
+        }
+        if !errors.is_empty() {
+            self.error_msg = Some(errors.join("\n"));
+        }
+    }
+
+    /// Rebuild everything derived from the documents at `indices` after their
+    /// content changed, keeping the selection where possible
+    fn documents_changed(&mut self, indices: &[usize]) {
+        for &idx in indices {
+            self.parsed.invalidate(idx);
+        }
+
+        if indices.contains(&self.active_document) {
+            let sidebar_selected = self
+                .sidebar_tree_view
+                .as_ref()
+                .map(TreeView::selected_index);
+            self.init_sidebar_tree_view();
+            if let (Some(selected), Some(sidebar)) =
+                (sidebar_selected, self.sidebar_tree_view.as_mut())
+            {
+                sidebar.select(selected);
+            }
+
+            if self.tree_view.is_some()
+                && let Some(nodes) = self.active_nodes()
+                && let Some(tree_view) = self.tree_view.as_mut()
+            {
+                tree_view.set_nodes(nodes);
+            }
+        }
+
+        if self.all_files_mode || indices.contains(&self.active_document) {
+            self.exec_query();
+        }
+    }
+
+    /// Get the current query string
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Get the current results
+    pub fn results(&self) -> &[mq_markdown::Node] {
+        &self.results
+    }
+
+    /// Get the currently selected result index
+    pub fn selected_idx(&self) -> usize {
+        self.selected_idx
+    }
+
+    /// Get the last execution time
+    pub fn last_exec_time(&self) -> Duration {
+        self.last_exec_time
+    }
+
+    /// Time spent parsing documents for the last query, `None` if they were cached
+    pub fn last_parse_time(&self) -> Option<Duration> {
+        self.last_parse_time
+    }
+
+    /// How long the query on the background worker has been running, if any
+    pub fn query_running_time(&self) -> Option<Duration> {
+        self.running_query.map(|(_, started)| started.elapsed())
+    }
+
+    /// Message to flash in the status line, until it expires
+    pub fn status_message(&self) -> Option<&str> {
+        self.status_message
+            .as_ref()
+            .filter(|(_, set_at)| set_at.elapsed() < STATUS_MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+
+    /// Get the byte range in the query that the last query error points at
+    pub fn error_span(&self) -> Option<Range<usize>> {
+        self.error_span.clone()
+    }
+
+    /// Get the current error message, if any
+    pub fn error_msg(&self) -> Option<&str> {
+        self.error_msg.as_deref()
+    }
+
+    /// Get the current app mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Check if detailed view is enabled
+    pub fn show_detail(&self) -> bool {
+        self.show_detail
+    }
+
+    /// Check if results are shown rendered rather than as Markdown source
+    pub fn rendered_view(&self) -> bool {
+        self.rendered_view
+    }
+
+    /// Get the horizontal scroll offset of tables in the results
+    pub fn table_scroll(&self) -> usize {
+        self.table_scroll
+    }
+
+    /// Get the cursor position in the query
+    pub fn cursor_position(&self) -> usize {
+        self.cursor_position
+    }
+
+    /// Check if the query editor is in multi-line mode
+    pub fn multiline(&self) -> bool {
+        self.multiline
+    }
+
+    /// Number of visible lines in the multi-line query editor
+    pub fn editor_height(&self) -> u16 {
+        self.editor_height
+    }
+
+    /// Height of the query input pane, including its borders
+    pub fn query_input_height(&self) -> u16 {
+        if self.mode == Mode::Query && self.multiline {
+            self.editor_height + 2
+        } else {
+            3
+        }
+    }
+
+    /// Line and display column of the cursor in the query
+    pub fn cursor_line_col(&self) -> (usize, usize) {
+        let pos = text::floor_char_boundary(&self.query, self.cursor_position);
+        let line = self.query[..pos].matches('\n').count();
+        let column = text::display_width(&self.query[self.line_start(pos)..pos]);
+        (line, column)
+    }
+
+    /// Get the filename of the active document, if any
+    pub fn filename(&self) -> Option<&str> {
+        self.documents[self.active_document].filename.as_deref()
+    }
+
+    /// Get the Markdown content of the active document
+    pub fn content(&self) -> &str {
+        &self.documents[self.active_document].content
+    }
+
+    /// Get all loaded documents
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    /// Get the index of the active document
+    pub fn active_document(&self) -> usize {
+        self.active_document
+    }
+
+    /// Check if the query runs over all loaded documents
+    pub fn all_files_mode(&self) -> bool {
+        self.all_files_mode
+    }
+
+    /// Check if the file list pane is shown
+    pub fn show_file_list(&self) -> bool {
+        self.show_file_list
+    }
+
+    /// Index of the document each result came from (all files mode only)
+    pub fn result_sources(&self) -> &[usize] {
+        &self.result_sources
+    }
+
+    /// Get the filename of the document the result at `idx` came from (all files mode only)
+    pub fn result_source(&self, idx: usize) -> Option<&str> {
+        self.result_sources
+            .get(idx)
+            .map(|&doc_idx| self.documents[doc_idx].name())
+    }
+
+    /// Get the query history
+    pub fn query_history(&self) -> &[String] {
+        self.query_history.entries()
+    }
+
+    /// Get the completion popup state, if open
+    pub fn completion(&self) -> Option<&Completion> {
+        self.completion.as_ref()
+    }
+
+    /// Get the history search popup state, if open
+    pub fn history_search(&self) -> Option<&HistorySearch> {
+        self.history_search.as_ref()
+    }
+
+    pub fn command_line(&self) -> &CommandLine {
+        &self.command_line
+    }
+
+    pub fn file_picker(&self) -> Option<&FilePicker> {
+        self.file_picker.as_ref()
+    }
+
+    pub fn save_prompt(&self) -> Option<&SavePrompt> {
+        self.save_prompt.as_ref()
+    }
+
+    /// Get the history entries matching the current history search
+    pub fn history_search_matches(&self) -> Vec<&str> {
+        self.history_search
+            .as_ref()
+            .map(|search| search.matches(&self.query_history))
+            .unwrap_or_default()
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.cursor_position = self.query.len();
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Replace the key bindings, e.g. with ones from the config file
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    /// Reload documents when their files change on disk
+    pub fn set_watch(&mut self, watch: bool) {
+        self.watch = watch;
+    }
+
+    /// Set the timeout and result cap for queries
+    pub fn set_query_limits(&mut self, limits: QueryLimits) {
+        self.limits = limits;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn results_cache(&self) -> &RefCell<ResultsCache> {
+        &self.results_cache
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.results_cache.get_mut().clear();
+    }
+
+    /// Make the user-defined themes of the config file available to `set theme`
+    pub fn set_user_themes(&mut self, themes: HashMap<String, HashMap<String, String>>) {
+        self.user_themes = themes;
+    }
+
+    /// Names of the built-in and user-defined themes
+    fn theme_names(&self) -> Vec<String> {
+        let mut names = theme::BUILTIN_THEMES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(self.user_themes.keys().cloned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn set_clipboard_mode(&mut self, mode: ClipboardMode) {
+        self.clipboard_mode = mode;
+    }
+
+    /// Load the query history from disk and save it again on exit. Without
+    /// this the history is kept in memory only.
+    pub fn load_history(&mut self) {
+        self.query_history = History::load(history::default_path());
+    }
+
+    /// Print the given target to stdout whenever the app exits
+    pub fn set_print_on_exit(&mut self, target: Option<PrintTarget>) {
+        self.print_on_exit = target;
+    }
+
+    #[cfg(test)]
+    pub fn set_results(&mut self, results: Vec<mq_markdown::Node>) {
+        self.results = results;
+        self.results_cache.get_mut().clear();
+    }
+
+    #[cfg(test)]
+    pub fn set_last_exec_time(&mut self, duration: Duration) {
+        self.last_exec_time = duration;
+    }
+
+    #[cfg(test)]
+    pub fn set_error_msg(&mut self, msg: String) {
+        self.error_msg = Some(msg);
+    }
+
+    #[cfg(test)]
+    pub fn set_cursor_position(&mut self, position: usize) {
+        self.cursor_position = position;
+    }
+
+    /// Get the tree view, if available
+    pub fn tree_view(&self) -> Option<&TreeView> {
+        self.tree_view.as_ref()
+    }
+
+    /// Get the detail view of inspect mode, if active
+    pub fn inspector(&self) -> Option<&Inspector> {
+        self.inspector.as_ref()
+    }
+
+    /// Check if tree sidebar is shown
+    pub fn show_tree_sidebar(&self) -> bool {
+        self.show_tree_sidebar
+    }
+
+    /// Get the sidebar tree view, if available
+    pub fn sidebar_tree_view(&self) -> Option<&TreeView> {
+        self.sidebar_tree_view.as_ref()
+    }
+
+    /// Get mutable reference to sidebar tree view
+    pub fn sidebar_tree_view_mut(&mut self) -> Option<&mut TreeView> {
+        self.sidebar_tree_view.as_mut()
+    }
+
+    /// Toggle tree sidebar visibility
+    pub fn toggle_tree_sidebar(&mut self) {
+        self.show_tree_sidebar = !self.show_tree_sidebar;
+    }
+
+    /// Toggle file list visibility
+    pub fn toggle_file_list(&mut self) {
+        self.show_file_list = !self.show_file_list;
+    }
+
+    /// Toggle running the query over all loaded documents
+    pub fn toggle_all_files_mode(&mut self) {
+        self.all_files_mode = !self.all_files_mode;
+        self.exec_query();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use mq_markdown::Node;
+
+    use super::*;
+
+    fn create_test_app() -> App {
+        App::new("# Test\nSome content".to_string())
+    }
+
+    fn create_test_app_with_file() -> App {
+        App::with_file("# Test\nSome content".to_string(), "test.md".to_string())
+    }
+
+    fn wait_for_query(app: &mut App) {
+        let start = Instant::now();
+        while app.query_running_time().is_some() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+            app.poll_query();
+        }
+    }
+
+    #[test]
+    fn test_background_query_discards_superseded_results() {
+        let mut app = create_test_app();
+        app.start_query_worker();
+
+        app.set_query(".h | unknown_fn()".to_string());
+        app.exec_query();
+        app.set_query(String::new());
+        app.exec_query();
+        assert!(app.query_running_time().is_some());
+
+        wait_for_query(&mut app);
+        // Only the newest query is applied
+        assert!(app.error_msg().is_none());
+        assert_eq!(app.results().len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_running_query() {
+        let mut app = create_test_app();
+        app.start_query_worker();
+        app.set_query(".h".to_string());
+        app.exec_query();
+
+        // Esc cancels the query instead of quitting
+        app.handle_event(Event::Key(KeyEvent {
+            code: KeyCode::Esc,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        }))
+        .unwrap();
+
+        assert!(app.query_running_time().is_none());
+        assert!(!app.should_quit);
+        assert_eq!(app.status_message(), Some("Query cancelled"));
+
+        // The fresh worker keeps evaluating queries
+        app.exec_query();
+        wait_for_query(&mut app);
+        assert_eq!(app.results().len(), 2);
+    }
+
+    #[test]
+    fn test_exec_query_result_limit() {
+        let mut app = create_test_app();
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+
+        app.set_query_limits(QueryLimits {
+            max_results: 1,
+            ..QueryLimits::default()
+        });
+        app.set_query(".h".to_string());
+        app.exec_query();
+
+        assert_eq!(app.error_msg(), Some("query exceeded 2s / 1 results"));
+        // Previous results are kept
+        assert_eq!(app.results().len(), 2);
+    }
+
+    #[test]
+    fn test_reload_changed_documents() {
+        let path = std::env::temp_dir().join(format!("mq-tui-watch-{}.md", std::process::id()));
+        std::fs::write(&path, "# One\n\n## Two\n").unwrap();
+
+        let mut app = App::with_documents(vec![
+            Document::from_path(&path, "watch.md".to_string()).unwrap(),
+        ]);
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.selected_idx = 1;
+        assert_eq!(app.results().len(), 2);
+
+        // Nothing changed yet
+        app.reload_changed_documents();
+        assert!(app.status_message().is_none());
+
+        std::fs::write(&path, "# One\n\n## Two\n\n## Three\n").unwrap();
+        let later = std::time::SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        app.reload_changed_documents();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(app.status_message(), Some("reloaded"));
+        assert_eq!(app.results().len(), 3);
+        assert_eq!(app.selected_idx(), 1);
+        assert_eq!(app.query(), ".h");
+    }
+
+    #[test]
+    fn test_reload_changed_documents_with_error() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-watch-error-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.md");
+        let bad = dir.join("bad.md");
+        std::fs::write(&good, "# One\n").unwrap();
+        std::fs::write(&bad, "# Bad\n").unwrap();
+
+        let mut app = App::with_documents(vec![
+            Document::from_path(&good, "good.md".to_string()).unwrap(),
+            Document::from_path(&bad, "bad.md".to_string()).unwrap(),
+        ]);
+        app.toggle_all_files_mode();
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+
+        // The bad file is no longer valid UTF-8
+        std::fs::write(&good, "# One\n\n## Two\n").unwrap();
+        std::fs::write(&bad, [0xff, 0xfe]).unwrap();
+        let later = std::time::SystemTime::now() + Duration::from_secs(5);
+        for path in [&good, &bad] {
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        }
+
+        app.reload_changed_documents();
+        let error = app.error_msg().unwrap().to_string();
+
+        // The good file is reloaded and re-queried, the bad one is retried
+        assert!(error.contains("Failed to reload bad.md"));
+        assert!(error.contains(&bad.display().to_string()));
+        assert_eq!(app.results().len(), 3);
+        assert!(!app.documents[0].changed_on_disk());
+        assert!(app.documents[1].changed_on_disk());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_file_keeps_query_and_history() {
+        let path = std::env::temp_dir().join(format!("mq-tui-open-{}.md", std::process::id()));
+        std::fs::write(&path, "# One\n\n## Two\n\n## Three\n").unwrap();
+
+        let mut app = create_test_app();
+        app.query_history.push(".h");
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+
+        app.open_file(&path).unwrap();
+        assert_eq!(app.query(), ".h");
+        assert!(app.query_history().contains(&".h".to_string()));
+        assert_eq!(app.filename(), Some(path.display().to_string().as_str()));
+        assert_eq!(app.results().len(), 3);
+        assert!(app.status_message().unwrap().starts_with("Opened "));
+
+        // Reload with the r key after the file changed
+        std::fs::write(&path, "# One\n").unwrap();
+        app.handle_event(Event::Key(KeyEvent {
+            code: KeyCode::Char('r'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        }))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(app.results().len(), 1);
+        assert_eq!(app.status_message(), Some("reloaded"));
+
+        assert!(app.open_file(&path).is_err());
+        assert_eq!(app.query(), ".h");
+    }
+
+    #[test]
+    fn test_command_mode() {
+        let mut app = App::new("# One\n\n## Two\n".to_string());
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        app.handle_event(key(KeyCode::Char('p'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Command);
+        for c in "side".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        app.handle_event(key(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.command_line().input, "sidebar");
+
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.show_tree_sidebar());
+
+        // Backspace on an empty command line leaves command mode
+        app.handle_event(key(KeyCode::Char(';'), KeyModifiers::NONE))
+            .unwrap();
+        app.handle_event(key(KeyCode::Backspace, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_set_theme_command() {
+        let config = crate::config::Config::parse(
+            "[themes.paper]\nbase = \"light\"\nheader = \"blue bold\"\n",
+        )
+        .unwrap();
+        let mut app = create_test_app();
+        app.set_user_themes(config.themes.clone());
+        let key = |code| Event::Key(KeyEvent::from(code));
+
+        app.handle_event(key(KeyCode::Char(';'))).unwrap();
+        for c in "set theme p".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.command_line().input, "set theme paper");
+
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert!(app.error_msg().is_none());
+        assert_eq!(app.theme(), &Theme::load("paper", &config.themes).unwrap());
+        assert_eq!(app.status_message(), Some("theme = paper"));
+
+        app.run_command("set theme light");
+        assert_eq!(app.theme(), &Theme::light());
+
+        // Unknown themes keep the current one
+        app.run_command("set theme solarized");
+        assert!(app.error_msg().unwrap().contains("solarized"));
+        assert_eq!(app.theme(), &Theme::light());
+    }
+
+    #[test]
+    fn test_configured_key_bindings() {
+        let config = crate::config::Config::parse(
+            "[keys.normal]\nq = \"none\"\n\"ctrl+q\" = \"quit\"\n\n[keys.query]\n\"ctrl+j\" = \"submit\"\n",
+        )
+        .unwrap();
+        let mut app = create_test_app();
+        app.set_keymap(Keymap::from_config(&config.keys).unwrap());
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        // Unbound keys do nothing
+        app.handle_event(key(KeyCode::Char('q'), KeyModifiers::NONE))
+            .unwrap();
+        assert!(!app.should_quit);
+
+        app.handle_event(key(KeyCode::Char(':'), KeyModifiers::NONE))
+            .unwrap();
+        app.handle_event(key(KeyCode::Char('q'), KeyModifiers::NONE))
+            .unwrap();
+        app.handle_event(key(KeyCode::Char('j'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.query(), "q");
+
+        app.handle_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(app.should_quit);
     }
 
-    /// Get the currently selected result index
-    pub fn selected_idx(&self) -> usize {
-        self.selected_idx
-    }
+    #[test]
+    fn test_run_command() {
+        let mut app = create_test_app();
 
-    /// Get the last execution time
-    pub fn last_exec_time(&self) -> Duration {
-        self.last_exec_time
-    }
+        // Every key-bound action can be run by name
+        app.run_command("tree");
+        assert_eq!(app.mode(), Mode::TreeView);
+        app.set_mode(Mode::Normal);
+        app.run_command("detail");
+        assert!(app.show_detail());
 
-    /// Get the current error message, if any
-    pub fn error_msg(&self) -> Option<&str> {
-        self.error_msg.as_deref()
-    }
+        app.run_command("set max_results 5");
+        assert!(app.error_msg().is_none());
+        assert_eq!(app.status_message(), Some("max_results = 5"));
 
-    /// Get the current app mode
-    pub fn mode(&self) -> Mode {
-        self.mode
+        app.run_command("frobnicate");
+        assert_eq!(app.error_msg(), Some("Unknown command: frobnicate"));
     }
 
-    /// Check if detailed view is enabled
-    pub fn show_detail(&self) -> bool {
-        self.show_detail
-    }
+    #[test]
+    fn test_write_and_export_commands() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-write-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let markdown = dir.join("out.md");
+        let json = dir.join("out.json");
 
-    /// Get the cursor position in the query
-    pub fn cursor_position(&self) -> usize {
-        self.cursor_position
-    }
+        let mut app = App::new("# One\n\n## Two\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
 
-    /// Get the filename, if any
-    pub fn filename(&self) -> Option<&str> {
-        self.filename.as_deref()
-    }
+        app.run_command(&format!("write {}", markdown.display()));
+        assert!(app.error_msg().is_none());
+        assert_eq!(
+            app.status_message(),
+            Some(format!("Wrote 2 results to {}", markdown.display()).as_str())
+        );
+        let written = std::fs::read_to_string(&markdown).unwrap();
+        assert!(written.starts_with("# One"));
+        assert!(written.contains("## Two"));
+
+        // Existing files are only replaced with !
+        app.run_command(&format!("write {}", markdown.display()));
+        assert!(app.error_msg().unwrap().contains("already exists"));
+        app.run_command(&format!("write! {}", markdown.display()));
+        assert!(app.error_msg().is_none());
 
-    /// Get the query history
-    pub fn query_history(&self) -> &[String] {
-        &self.query_history
-    }
+        app.run_command(&format!("export json {}", json.display()));
+        let exported = std::fs::read_to_string(&json).unwrap();
+        assert!(exported.contains("\"type\": \"heading\""));
 
-    pub fn set_query(&mut self, query: String) {
-        self.query = query;
-        self.cursor_position = self.query.len();
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    pub fn set_mode(&mut self, mode: Mode) {
-        self.mode = mode;
-    }
+    #[test]
+    fn test_write_results_prompt() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-prompt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.md");
+        let key = |code| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers: KeyModifiers::NONE,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
 
-    #[cfg(test)]
-    pub fn set_results(&mut self, results: Vec<mq_markdown::Node>) {
-        self.results = results;
-    }
+        let mut app = App::new("# One\n\n## Two\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
 
-    #[cfg(test)]
-    pub fn set_last_exec_time(&mut self, duration: Duration) {
-        self.last_exec_time = duration;
-    }
+        let write_to = |app: &mut App| {
+            app.handle_event(key(KeyCode::Char('w'))).unwrap();
+            assert!(app.save_prompt().is_some());
+            for c in path.display().to_string().chars() {
+                app.handle_event(key(KeyCode::Char(c))).unwrap();
+            }
+            app.handle_event(key(KeyCode::Enter)).unwrap();
+        };
 
-    #[cfg(test)]
-    pub fn set_error_msg(&mut self, msg: String) {
-        self.error_msg = Some(msg);
-    }
+        write_to(&mut app);
+        assert!(app.save_prompt().is_none());
+        assert_eq!(
+            app.status_message(),
+            Some(format!("Wrote 2 results to {}", path.display()).as_str())
+        );
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("# One"));
+
+        // An existing file is only replaced after confirming
+        std::fs::write(&path, "old").unwrap();
+        write_to(&mut app);
+        assert!(app.save_prompt().unwrap().confirm_overwrite);
+        app.handle_event(key(KeyCode::Char('n'))).unwrap();
+        assert!(!app.save_prompt().unwrap().confirm_overwrite);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        app.handle_event(key(KeyCode::Char('y'))).unwrap();
+        assert!(app.save_prompt().is_none());
+        assert!(app.error_msg().is_none());
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("# One"));
 
-    #[cfg(test)]
-    pub fn set_cursor_position(&mut self, position: usize) {
-        self.cursor_position = position;
-    }
+        // Nothing to write without results
+        let mut app = create_test_app();
+        app.handle_event(key(KeyCode::Char('w'))).unwrap();
+        assert!(app.save_prompt().is_none());
+        assert_eq!(app.status_message(), Some("No results to write"));
 
-    /// Get the tree view, if available
-    pub fn tree_view(&self) -> Option<&TreeView> {
-        self.tree_view.as_ref()
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    /// Check if tree sidebar is shown
-    pub fn show_tree_sidebar(&self) -> bool {
-        self.show_tree_sidebar
-    }
+    #[test]
+    fn test_copy_with_osc52() {
+        let mut app = App::new("# One\n\n## Two\n".to_string());
+        app.set_clipboard_mode(ClipboardMode::Osc52);
+        app.set_query(".h".to_string());
+        app.exec_query();
 
-    /// Get the sidebar tree view, if available
-    pub fn sidebar_tree_view(&self) -> Option<&TreeView> {
-        self.sidebar_tree_view.as_ref()
-    }
+        app.perform_action(Action::Copy);
+        assert!(app.error_msg().is_none());
+        assert_eq!(app.status_message(), Some("Copied 2 results (OSC 52)"));
 
-    /// Get mutable reference to sidebar tree view
-    pub fn sidebar_tree_view_mut(&mut self) -> Option<&mut TreeView> {
-        self.sidebar_tree_view.as_mut()
+        let mut output = Vec::new();
+        app.flush_clipboard(&mut output).unwrap();
+        let expected = clipboard::osc52_sequence(
+            &mq_markdown::Markdown::new(app.results().to_vec()).to_string(),
+            std::env::var_os("TMUX").is_some(),
+        )
+        .unwrap();
+        assert_eq!(output, expected.as_bytes());
+
+        // The sequence is written once
+        let mut output = Vec::new();
+        app.flush_clipboard(&mut output).unwrap();
+        assert!(output.is_empty());
     }
 
-    /// Toggle tree sidebar visibility
-    pub fn toggle_tree_sidebar(&mut self) {
-        self.show_tree_sidebar = !self.show_tree_sidebar;
+    #[test]
+    fn test_reload_document_without_file() {
+        let mut app = create_test_app();
+        app.reload_document();
+        assert!(app.error_msg().is_some());
     }
-}
-#[cfg(test)]
-mod tests {
-    use mq_markdown::Node;
 
-    use super::*;
+    #[test]
+    fn test_exec_query_uses_cached_documents() {
+        let mut app = App::with_documents(vec![
+            Document::with_file("# One\n".to_string(), "one.md".to_string()),
+            Document::with_file("# Two\n\n## Three\n".to_string(), "two.md".to_string()),
+        ]);
 
-    fn create_test_app() -> App {
-        App::new("# Test\nSome content".to_string())
-    }
+        // The active document is parsed when the app is created
+        app.exec_query();
+        assert!(app.last_parse_time().is_none());
+        assert_eq!(app.results().len(), 1);
 
-    fn create_test_app_with_file() -> App {
-        App::with_file("# Test\nSome content".to_string(), "test.md".to_string())
+        // Other documents are parsed the first time they are queried
+        app.toggle_all_files_mode();
+        assert!(app.last_parse_time().is_some());
+        assert_eq!(app.results().len(), 3);
+
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert!(app.last_parse_time().is_none());
+        assert_eq!(app.results().len(), 3);
     }
 
     #[test]
@@ -894,6 +2859,83 @@ mod tests {
         assert!(!app.show_detail());
     }
 
+    #[test]
+    fn test_normal_mode_toggle_rendered_view() {
+        let mut app = create_test_app();
+        assert!(!app.rendered_view());
+
+        let rendered_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('m'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(rendered_event.clone()).unwrap();
+        assert!(app.rendered_view());
+
+        app.handle_event(rendered_event).unwrap();
+        assert!(!app.rendered_view());
+    }
+
+    #[test]
+    fn test_inspect_mode() {
+        let mut app = create_test_app();
+        let key = |c| {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers: KeyModifiers::NONE,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        app.set_results(Vec::new());
+        app.perform_action(Action::Inspect);
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.status_message(), Some("No result to inspect"));
+
+        app.set_results(
+            mq_markdown::Markdown::from_markdown_str("## Hello *world*\n\ntext\n")
+                .unwrap()
+                .nodes,
+        );
+        app.handle_event(key('i')).unwrap();
+        assert_eq!(app.mode(), Mode::Inspect);
+        assert!(app.show_detail());
+        assert!(app.inspector().is_some());
+
+        // Moving scrolls the detail view, not the results
+        app.handle_event(key('j')).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+
+        app.handle_event(key('i')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.inspector().is_none());
+    }
+
+    #[test]
+    fn test_inspector_follows_results() {
+        let mut app = App::new("# One\n\n## Two\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.perform_action(Action::Inspect);
+        app.handle_event(Event::Key(KeyEvent::from(KeyCode::Char('j'))))
+            .unwrap();
+        assert_eq!(app.inspector().unwrap().selected(), 1);
+
+        // Re-running the query keeps inspecting the same node
+        app.exec_query();
+        assert_eq!(app.inspector().unwrap().selected(), 1);
+
+        // Another node at the selected index starts over
+        app.set_query(".h | upcase()".to_string());
+        app.exec_query();
+        assert_eq!(app.mode(), Mode::Inspect);
+        let inspector = app.inspector().unwrap();
+        assert_eq!(inspector.node(), &app.results()[0]);
+        assert_eq!(inspector.selected(), 0);
+    }
+
     #[test]
     fn test_normal_mode_enter_query_mode() {
         let mut app = create_test_app();
@@ -1055,6 +3097,309 @@ mod tests {
         assert_eq!(app.cursor_position(), 4);
     }
 
+    #[test]
+    fn test_query_mode_readline_keys() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query(String::new());
+
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+        let ctrl = |c| key(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let alt = |c| key(KeyCode::Char(c), KeyModifiers::ALT);
+
+        for c in ".h | select(.depth == 2)".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+
+        // Word motion and line start/end
+        app.handle_event(alt('b')).unwrap();
+        assert_eq!(app.cursor_position(), 22);
+        app.handle_event(ctrl('a')).unwrap();
+        assert_eq!(app.cursor_position(), 0);
+        app.handle_event(alt('f')).unwrap();
+        assert_eq!(app.cursor_position(), 2);
+        app.handle_event(ctrl('e')).unwrap();
+        assert_eq!(app.cursor_position(), app.query().len());
+
+        // Consecutive kills are joined and can be yanked back
+        app.handle_event(ctrl('w')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == ");
+        app.handle_event(ctrl('w')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth ");
+        app.handle_event(ctrl('y')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+
+        app.handle_event(ctrl('a')).unwrap();
+        app.handle_event(ctrl('k')).unwrap();
+        assert_eq!(app.query(), "");
+        app.handle_event(ctrl('y')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+
+        // Alt+Y cycles through older kills
+        app.handle_event(ctrl('u')).unwrap();
+        app.handle_event(ctrl('y')).unwrap();
+        app.handle_event(alt('y')).unwrap();
+        assert_eq!(app.query(), "== 2)");
+
+        // Undo restores the previous text, redo reapplies it
+        app.handle_event(ctrl('z')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+        app.handle_event(ctrl('z')).unwrap();
+        assert_eq!(app.query(), "");
+        app.handle_event(alt('z')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+
+        // Typing a word is undone at once
+        app.handle_event(ctrl('e')).unwrap();
+        for c in " | to_text".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        app.handle_event(ctrl('z')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2) | ");
+
+        // Unbound Ctrl keys don't insert characters, AltGr (Ctrl+Alt) does
+        app.handle_event(ctrl('g')).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2) | ");
+        app.handle_event(key(
+            KeyCode::Char('@'),
+            KeyModifiers::CONTROL | KeyModifiers::ALT,
+        ))
+        .unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 2) | @");
+    }
+
+    #[test]
+    fn test_query_mode_unicode_editing() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query(String::new());
+
+        let key = |code| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers: KeyModifiers::NONE,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        for c in "\"日本👍🏽\"".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+        assert_eq!(app.cursor_position(), app.query().len());
+
+        // Left skips the whole emoji cluster including its modifier
+        app.handle_event(key(KeyCode::Left)).unwrap();
+        app.handle_event(key(KeyCode::Left)).unwrap();
+        assert_eq!(app.cursor_position(), "\"日本".len());
+        app.handle_event(key(KeyCode::Backspace)).unwrap();
+        assert_eq!(app.query(), "\"日👍🏽\"");
+        // Wide characters take two columns
+        assert_eq!(app.cursor_line_col(), (0, 3));
+        app.handle_event(key(KeyCode::Delete)).unwrap();
+        assert_eq!(app.query(), "\"日\"");
+        app.handle_event(key(KeyCode::Right)).unwrap();
+        assert_eq!(app.cursor_position(), app.query().len());
+    }
+
+    #[test]
+    fn test_query_mode_accept_after_deletion() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query(".h | upcase()".to_string());
+        app.set_cursor_position(app.query().len());
+        app.exec_query();
+
+        // The shortened query is run before the results are accepted
+        for _ in 0.." | upcase()".len() {
+            app.handle_event(Event::Key(KeyEvent::from(KeyCode::Backspace)))
+                .unwrap();
+        }
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('o'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        assert_eq!(app.exit_output().as_deref(), Some("# Test\n"));
+    }
+
+    #[test]
+    fn test_accept_waits_for_background_query() {
+        let mut app = create_test_app();
+        app.start_query_worker();
+        app.set_mode(Mode::Query);
+        app.set_query(".h".to_string());
+        app.set_cursor_position(app.query().len());
+        app.exec_query();
+        wait_for_query(&mut app);
+
+        // Accepting right after an edit runs the edited query to completion
+        for c in " | upcase()".chars() {
+            app.handle_event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+                .unwrap();
+        }
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('o'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        assert!(app.should_quit);
+        assert!(app.query_running_time().is_none());
+        assert_eq!(app.exit_output().as_deref(), Some("# TEST\n"));
+    }
+
+    #[test]
+    fn test_queries_refused_while_abandoned_queries_run() {
+        let mut app = create_test_app();
+        app.start_query_worker();
+        app.exec_query();
+        wait_for_query(&mut app);
+
+        // Threads standing in for abandoned queries that are still running
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let stopped = Arc::new(std::sync::Mutex::new(stopped));
+        for _ in 0..worker::MAX_ABANDONED_WORKERS {
+            let stopped = Arc::clone(&stopped);
+            app.abandoned_workers.push(std::thread::spawn(move || {
+                let _ = stopped.lock().unwrap().recv();
+            }));
+        }
+        app.restart_query_worker();
+        assert!(app.worker.is_none());
+
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert!(app.query_running_time().is_none());
+        assert!(
+            app.error_msg()
+                .is_some_and(|msg| msg.contains("abandoned queries are still running"))
+        );
+
+        // Once they end the query runs on a new worker
+        drop(stop);
+        let start = Instant::now();
+        while app.abandoned_workers.iter().any(|t| !t.is_finished()) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        app.exec_query();
+        assert!(app.worker.is_some());
+        wait_for_query(&mut app);
+        assert!(app.error_msg().is_none());
+        assert_eq!(app.results().len(), 2);
+    }
+
+    #[test]
+    fn test_esc_closes_popup_while_query_runs() {
+        let mut app = create_test_app();
+        // A query that is still running
+        app.running_query = Some((app.next_query_id, Instant::now()));
+
+        app.set_mode(Mode::Help);
+        app.handle_event(Event::Key(KeyEvent::from(KeyCode::Esc)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.query_running_time().is_some());
+
+        app.set_mode(Mode::Query);
+        app.history_search = Some(HistorySearch::default());
+        app.handle_event(Event::Key(KeyEvent::from(KeyCode::Esc)))
+            .unwrap();
+        assert!(app.history_search.is_none());
+        assert_eq!(app.mode(), Mode::Query);
+        assert!(app.query_running_time().is_some());
+
+        // Without a popup Esc cancels the query
+        app.handle_event(Event::Key(KeyEvent::from(KeyCode::Esc)))
+            .unwrap();
+        assert!(app.query_running_time().is_none());
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.status_message(), Some("Query cancelled"));
+    }
+
+    #[test]
+    fn test_quit_waits_for_query_printed_on_exit() {
+        let mut app = create_test_app();
+        app.start_query_worker();
+        app.set_print_on_exit(Some(PrintTarget::Results));
+        app.set_query(".h | upcase()".to_string());
+        app.exec_query();
+
+        app.perform_action(Action::Quit);
+        assert!(app.query_running_time().is_none());
+        assert_eq!(app.exit_output().as_deref(), Some("# TEST\n"));
+    }
+
+    #[test]
+    fn test_query_mode_multiline_editing() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query(".h".to_string());
+
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        // Shift+Enter inserts a newline and switches to multi-line mode
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::SHIFT))
+            .unwrap();
+        assert!(app.multiline());
+        for c in "| sel".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        assert_eq!(app.query(), ".h\n| sel");
+        assert_eq!(app.cursor_line_col(), (1, 5));
+
+        // Enter inserts a newline in multi-line mode
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.cursor_line_col(), (2, 0));
+
+        // Up/Down move between lines, keeping the column where possible
+        app.handle_event(key(KeyCode::Up, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.cursor_line_col(), (1, 0));
+        app.handle_event(key(KeyCode::End, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.cursor_line_col(), (1, 5));
+        app.handle_event(key(KeyCode::Up, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.cursor_line_col(), (0, 2));
+        app.handle_event(key(KeyCode::Down, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.cursor_line_col(), (1, 2));
+
+        // Alt+Down/Up grow and shrink the editor
+        let height = app.editor_height();
+        app.handle_event(key(KeyCode::Down, KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.editor_height(), height + 1);
+        app.handle_event(key(KeyCode::Up, KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.editor_height(), height);
+
+        // Ctrl+S executes the query
+        app.handle_event(key(KeyCode::Char('s'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
     #[test]
     fn test_query_mode_exit_on_escape() {
         let mut app = create_test_app();
@@ -1087,6 +3432,105 @@ mod tests {
         assert!(app.query_history().contains(&"test query".to_string()));
     }
 
+    #[test]
+    fn test_query_mode_history_search() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.query_history.push(".h | select(.depth == 2)");
+        app.query_history.push(".code");
+        app.query_history.push(".link");
+
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        app.handle_event(key(KeyCode::Char('r'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(app.history_search().is_some());
+        assert_eq!(app.history_search_matches().len(), 3);
+
+        for c in "dep".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        assert_eq!(
+            app.history_search_matches(),
+            vec![".h | select(.depth == 2)"]
+        );
+        // Typing into the popup leaves the query untouched
+        assert_eq!(app.query(), "");
+
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.history_search().is_none());
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+        assert_eq!(app.cursor_position(), app.query().len());
+
+        // Esc closes the popup without changing the query
+        app.handle_event(key(KeyCode::Char('r'), KeyModifiers::CONTROL))
+            .unwrap();
+        app.handle_event(key(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.history_search().is_none());
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".h | select(.depth == 2)");
+    }
+
+    #[test]
+    fn test_query_mode_completion() {
+        let mut app = App::new("# Title\n\n```rust\nfn main() {}\n```\n".to_string());
+        app.set_mode(Mode::Query);
+
+        let key = |code, modifiers| {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: crossterm::event::KeyEventKind::Press,
+                state: crossterm::event::KeyEventState::NONE,
+            })
+        };
+
+        // A single candidate is inserted directly
+        app.set_query(".co".to_string());
+        app.handle_event(key(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.completion().is_none());
+        assert_eq!(app.query(), ".code");
+
+        // Several candidates open the popup
+        app.set_query(".h".to_string());
+        app.handle_event(key(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.completion().unwrap().candidates.len(), 2);
+
+        // Typing refines the candidates
+        app.handle_event(key(KeyCode::Char('1'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.query(), ".h1");
+        assert_eq!(app.completion().unwrap().candidates.len(), 1);
+
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.completion().is_none());
+        assert_eq!(app.query(), ".h1");
+        assert_eq!(app.mode(), Mode::Query);
+
+        // Esc closes the popup but stays in query mode
+        app.set_query(".h".to_string());
+        app.handle_event(key(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        app.handle_event(key(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.completion().is_none());
+        assert_eq!(app.mode(), Mode::Query);
+    }
+
     #[test]
     fn test_help_mode_exit_on_any_key() {
         let mut app = create_test_app();
@@ -1127,6 +3571,43 @@ mod tests {
         assert!(app.should_quit);
     }
 
+    #[test]
+    fn test_exit_output() {
+        let mut app = create_test_app();
+        app.set_results(vec![Node::from("result1")]);
+        app.set_query(".text".to_string());
+
+        // Nothing is printed when quitting without accepting
+        assert!(app.exit_output().is_none());
+
+        app.set_print_on_exit(Some(PrintTarget::Query));
+        assert_eq!(app.exit_output(), Some(".text".to_string()));
+
+        app.set_print_on_exit(Some(PrintTarget::Results));
+        assert!(
+            app.exit_output()
+                .is_some_and(|output| output.contains("result1"))
+        );
+    }
+
+    #[test]
+    fn test_accept_and_quit() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+
+        let accept_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('o'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(accept_event).unwrap();
+
+        assert!(app.should_quit);
+        assert!(app.accepted);
+        assert!(app.exit_output().is_some());
+    }
+
     #[test]
     fn test_error_message_cleared_on_event() {
         let mut app = create_test_app();
@@ -1198,6 +3679,58 @@ mod tests {
         assert!(app.query_history().is_empty());
     }
 
+    #[test]
+    fn test_document_switching() {
+        let mut app = App::with_documents(vec![
+            Document::with_file("# First".to_string(), "first.md".to_string()),
+            Document::with_file("# Second".to_string(), "second.md".to_string()),
+        ]);
+        assert_eq!(app.filename(), Some("first.md"));
+
+        let tab_event = Event::Key(KeyEvent {
+            code: KeyCode::Tab,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(tab_event.clone()).unwrap();
+        assert_eq!(app.active_document(), 1);
+        assert_eq!(app.filename(), Some("second.md"));
+        assert_eq!(app.content(), "# Second");
+
+        // Wraps around to the first document
+        app.handle_event(tab_event).unwrap();
+        assert_eq!(app.active_document(), 0);
+
+        let back_tab_event = Event::Key(KeyEvent {
+            code: KeyCode::BackTab,
+            modifiers: KeyModifiers::SHIFT,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(back_tab_event).unwrap();
+        assert_eq!(app.active_document(), 1);
+    }
+
+    #[test]
+    fn test_all_files_mode_tags_results() {
+        let mut app = App::with_documents(vec![
+            Document::with_file("# First\n\n## Sub".to_string(), "first.md".to_string()),
+            Document::with_file("# Second".to_string(), "second.md".to_string()),
+        ]);
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+        assert_eq!(app.result_source(0), None);
+
+        app.toggle_all_files_mode();
+        assert!(app.all_files_mode());
+        assert_eq!(app.results().len(), 3);
+        assert_eq!(app.result_source(0), Some("first.md"));
+        assert_eq!(app.result_source(1), Some("first.md"));
+        assert_eq!(app.result_source(2), Some("second.md"));
+    }
+
     #[test]
     fn test_tree_view_mode() {
         let mut app = create_test_app();
@@ -0,0 +1,139 @@
+use arboard::Clipboard;
+use serde::Deserialize;
+
+/// Largest text copied with OSC 52. Base64 turns it into about 100 kB, the
+/// most many terminals (and tmux) accept in a single sequence.
+pub const OSC52_MAX_BYTES: usize = 74_994;
+
+/// How results are copied to the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardMode {
+    /// The system clipboard, falling back to OSC 52 when it isn't available
+    #[default]
+    Auto,
+    /// Only the system clipboard
+    System,
+    /// Only OSC 52, which sets the clipboard of the local terminal even over SSH
+    Osc52,
+}
+
+/// How the text was copied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Copied {
+    System,
+    /// The escape sequence to write to the terminal
+    Osc52(String),
+}
+
+/// Copy `text` to the clipboard. With OSC 52 the returned sequence still has
+/// to be written to the terminal. `tmux` wraps it for tmux passthrough.
+pub fn copy(text: &str, mode: ClipboardMode, tmux: bool) -> Result<Copied, String> {
+    match mode {
+        ClipboardMode::System => copy_to_system(text).map(|_| Copied::System),
+        ClipboardMode::Osc52 => osc52_sequence(text, tmux).map(Copied::Osc52),
+        ClipboardMode::Auto => match copy_to_system(text) {
+            Ok(()) => Ok(Copied::System),
+            Err(_) => osc52_sequence(text, tmux).map(Copied::Osc52),
+        },
+    }
+}
+
+fn copy_to_system(text: &str) -> Result<(), String> {
+    let mut clipboard =
+        Clipboard::new().map_err(|_| "Error: Could not access clipboard".to_string())?;
+    clipboard
+        .set_text(text)
+        .map_err(|_| "Error: Could not copy to clipboard".to_string())
+}
+
+/// The OSC 52 escape sequence setting the clipboard to `text`, wrapped in a
+/// DCS passthrough sequence when running inside tmux
+pub fn osc52_sequence(text: &str, tmux: bool) -> Result<String, String> {
+    if text.len() > OSC52_MAX_BYTES {
+        return Err(format!(
+            "Error: {} bytes is too much to copy with OSC 52 (limit {})",
+            text.len(),
+            OSC52_MAX_BYTES
+        ));
+    }
+
+    let sequence = format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    if tmux {
+        // tmux passes the sequence on when its escapes are doubled
+        Ok(format!(
+            "\x1bPtmux;{}\x1b\\",
+            sequence.replace('\x1b', "\x1b\x1b")
+        ))
+    } else {
+        Ok(sequence)
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(char::from(BASE64_ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode("# Ü".as_bytes()), "IyDDnA==");
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(
+            osc52_sequence("# Title", false).unwrap(),
+            "\x1b]52;c;IyBUaXRsZQ==\x07"
+        );
+        assert_eq!(
+            osc52_sequence("# Title", true).unwrap(),
+            "\x1bPtmux;\x1b\x1b]52;c;IyBUaXRsZQ==\x07\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_osc52_size_limit() {
+        assert!(osc52_sequence(&"a".repeat(OSC52_MAX_BYTES), false).is_ok());
+        assert!(osc52_sequence(&"a".repeat(OSC52_MAX_BYTES + 1), false).is_err());
+    }
+
+    #[test]
+    fn test_copy_with_osc52_mode() {
+        assert_eq!(
+            copy("foo", ClipboardMode::Osc52, false),
+            Ok(Copied::Osc52("\x1b]52;c;Zm9v\x07".to_string()))
+        );
+    }
+}
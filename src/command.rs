@@ -0,0 +1,418 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{action::Action, export::ExportFormat};
+
+/// Commands that take arguments, with their usage. Every [`Action`] can also
+/// be run by name.
+const COMMANDS: &[(&str, &str)] = &[
+    ("write", "write[!] [FILE]"),
+    ("export", "export[!] <FORMAT> <FILE>"),
+    ("open", "open [FILE]"),
+    ("set", "set <OPTION> <VALUE>"),
+];
+
+/// Options that can be changed with `set`
+const OPTIONS: &[&str] = &["timeout", "max_results", "theme"];
+
+/// A parsed command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Action(Action),
+    /// Write the results as Markdown (`!` overwrites an existing file)
+    Write {
+        path: PathBuf,
+        overwrite: bool,
+    },
+    Export {
+        format: ExportFormat,
+        path: PathBuf,
+        overwrite: bool,
+    },
+    /// Replace the active document with a file
+    Open(PathBuf),
+    Set(Setting),
+}
+
+/// A value changed with `set`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Timeout(Duration),
+    MaxResults(usize),
+    /// Name of a built-in or user-defined theme, resolved by the app
+    Theme(String),
+}
+
+/// Parse a command line such as `write out.md` or `set timeout 5`
+pub fn parse(input: &str) -> Result<Command, String> {
+    let input = input.trim();
+    let (name, args) = input.split_once(' ').unwrap_or((input, ""));
+    let args = args.trim();
+    let (name, overwrite) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    match name {
+        "" => Err("No command given".to_string()),
+        // Without a file, ask for one
+        "write" | "w" if args.is_empty() => Ok(Command::Action(Action::Write)),
+        "write" | "w" => Ok(Command::Write {
+            path: PathBuf::from(args),
+            overwrite,
+        }),
+        "export" => {
+            let (format, path) = args.split_once(' ').ok_or_else(|| usage("export"))?;
+            let format = ExportFormat::from_name(format)
+                .ok_or_else(|| format!("Unknown export format: {}", format))?;
+            Ok(Command::Export {
+                format,
+                path: PathBuf::from(path.trim()),
+                overwrite,
+            })
+        }
+        "open" | "e" if !args.is_empty() => Ok(Command::Open(PathBuf::from(args))),
+        "set" => {
+            let (option, value) = args.split_once(' ').ok_or_else(|| usage("set"))?;
+            parse_setting(option, value.trim()).map(Command::Set)
+        }
+        "q" => Ok(Command::Action(Action::Quit)),
+        _ => match Action::from_name(name) {
+            Some(action) if args.is_empty() => Ok(Command::Action(action)),
+            Some(action) => Err(format!("{} takes no arguments", action.name())),
+            None => Err(format!("Unknown command: {}", name)),
+        },
+    }
+}
+
+fn parse_setting(option: &str, value: &str) -> Result<Setting, String> {
+    match option {
+        "timeout" => value
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .filter(|timeout| !timeout.is_zero())
+            .map(Setting::Timeout)
+            .ok_or_else(|| format!("Invalid timeout: {}", value)),
+        "max_results" => value
+            .parse()
+            .map(Setting::MaxResults)
+            .map_err(|_| format!("Invalid max_results: {}", value)),
+        "theme" => Ok(Setting::Theme(value.to_string())),
+        _ => Err(format!("Unknown option: {}", option)),
+    }
+}
+
+fn usage(name: &str) -> String {
+    let usage = COMMANDS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map_or(name, |(_, usage)| usage);
+    format!("Usage: {}", usage)
+}
+
+/// Completions of the command line, each replacing the whole input.
+/// `themes` are the names offered for `set theme`.
+pub fn complete(input: &str, themes: &[String]) -> Vec<String> {
+    let Some((name, args)) = input.split_once(' ') else {
+        let mut names = COMMANDS
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(Action::ALL.iter().map(|action| action.name()))
+            .filter(|name| name.starts_with(input))
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        return names;
+    };
+
+    let prefix = &input[..input.len() - args.len()];
+    let candidates = match name.trim_end_matches('!') {
+        "set" => match args.split_once(' ') {
+            Some(("theme", partial)) => themes
+                .iter()
+                .filter(|theme| theme.starts_with(partial))
+                .map(|theme| format!("theme {}", theme))
+                .collect(),
+            Some(_) => Vec::new(),
+            None => complete_word(args, OPTIONS),
+        },
+        "export" => match args.split_once(' ') {
+            Some((format, path)) => complete_path(path)
+                .into_iter()
+                .map(|path| format!("{} {}", format, path))
+                .collect(),
+            None => complete_word(
+                args,
+                &ExportFormat::ALL
+                    .iter()
+                    .map(|format| format.name())
+                    .collect::<Vec<_>>(),
+            ),
+        },
+        "write" | "w" | "open" | "e" => complete_path(args),
+        _ => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{}{}", prefix, candidate))
+        .collect()
+}
+
+/// Short description of a command or action name, shown next to completions
+pub fn describe(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, usage)| *usage)
+        .or_else(|| Action::from_name(name).map(Action::description))
+}
+
+/// Words starting with `partial`, followed by a space for the next argument
+fn complete_word(partial: &str, words: &[&str]) -> Vec<String> {
+    words
+        .iter()
+        .filter(|word| word.starts_with(partial))
+        .map(|word| format!("{} ", word))
+        .collect()
+}
+
+/// Paths starting with `partial`. Directories end with a slash so
+/// completion can continue inside them.
+pub fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, file_prefix) = match partial.rfind('/') {
+        Some(idx) => (&partial[..=idx], &partial[idx + 1..]),
+        None => ("", partial),
+    };
+
+    let dir_path = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(Path::new(dir_path)) else {
+        return Vec::new();
+    };
+
+    let mut paths = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !name.starts_with(file_prefix)
+                || (name.starts_with('.') && !file_prefix.starts_with('.'))
+            {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, suffix))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// State of the command line (command mode only)
+#[derive(Debug, Clone, Default)]
+pub struct CommandLine {
+    /// Text typed after the `:` prompt
+    pub input: String,
+    /// Completions of the input, cycled with Tab
+    pub candidates: Vec<String>,
+    /// Candidate currently inserted into the input
+    pub selected: Option<usize>,
+}
+
+impl CommandLine {
+    /// Complete the input, cycling through the candidates on repeated calls
+    pub fn complete(&mut self, forward: bool, themes: &[String]) {
+        if self.selected.is_none() {
+            self.candidates = complete(&self.input, themes);
+
+            // Insert a single candidate directly
+            if let [candidate] = self.candidates.as_slice() {
+                self.input = candidate.clone();
+                self.candidates.clear();
+                return;
+            }
+        }
+
+        let len = self.candidates.len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = match self.selected {
+            None if forward => 0,
+            None => len - 1,
+            Some(idx) if forward => (idx + 1) % len,
+            Some(idx) => (idx + len - 1) % len,
+        };
+        self.selected = Some(selected);
+        self.input = self.candidates[selected].clone();
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.reset_completion();
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.reset_completion();
+    }
+
+    fn reset_completion(&mut self) {
+        self.candidates.clear();
+        self.selected = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse("write out.md"),
+            Ok(Command::Write {
+                path: PathBuf::from("out.md"),
+                overwrite: false
+            })
+        );
+        assert_eq!(
+            parse("w! out.md"),
+            Ok(Command::Write {
+                path: PathBuf::from("out.md"),
+                overwrite: true
+            })
+        );
+        assert_eq!(
+            parse("export json results.json"),
+            Ok(Command::Export {
+                format: ExportFormat::Json,
+                path: PathBuf::from("results.json"),
+                overwrite: false
+            })
+        );
+        assert_eq!(
+            parse("e docs/my notes.md"),
+            Ok(Command::Open(PathBuf::from("docs/my notes.md")))
+        );
+        assert_eq!(parse("open"), Ok(Command::Action(Action::Open)));
+        assert_eq!(parse("w"), Ok(Command::Action(Action::Write)));
+        assert_eq!(
+            parse("set timeout 1.5"),
+            Ok(Command::Set(Setting::Timeout(Duration::from_millis(1500))))
+        );
+        assert_eq!(
+            parse(" set max_results 10 "),
+            Ok(Command::Set(Setting::MaxResults(10)))
+        );
+        assert_eq!(
+            parse("set theme light"),
+            Ok(Command::Set(Setting::Theme("light".to_string())))
+        );
+        assert_eq!(parse("sidebar"), Ok(Command::Action(Action::ToggleSidebar)));
+        assert_eq!(parse("tree"), Ok(Command::Action(Action::TreeView)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("export json"),
+            Err("Usage: export[!] <FORMAT> <FILE>".to_string())
+        );
+        assert_eq!(
+            parse("export yaml out.yaml"),
+            Err("Unknown export format: yaml".to_string())
+        );
+        assert_eq!(
+            parse("set timeout 0"),
+            Err("Invalid timeout: 0".to_string())
+        );
+        assert_eq!(
+            parse("set colour red"),
+            Err("Unknown option: colour".to_string())
+        );
+        assert_eq!(
+            parse("frobnicate"),
+            Err("Unknown command: frobnicate".to_string())
+        );
+        assert_eq!(
+            parse("quit now"),
+            Err("quit takes no arguments".to_string())
+        );
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_complete_command_names() {
+        assert_eq!(complete("wr", &[]), vec!["write"]);
+        assert_eq!(complete("s", &[]), vec!["set", "sidebar"]);
+        assert!(complete("", &[]).len() > Action::ALL.len());
+        assert_eq!(complete("set t", &[]), vec!["set timeout ", "set theme "]);
+        let themes = ["dark".to_string(), "light".to_string(), "mine".to_string()];
+        assert_eq!(complete("set theme ", &themes).len(), 3);
+        assert_eq!(complete("set theme l", &themes), vec!["set theme light"]);
+        assert!(complete("set timeout ", &themes).is_empty());
+        assert_eq!(complete("export j", &[]), vec!["export json "]);
+        assert!(complete("quit ", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe("write"), Some("write[!] [FILE]"));
+        assert_eq!(describe("sidebar"), Some("Toggle header sidebar"));
+        assert!(describe("write out.md").is_none());
+    }
+
+    #[test]
+    fn test_complete_paths() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        let dir_name = dir.display().to_string();
+
+        assert_eq!(
+            complete(&format!("open {}/n", dir_name), &[]),
+            vec![format!("open {}/notes.md", dir_name)]
+        );
+        assert_eq!(
+            complete(&format!("export json {}/d", dir_name), &[]),
+            vec![format!("export json {}/docs/", dir_name)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_command_line_cycles_candidates() {
+        let mut command_line = CommandLine::default();
+        command_line.push('s');
+        command_line.complete(true, &[]);
+        assert_eq!(command_line.input, "set");
+        command_line.complete(true, &[]);
+        assert_eq!(command_line.input, "sidebar");
+        command_line.complete(true, &[]);
+        assert_eq!(command_line.input, "set");
+        command_line.complete(false, &[]);
+        assert_eq!(command_line.input, "sidebar");
+
+        // Typing starts a new completion
+        command_line.pop();
+        command_line.pop();
+        command_line.push('t');
+        assert!(command_line.candidates.is_empty());
+
+        // A single candidate is inserted directly
+        let mut command_line = CommandLine {
+            input: "wri".to_string(),
+            ..CommandLine::default()
+        };
+        command_line.complete(true, &[]);
+        assert_eq!(command_line.input, "write");
+        assert!(command_line.selected.is_none());
+    }
+}
@@ -0,0 +1,541 @@
+use mq_markdown::Node;
+use std::{collections::BTreeSet, sync::LazyLock};
+
+use crate::node::node_children;
+
+/// Node selectors offered for completion, with a short description
+const SELECTORS: &[(&str, &str)] = &[
+    (".h", "Headings of any depth"),
+    (".h1", "Level 1 headings"),
+    (".h2", "Level 2 headings"),
+    (".h3", "Level 3 headings"),
+    (".h4", "Level 4 headings"),
+    (".h5", "Level 5 headings"),
+    (".h6", "Level 6 headings"),
+    (".blockquote", "Blockquotes"),
+    (".break", "Line breaks"),
+    (".code", "Code blocks"),
+    (".code_inline", "Inline code"),
+    (".definition", "Link definitions"),
+    (".delete", "Strikethrough text"),
+    (".emphasis", "Emphasized text"),
+    (".footnote", "Footnotes"),
+    (".footnote_ref", "Footnote references"),
+    (".hr", "Horizontal rules"),
+    (".html", "HTML blocks"),
+    (".image", "Images"),
+    (".image_ref", "Image references"),
+    (".link", "Links"),
+    (".link_ref", "Link references"),
+    (".list", "List items"),
+    (".math", "Math blocks"),
+    (".math_inline", "Inline math"),
+    (".strong", "Strong text"),
+    (".text", "Text"),
+    (".toml", "TOML front matter"),
+    (".yaml", "YAML front matter"),
+];
+
+/// Standard modules that can be loaded with `import "name"`. mq-lang keeps
+/// its list private, so only the names are kept here; descriptions and
+/// functions are read from the module sources.
+const STANDARD_MODULES: &[&str] = &[
+    "csv", "fuzzy", "json", "section", "test", "toml", "xml", "yaml",
+];
+
+/// Functions defined in mq source by the builtin module
+static BUILTIN_DEFINITIONS: LazyLock<Vec<Definition>> =
+    LazyLock::new(|| definitions(mq_lang::BUILTIN_MODULE_FILE));
+
+/// A function defined with `def` in mq source
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Definition {
+    name: String,
+    params: String,
+    /// The `#` comment above the definition
+    description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateKind {
+    Selector,
+    Function,
+    Module,
+}
+
+/// A single completion candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Text inserted into the query
+    pub text: String,
+    /// Signature shown in the popup, e.g. `select(condition)`
+    pub signature: String,
+    /// Short description shown in the popup
+    pub description: String,
+    pub kind: CandidateKind,
+}
+
+impl Candidate {
+    fn function(name: &str, params: &str, description: &str) -> Self {
+        Self {
+            text: name.to_string(),
+            signature: format!("{}({})", name, params),
+            description: description.to_string(),
+            kind: CandidateKind::Function,
+        }
+    }
+}
+
+/// State of the completion popup in query mode
+#[derive(Debug, Clone)]
+pub struct Completion {
+    /// Matching candidates, sorted by text
+    pub candidates: Vec<Candidate>,
+    /// Index of the selected candidate
+    pub selected: usize,
+    /// Byte offset in the query where the completed word starts
+    pub start: usize,
+}
+
+impl Completion {
+    pub fn selected_candidate(&self) -> Option<&Candidate> {
+        self.candidates.get(self.selected)
+    }
+
+    pub fn move_down(&mut self) {
+        if !self.candidates.is_empty() {
+            self.selected = (self.selected + 1) % self.candidates.len();
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if !self.candidates.is_empty() {
+            self.selected = if self.selected > 0 {
+                self.selected - 1
+            } else {
+                self.candidates.len() - 1
+            };
+        }
+    }
+
+    /// Replace the word being completed with the selected candidate.
+    ///
+    /// Returns the new query and cursor position. Functions are inserted with
+    /// parentheses and the cursor is placed inside them when they take arguments.
+    pub fn apply(&self, query: &str, cursor: usize) -> Option<(String, usize)> {
+        let candidate = self.selected_candidate()?;
+
+        let (insert, cursor_offset) = match candidate.kind {
+            CandidateKind::Function if candidate.signature.ends_with("()") => {
+                let insert = format!("{}()", candidate.text);
+                let offset = insert.len();
+                (insert, offset)
+            }
+            CandidateKind::Function => (format!("{}()", candidate.text), candidate.text.len() + 1),
+            CandidateKind::Selector | CandidateKind::Module => {
+                (candidate.text.clone(), candidate.text.len())
+            }
+        };
+
+        let new_query = format!("{}{}{}", &query[..self.start], insert, &query[cursor..]);
+        Some((new_query, self.start + cursor_offset))
+    }
+}
+
+/// Compute completion candidates for the word before `cursor` in `query`.
+///
+/// Selectors are limited to the node types occurring in `nodes`, and module
+/// functions to the modules imported by the query.
+pub fn complete(query: &str, cursor: usize, nodes: &[Node]) -> Option<Completion> {
+    let before = query.get(..cursor)?;
+
+    // Module names inside `import "...`
+    if let Some(start) = import_name_start(before) {
+        let prefix = &before[start..];
+        let candidates = STANDARD_MODULES
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .filter_map(|name| {
+                let source = module_source(name)?;
+                Some(Candidate {
+                    text: name.to_string(),
+                    signature: format!("import \"{}\"", name),
+                    description: module_description(&source),
+                    kind: CandidateKind::Module,
+                })
+            })
+            .collect();
+        return new_completion(candidates, start);
+    }
+
+    let start = before
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !is_word_char(*c))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    let word = &before[start..];
+
+    let candidates = if word.starts_with('.') {
+        document_selectors(nodes)
+            .into_iter()
+            .filter(|(selector, _)| selector.starts_with(word))
+            .map(|(selector, description)| Candidate {
+                text: selector.to_string(),
+                signature: selector.to_string(),
+                description: description.to_string(),
+                kind: CandidateKind::Selector,
+            })
+            .collect()
+    } else {
+        let imported = imported_modules(query);
+        let mut candidates = builtin_functions();
+        candidates.extend(user_functions(query));
+        for module in imported {
+            let Some(source) = module_source(module) else {
+                continue;
+            };
+            candidates.extend(definitions(&source).into_iter().map(|definition| {
+                Candidate::function(
+                    &format!("{}::{}", module, definition.name),
+                    &definition.params,
+                    &definition.description,
+                )
+            }));
+        }
+        candidates.retain(|candidate| candidate.text.starts_with(word));
+        candidates.sort_by(|a, b| a.text.cmp(&b.text));
+        candidates.dedup_by(|a, b| a.text == b.text);
+        candidates
+    };
+
+    new_completion(candidates, start)
+}
+
+fn new_completion(candidates: Vec<Candidate>, start: usize) -> Option<Completion> {
+    if candidates.is_empty() {
+        None
+    } else {
+        Some(Completion {
+            candidates,
+            selected: 0,
+            start,
+        })
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == ':' || c == '.'
+}
+
+/// If the cursor is inside the module name of `import "`, return where the name starts
+fn import_name_start(before: &str) -> Option<usize> {
+    let idx = before.rfind("import \"")?;
+    let start = idx + "import \"".len();
+    (!before[start..].contains('"')).then_some(start)
+}
+
+/// Modules imported with `import "name"` in the query
+fn imported_modules(query: &str) -> Vec<&str> {
+    query
+        .match_indices("import \"")
+        .filter_map(|(idx, pattern)| {
+            let rest = &query[idx + pattern.len()..];
+            rest.find('"').map(|end| &rest[..end])
+        })
+        .collect()
+}
+
+/// Builtin functions provided by mq-lang, both native ones and those
+/// defined in its builtin module
+fn builtin_functions() -> Vec<Candidate> {
+    let native = mq_lang::BUILTIN_FUNCTION_DOC
+        .iter()
+        .map(|(name, doc)| Candidate::function(name, &doc.params.join(", "), doc.description));
+    let defined = BUILTIN_DEFINITIONS.iter().map(|definition| {
+        Candidate::function(
+            &definition.name,
+            &definition.params,
+            &definition.description,
+        )
+    });
+
+    native
+        .chain(defined)
+        .filter(|candidate| !candidate.text.starts_with('_'))
+        .collect()
+}
+
+/// Source of a module as mq-lang resolves it for `import`
+fn module_source(name: &str) -> Option<String> {
+    mq_lang::DefaultModuleLoader::default().resolve(name).ok()
+}
+
+/// The comment at the top of a module's source
+fn module_description(source: &str) -> String {
+    source
+        .lines()
+        .map_while(|line| line.trim().strip_prefix('#'))
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Public functions defined at the top level of mq source, described by the
+/// comment lines right above them
+fn definitions(source: &str) -> Vec<Definition> {
+    let mut result = Vec::new();
+    let mut comment: Vec<&str> = Vec::new();
+
+    for line in source.lines() {
+        if let Some(text) = line.strip_prefix('#') {
+            comment.push(text.trim());
+            continue;
+        }
+
+        if let Some((name, params)) = line.strip_prefix("def ").and_then(parse_def)
+            && !name.starts_with('_')
+        {
+            result.push(Definition {
+                name: name.to_string(),
+                params: params.to_string(),
+                description: comment.join(" "),
+            });
+        }
+        comment.clear();
+    }
+
+    result
+}
+
+/// Name and parameters of a definition, given the text after `def `
+fn parse_def(rest: &str) -> Option<(&str, &str)> {
+    let name_end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let name = &rest[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+
+    let params = rest[name_end..]
+        .strip_prefix('(')
+        .and_then(|params| params.split_once(')'))
+        .map(|(params, _)| params.trim())
+        .unwrap_or_default();
+    Some((name, params))
+}
+
+/// Functions defined with `def name(params):` in the query itself
+fn user_functions(query: &str) -> Vec<Candidate> {
+    query
+        .match_indices("def ")
+        .filter_map(|(idx, pattern)| parse_def(&query[idx + pattern.len()..]))
+        .map(|(name, params)| Candidate::function(name, params, "Defined in this query"))
+        .collect()
+}
+
+/// Selectors matching at least one node in the document
+fn document_selectors(nodes: &[Node]) -> Vec<(&'static str, &'static str)> {
+    let mut found = BTreeSet::new();
+    collect_selectors(nodes, &mut found);
+
+    SELECTORS
+        .iter()
+        .filter(|(selector, _)| found.contains(selector))
+        .copied()
+        .collect()
+}
+
+fn collect_selectors(nodes: &[Node], found: &mut BTreeSet<&'static str>) {
+    for node in nodes {
+        found.extend(node_selectors(node));
+        collect_selectors(node_children(node), found);
+    }
+}
+
+fn node_selectors(node: &Node) -> Vec<&'static str> {
+    match node {
+        Node::Heading(h) => {
+            let depth = match h.depth {
+                1 => ".h1",
+                2 => ".h2",
+                3 => ".h3",
+                4 => ".h4",
+                5 => ".h5",
+                _ => ".h6",
+            };
+            vec![".h", depth]
+        }
+        Node::Blockquote(_) => vec![".blockquote"],
+        Node::Break(_) => vec![".break"],
+        Node::Code(_) => vec![".code"],
+        Node::CodeInline(_) => vec![".code_inline"],
+        Node::Definition(_) => vec![".definition"],
+        Node::Delete(_) => vec![".delete"],
+        Node::Emphasis(_) => vec![".emphasis"],
+        Node::Footnote(_) => vec![".footnote"],
+        Node::FootnoteRef(_) => vec![".footnote_ref"],
+        Node::HorizontalRule(_) => vec![".hr"],
+        Node::Html(_) => vec![".html"],
+        Node::Image(_) => vec![".image"],
+        Node::ImageRef(_) => vec![".image_ref"],
+        Node::Link(_) => vec![".link"],
+        Node::LinkRef(_) => vec![".link_ref"],
+        Node::List(_) => vec![".list"],
+        Node::Math(_) => vec![".math"],
+        Node::MathInline(_) => vec![".math_inline"],
+        Node::Strong(_) => vec![".strong"],
+        Node::Text(_) => vec![".text"],
+        Node::Toml(_) => vec![".toml"],
+        Node::Yaml(_) => vec![".yaml"],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::Markdown;
+
+    fn nodes(markdown: &str) -> Vec<Node> {
+        Markdown::from_markdown_str(markdown).unwrap().nodes
+    }
+
+    #[test]
+    fn test_complete_selectors_from_document() {
+        let nodes = nodes("# Title\n\n```rust\nfn main() {}\n```\n");
+        let completion = complete(".", 1, &nodes).unwrap();
+        let texts = completion
+            .candidates
+            .iter()
+            .map(|c| c.text.as_str())
+            .collect::<Vec<_>>();
+
+        assert!(texts.contains(&".h"));
+        assert!(texts.contains(&".h1"));
+        assert!(texts.contains(&".code"));
+        assert!(!texts.contains(&".h2"));
+        assert!(!texts.contains(&".link"));
+    }
+
+    #[test]
+    fn test_complete_selector_prefix() {
+        let nodes = nodes("# Title\n\n```rust\nfn main() {}\n```\n");
+        let completion = complete(".h | .c", 7, &nodes).unwrap();
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.candidates.len(), 1);
+        assert_eq!(completion.candidates[0].text, ".code");
+    }
+
+    #[test]
+    fn test_complete_builtin_functions() {
+        let completion = complete(".h | sel", 8, &[]).unwrap();
+        assert!(
+            completion
+                .candidates
+                .iter()
+                .any(|c| c.text == "select" && c.kind == CandidateKind::Function)
+        );
+    }
+
+    #[test]
+    fn test_complete_module_names_and_functions() {
+        let query = "import \"se";
+        let completion = complete(query, query.len(), &[]).unwrap();
+        assert_eq!(completion.candidates[0].text, "section");
+
+        let query = "import \"section\" | section::sp";
+        let completion = complete(query, query.len(), &[]).unwrap();
+        assert_eq!(completion.candidates.len(), 1);
+        assert_eq!(completion.candidates[0].text, "section::split");
+        assert_eq!(
+            completion.candidates[0].signature,
+            "section::split(md_nodes, level)"
+        );
+        assert!(
+            completion.candidates[0]
+                .description
+                .starts_with("Returns an array of sections")
+        );
+
+        // Module functions are only offered once the module is imported
+        assert!(complete("section::sp", 11, &[]).is_none());
+    }
+
+    #[test]
+    fn test_definitions_from_source() {
+        let source =
+            "# Doubles x\n# twice\ndef double(x): x + x;\n\ndef _private(): 1;\ndef plain(): 2;\n";
+        assert_eq!(
+            definitions(source),
+            vec![
+                Definition {
+                    name: "double".to_string(),
+                    params: "x".to_string(),
+                    description: "Doubles x twice".to_string(),
+                },
+                Definition {
+                    name: "plain".to_string(),
+                    params: String::new(),
+                    description: String::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            module_description("# CSV in mq\n\ndef a(): 1;"),
+            "CSV in mq"
+        );
+    }
+
+    #[test]
+    fn test_complete_user_functions() {
+        let query = "def double(x): x + x; | dou";
+        let completion = complete(query, query.len(), &[]).unwrap();
+        assert_eq!(completion.candidates[0].text, "double");
+        assert_eq!(completion.candidates[0].signature, "double(x)");
+    }
+
+    #[test]
+    fn test_apply_completion() {
+        let query = ".h | sel";
+        let completion = Completion {
+            candidates: vec![Candidate::function("select", "condition", "")],
+            selected: 0,
+            start: 5,
+        };
+        assert_eq!(
+            completion.apply(query, query.len()),
+            Some((".h | select()".to_string(), 12))
+        );
+
+        let completion = Completion {
+            candidates: vec![Candidate {
+                text: ".code".to_string(),
+                signature: ".code".to_string(),
+                description: String::new(),
+                kind: CandidateKind::Selector,
+            }],
+            selected: 0,
+            start: 0,
+        };
+        assert_eq!(completion.apply(".c", 2), Some((".code".to_string(), 5)));
+    }
+
+    #[test]
+    fn test_completion_navigation_wraps() {
+        let mut completion = Completion {
+            candidates: vec![
+                Candidate::function("a", "", ""),
+                Candidate::function("b", "", ""),
+            ],
+            selected: 0,
+            start: 0,
+        };
+        completion.move_up();
+        assert_eq!(completion.selected, 1);
+        completion.move_down();
+        assert_eq!(completion.selected, 0);
+    }
+}
@@ -0,0 +1,163 @@
+use miette::{IntoDiagnostic, WrapErr};
+use serde::Deserialize;
+use std::{collections::HashMap, ffi::OsString, fs, path::PathBuf};
+
+use crate::clipboard::ClipboardMode;
+
+/// User settings read from `$XDG_CONFIG_HOME/mq-tui/config.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds to wait for a query before abandoning it. The engine can't
+    /// interrupt a query, so it keeps running in the background until it ends.
+    pub query_timeout: Option<f64>,
+    /// Maximum number of result nodes kept for a query
+    pub max_results: Option<usize>,
+    /// Key bindings replacing the defaults
+    pub keys: KeysConfig,
+    /// Name of the theme to use
+    pub theme: Option<String>,
+    /// User-defined themes from the `[themes.<name>]` tables, mapping style
+    /// names to styles such as `"black on yellow"`
+    pub themes: HashMap<String, HashMap<String, String>>,
+    /// How results are copied: `auto`, `system` or `osc52`
+    pub clipboard: ClipboardMode,
+}
+
+/// Key chords mapped to action names for each mode, from the `[keys.normal]`,
+/// `[keys.query]`, `[keys.tree]` and `[keys.inspect]` tables. Mapping a key to
+/// `"none"` unbinds it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    pub normal: HashMap<String, String>,
+    pub query: HashMap<String, String>,
+    pub tree: HashMap<String, String>,
+    pub inspect: HashMap<String, String>,
+}
+
+impl Config {
+    /// Load the config from `path`. A missing file yields the default config.
+    pub fn load(path: Option<PathBuf>) -> miette::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content)
+                .wrap_err_with(|| format!("Invalid config file {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).into_diagnostic(),
+        }
+    }
+
+    pub fn parse(content: &str) -> miette::Result<Self> {
+        toml::from_str(content).into_diagnostic()
+    }
+}
+
+/// Default config file location (`$XDG_CONFIG_HOME/mq-tui/config.toml`)
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(test) {
+        // Never read the user's config from unit tests
+        return None;
+    }
+
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("mq-tui").join("config.toml"))
+}
+
+/// Base directory named by the XDG variable `var`, or `fallback` below the
+/// home directory when it's unset.
+///
+/// Unlike `dirs::config_dir` this follows the XDG layout on macOS as well.
+pub(crate) fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    resolve_xdg_dir(std::env::var_os(var), dirs::home_dir(), fallback)
+}
+
+fn resolve_xdg_dir(
+    value: Option<OsString>,
+    home: Option<PathBuf>,
+    fallback: &str,
+) -> Option<PathBuf> {
+    // The spec says relative paths are invalid and must be ignored
+    value
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home.map(|home| home.join(fallback)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_xdg_dir() {
+        let home = Some(PathBuf::from("/home/user"));
+        assert_eq!(
+            resolve_xdg_dir(Some("/xdg/config".into()), home.clone(), ".config"),
+            Some(PathBuf::from("/xdg/config"))
+        );
+        assert_eq!(
+            resolve_xdg_dir(None, home.clone(), ".config"),
+            Some(PathBuf::from("/home/user/.config"))
+        );
+        // Relative paths are ignored
+        assert_eq!(
+            resolve_xdg_dir(Some("config".into()), home, ".local/share"),
+            Some(PathBuf::from("/home/user/.local/share"))
+        );
+        assert_eq!(resolve_xdg_dir(None, None, ".config"), None);
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse("query_timeout = 2.5\nmax_results = 1000\n").unwrap();
+        assert_eq!(config.query_timeout, Some(2.5));
+        assert_eq!(config.max_results, Some(1000));
+
+        let config = Config::parse("").unwrap();
+        assert!(config.query_timeout.is_none());
+        assert!(config.max_results.is_none());
+
+        assert!(Config::parse("max_results = \"many\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_bindings() {
+        let config = Config::parse(
+            "[keys.normal]\nq = \"none\"\n\"ctrl+q\" = \"quit\"\n\n[keys.tree]\nl = \"toggle\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.keys.normal.get("ctrl+q").unwrap(), "quit");
+        assert_eq!(config.keys.normal.get("q").unwrap(), "none");
+        assert_eq!(config.keys.tree.get("l").unwrap(), "toggle");
+        assert!(config.keys.query.is_empty());
+    }
+
+    #[test]
+    fn test_parse_themes() {
+        let config = Config::parse(
+            "theme = \"paper\"\n\n[themes.paper]\nbase = \"light\"\nheader = \"blue bold\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.theme.as_deref(), Some("paper"));
+        assert_eq!(config.themes["paper"]["base"], "light");
+        assert_eq!(config.themes["paper"]["header"], "blue bold");
+    }
+
+    #[test]
+    fn test_parse_clipboard_mode() {
+        let config = Config::parse("clipboard = \"osc52\"\n").unwrap();
+        assert_eq!(config.clipboard, ClipboardMode::Osc52);
+        assert_eq!(Config::parse("").unwrap().clipboard, ClipboardMode::Auto);
+        assert!(Config::parse("clipboard = \"x11\"\n").is_err());
+    }
+
+    #[test]
+    fn test_load_missing_config() {
+        let path = std::env::temp_dir().join("mq-tui-missing-config.toml");
+        let config = Config::load(Some(path)).unwrap();
+        assert!(config.query_timeout.is_none());
+        assert!(Config::load(None).is_ok());
+    }
+}
@@ -0,0 +1,333 @@
+use miette::{IntoDiagnostic, miette};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// File extensions treated as Markdown when loading a directory
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+/// Build and dependency directories skipped when searching for files
+const SKIPPED_DIRECTORIES: [&str; 2] = ["node_modules", "target"];
+
+/// Directory levels below the root searched by [`find_markdown_files`]
+const MAX_SEARCH_DEPTH: usize = 8;
+
+/// Directory entries looked at by [`find_markdown_files`] before it gives up
+const MAX_SEARCH_ENTRIES: usize = 20_000;
+
+/// A Markdown document loaded into the app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    /// Name shown in the title bar and file list
+    pub filename: Option<String>,
+    /// The Markdown content
+    pub content: String,
+    /// File the content was read from, if it can be reloaded
+    pub path: Option<PathBuf>,
+    /// Modification time of `path` when the content was read
+    pub modified: Option<SystemTime>,
+}
+
+impl Document {
+    pub fn new(content: String) -> Self {
+        Self {
+            filename: None,
+            content,
+            path: None,
+            modified: None,
+        }
+    }
+
+    pub fn with_file(content: String, filename: String) -> Self {
+        Self {
+            filename: Some(filename),
+            content,
+            path: None,
+            modified: None,
+        }
+    }
+
+    /// Read a document from `path`, remembering the path so it can be reloaded
+    pub fn from_path(path: &Path, filename: String) -> miette::Result<Self> {
+        let mut document = Self::with_file(String::new(), filename);
+        document.path = Some(path.to_path_buf());
+        document.reload()?;
+        Ok(document)
+    }
+
+    /// Re-read the content from the document's file
+    pub fn reload(&mut self) -> miette::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // Only a successful read counts, so a failed one is retried
+        let modified = modified_time(path);
+        self.content =
+            fs::read_to_string(path).map_err(|err| miette!("{}: {}", path.display(), err))?;
+        self.modified = modified;
+        Ok(())
+    }
+
+    /// Whether the document's file was modified since it was last read.
+    ///
+    /// A file that is missing (e.g. while an editor replaces it) doesn't count as changed.
+    pub fn changed_on_disk(&self) -> bool {
+        self.path
+            .as_deref()
+            .and_then(modified_time)
+            .is_some_and(|modified| Some(modified) != self.modified)
+    }
+
+    /// Display name of the document
+    pub fn name(&self) -> &str {
+        self.filename.as_deref().unwrap_or("None")
+    }
+}
+
+/// Collect Markdown files from a path.
+///
+/// Files are returned as-is; directories are walked recursively and every
+/// Markdown file below them is returned in sorted order. Hidden entries and
+/// symlinked directories, which may loop back to a parent, are skipped.
+pub fn collect_markdown_files(path: &Path) -> miette::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut entries = fs::read_dir(path)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .into_diagnostic()?;
+    entries.sort();
+
+    let mut files = Vec::new();
+    for entry in entries {
+        if is_hidden(&entry) {
+            continue;
+        }
+
+        let file_type = fs::symlink_metadata(&entry).into_diagnostic()?.file_type();
+        if file_type.is_dir() {
+            files.extend(collect_markdown_files(&entry)?);
+        } else if file_type.is_symlink() && entry.is_dir() {
+            continue;
+        } else if is_markdown_file(&entry) {
+            files.push(entry);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Markdown files below `root` for the file picker, nearest first.
+///
+/// Unlike [`collect_markdown_files`] the search is bounded, so starting it
+/// from a home directory or a large repository doesn't hang: it skips hidden,
+/// VCS and build directories, stays within a few levels of `root` and stops
+/// after looking at a fixed number of entries. The second value tells whether
+/// it stopped early. Unreadable directories are skipped.
+pub fn find_markdown_files(root: &Path) -> (Vec<PathBuf>, bool) {
+    let mut files = Vec::new();
+    let mut directories = VecDeque::from([(root.to_path_buf(), 0)]);
+    let mut seen = 0;
+
+    while let Some((dir, depth)) = directories.pop_front() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        for entry in entries {
+            seen += 1;
+            if seen > MAX_SEARCH_ENTRIES {
+                return (files, true);
+            }
+            if is_hidden(&entry) {
+                continue;
+            }
+
+            let Ok(metadata) = fs::symlink_metadata(&entry) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                let skipped = entry
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| SKIPPED_DIRECTORIES.contains(&n));
+                if !skipped && depth < MAX_SEARCH_DEPTH {
+                    directories.push_back((entry, depth + 1));
+                }
+            } else if is_markdown_file(&entry) && !entry.is_dir() {
+                files.push(entry);
+            }
+        }
+    }
+
+    (files, false)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            MARKDOWN_EXTENSIONS
+                .iter()
+                .any(|md| ext.eq_ignore_ascii_case(md))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_name() {
+        assert_eq!(Document::new("# Test".to_string()).name(), "None");
+        assert_eq!(
+            Document::with_file("# Test".to_string(), "test.md".to_string()).name(),
+            "test.md"
+        );
+    }
+
+    #[test]
+    fn test_document_reload() {
+        let path = std::env::temp_dir().join(format!("mq-tui-reload-{}.md", std::process::id()));
+        fs::write(&path, "# Before").unwrap();
+
+        let mut document = Document::from_path(&path, "reload.md".to_string()).unwrap();
+        assert_eq!(document.content, "# Before");
+        assert!(!document.changed_on_disk());
+
+        fs::write(&path, "# After").unwrap();
+        // Make sure the modification time differs on coarse-grained file systems
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(document.changed_on_disk());
+
+        document.reload().unwrap();
+        assert_eq!(document.content, "# After");
+        assert!(!document.changed_on_disk());
+
+        // A failed read keeps the content and is retried
+        fs::write(&path, [0xff, 0xfe]).unwrap();
+        let later = later + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let err = document.reload().unwrap_err();
+        assert!(err.to_string().contains(&path.display().to_string()));
+        assert_eq!(document.content, "# After");
+        assert!(document.changed_on_disk());
+
+        fs::remove_file(&path).unwrap();
+        assert!(Document::new("# Test".to_string()).reload().is_ok());
+    }
+
+    #[test]
+    fn test_is_markdown_file() {
+        assert!(is_markdown_file(Path::new("README.md")));
+        assert!(is_markdown_file(Path::new("docs/guide.MARKDOWN")));
+        assert!(!is_markdown_file(Path::new("main.rs")));
+        assert!(!is_markdown_file(Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_collect_markdown_files_from_directory() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-docs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        fs::write(dir.join("b.md"), "# B").unwrap();
+        fs::write(dir.join("a.md"), "# A").unwrap();
+        fs::write(dir.join("notes.txt"), "not markdown").unwrap();
+        fs::write(dir.join("nested").join("c.markdown"), "# C").unwrap();
+        fs::write(dir.join(".hidden").join("d.md"), "# D").unwrap();
+
+        let files = collect_markdown_files(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join("a.md"),
+                dir.join("b.md"),
+                dir.join("nested").join("c.markdown"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_markdown_files_skips_symlinked_directories() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-loop-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("nested").join("a.md"), "# A").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("nested").join("loop")).unwrap();
+        std::os::unix::fs::symlink("nested/a.md", dir.join("link.md")).unwrap();
+
+        let files = collect_markdown_files(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            vec![dir.join("link.md"), dir.join("nested").join("a.md")]
+        );
+    }
+
+    #[test]
+    fn test_find_markdown_files() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-find-{}", std::process::id()));
+        let deep =
+            (0..=MAX_SEARCH_DEPTH).fold(dir.join("docs"), |path, i| path.join(i.to_string()));
+        fs::create_dir_all(&deep).unwrap();
+        for skipped in ["node_modules", "target", ".git"] {
+            fs::create_dir_all(dir.join(skipped)).unwrap();
+            fs::write(dir.join(skipped).join("skipped.md"), "").unwrap();
+        }
+        fs::write(dir.join("docs").join("guide.md"), "").unwrap();
+        fs::write(dir.join("README.md"), "").unwrap();
+        fs::write(deep.join("too-deep.md"), "").unwrap();
+
+        let (files, truncated) = find_markdown_files(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Nearest first, without skipped or too deep directories
+        assert_eq!(
+            files,
+            vec![dir.join("README.md"), dir.join("docs").join("guide.md")]
+        );
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_collect_markdown_files_from_file() {
+        let files = collect_markdown_files(Path::new("README.md")).unwrap();
+        assert_eq!(files, vec![PathBuf::from("README.md")]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+/// Maximum number of killed texts kept in the kill ring
+const MAX_KILL_RING_ENTRIES: usize = 30;
+/// Maximum number of undo steps kept for the query
+const MAX_UNDO_ENTRIES: usize = 200;
+
+/// Emacs-style kill ring for text deleted with Ctrl+W/Ctrl+U/Ctrl+K/Alt+D
+#[derive(Debug, Clone, Default)]
+pub struct KillRing {
+    /// Killed texts, newest first
+    entries: VecDeque<String>,
+    /// Entry inserted by the last yank, advanced by yank-pop
+    yank_index: usize,
+}
+
+impl KillRing {
+    /// Add a newly killed text as the newest entry.
+    ///
+    /// Killing the same text as the newest entry again is not recorded twice.
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() || self.entries.front() == Some(&text) {
+            return;
+        }
+
+        self.entries.push_front(text);
+        self.entries.truncate(MAX_KILL_RING_ENTRIES);
+    }
+
+    /// Merge text killed by a consecutive kill into the newest entry.
+    ///
+    /// Backward kills are prepended so the entry reads in document order.
+    pub fn extend(&mut self, text: &str, backward: bool) {
+        match self.entries.front_mut() {
+            Some(entry) if backward => entry.insert_str(0, text),
+            Some(entry) => entry.push_str(text),
+            None => self.push(text.to_string()),
+        }
+    }
+
+    /// The newest entry, resetting the yank-pop position
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.entries.front().map(String::as_str)
+    }
+
+    /// The entry before the last yanked one, wrapping around
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.entries.get(self.yank_index).map(String::as_str)
+    }
+}
+
+/// Query text and cursor position at some point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub text: String,
+    pub cursor: usize,
+}
+
+/// Undo and redo stacks for the query editor
+#[derive(Debug, Clone, Default)]
+pub struct UndoStack {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl UndoStack {
+    /// Record the state before an edit.
+    ///
+    /// With `coalesce` the edit joins the previous undo step, so typing a
+    /// word can be undone at once.
+    pub fn record(&mut self, snapshot: Snapshot, coalesce: bool) {
+        if !coalesce || self.undo.is_empty() {
+            self.undo.push(snapshot);
+            if self.undo.len() > MAX_UNDO_ENTRIES {
+                self.undo.remove(0);
+            }
+        }
+
+        self.redo.clear();
+    }
+
+    /// Restore the previous state, saving `current` for redo
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(current);
+        Some(snapshot)
+    }
+
+    /// Restore the state undone last, saving `current` for undo
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(current);
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(text: &str) -> Snapshot {
+        Snapshot {
+            text: text.to_string(),
+            cursor: text.len(),
+        }
+    }
+
+    #[test]
+    fn test_kill_ring_yank_pop() {
+        let mut ring = KillRing::default();
+        assert!(ring.yank().is_none());
+
+        ring.push("first".to_string());
+        ring.push("second".to_string());
+        ring.push(String::new());
+        ring.push("second".to_string());
+
+        assert_eq!(ring.yank(), Some("second"));
+        assert_eq!(ring.yank_pop(), Some("first"));
+        assert_eq!(ring.yank_pop(), Some("second"));
+        assert_eq!(ring.yank(), Some("second"));
+    }
+
+    #[test]
+    fn test_kill_ring_extend() {
+        let mut ring = KillRing::default();
+        ring.push("select".to_string());
+        ring.extend(".h | ", true);
+        ring.extend("(1)", false);
+
+        assert_eq!(ring.yank(), Some(".h | select(1)"));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut stack = UndoStack::default();
+        stack.record(snapshot(""), false);
+        // Typing more characters joins the same undo step
+        stack.record(snapshot(".h"), true);
+        stack.record(snapshot(".h1"), false);
+
+        assert_eq!(stack.undo(snapshot(".h1 | ")), Some(snapshot(".h1")));
+        assert_eq!(stack.undo(snapshot(".h1")), Some(snapshot("")));
+        assert_eq!(stack.undo(snapshot("")), None);
+        assert_eq!(stack.redo(snapshot("")), Some(snapshot(".h1")));
+        assert_eq!(stack.redo(snapshot(".h1")), Some(snapshot(".h1 | ")));
+        assert_eq!(stack.redo(snapshot(".h1 | ")), None);
+
+        // A new edit clears the redo stack
+        stack.undo(snapshot(".h1 | "));
+        stack.record(snapshot(".h1"), false);
+        assert_eq!(stack.redo(snapshot(".h1x")), None);
+    }
+}
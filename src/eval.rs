@@ -0,0 +1,272 @@
+use miette::Diagnostic;
+use mq_lang::{Engine, RuntimeValue};
+use mq_markdown::{Markdown, Node};
+use std::{ops::Range, sync::Arc};
+
+/// Errors that can occur while evaluating a query against a document
+#[derive(Debug, Clone)]
+pub enum EvalError {
+    Parse(String),
+    Query(String, Option<Range<usize>>),
+    /// The query hit the timeout or the result cap
+    LimitExceeded,
+}
+
+impl EvalError {
+    /// Prefix the message with the name of the document that failed
+    pub fn with_source(self, name: &str) -> Self {
+        match self {
+            EvalError::Parse(msg) => EvalError::Parse(format!("{}: {}", name, msg)),
+            EvalError::Query(msg, span) => EvalError::Query(format!("{}: {}", name, msg), span),
+            EvalError::LimitExceeded => EvalError::LimitExceeded,
+        }
+    }
+}
+
+/// Markdown documents parsed once and reused by every query until their
+/// content changes.
+///
+/// Documents are identified by their index in the app. Parsed nodes are
+/// shared with the query worker through an [`Arc`].
+#[derive(Debug, Default)]
+pub struct ParsedDocuments {
+    documents: Vec<Option<Result<Arc<Vec<Node>>, String>>>,
+}
+
+impl ParsedDocuments {
+    /// Whether the document at `idx` is parsed and cached
+    pub fn is_parsed(&self, idx: usize) -> bool {
+        self.documents.get(idx).is_some_and(Option::is_some)
+    }
+
+    /// Parse the document at `idx` unless it's already cached
+    pub fn parse(&mut self, idx: usize, content: &str) -> Result<Arc<Vec<Node>>, EvalError> {
+        if self.documents.len() <= idx {
+            self.documents.resize_with(idx + 1, || None);
+        }
+
+        self.documents[idx]
+            .get_or_insert_with(|| {
+                Markdown::from_markdown_str(content)
+                    .map(|markdown| Arc::new(markdown.nodes))
+                    .map_err(|err| err.to_string())
+            })
+            .clone()
+            .map_err(EvalError::Parse)
+    }
+
+    /// Parsed nodes of the document at `idx`, if it has been parsed successfully
+    #[cfg(test)]
+    pub fn nodes(&self, idx: usize) -> Option<&[Node]> {
+        match self.documents.get(idx) {
+            Some(Some(Ok(nodes))) => Some(nodes),
+            _ => None,
+        }
+    }
+
+    /// Drop the cached parse of the document at `idx` after its content changed
+    pub fn invalidate(&mut self, idx: usize) {
+        if let Some(parsed) = self.documents.get_mut(idx) {
+            *parsed = None;
+        }
+    }
+}
+
+/// Parsed nodes of a document and their `RuntimeValue` conversion
+type ConvertedNodes = (Arc<Vec<Node>>, Vec<RuntimeValue>);
+
+/// Deepest nesting of function calls a query may reach, so runaway recursion
+/// ends with an error
+const MAX_CALL_STACK_DEPTH: u32 = 128;
+
+/// Create an engine with the builtin module loaded.
+///
+/// Every query needs a fresh engine: a query's `def`s are stored in the
+/// engine's environment and its tokens in the engine's token arena, and clones
+/// of an engine share both, so a loaded engine can't serve as a prototype to
+/// clone from. The query worker builds the next engine while it waits for a
+/// query instead.
+pub fn new_engine() -> Engine {
+    let mut engine = Engine::default();
+    engine.set_max_call_stack_depth(MAX_CALL_STACK_DEPTH);
+    engine.load_builtin_module();
+    engine
+}
+
+/// Evaluates queries against parsed documents.
+///
+/// The `RuntimeValue` conversion of each document is cached and reused as long
+/// as the same parsed nodes are passed in.
+#[derive(Default)]
+pub struct Evaluator {
+    values: Vec<Option<ConvertedNodes>>,
+}
+
+impl Evaluator {
+    /// Evaluate the query against the parsed nodes of the document at `idx`.
+    ///
+    /// At most `limit` results are converted, so callers can tell a query that
+    /// went over their cap without copying every result. The engine returns
+    /// all results at once, so the cap doesn't bound the evaluation itself;
+    /// only the worker's timeout does.
+    pub fn eval(
+        &mut self,
+        engine: &mut Engine,
+        query: &str,
+        idx: usize,
+        nodes: &Arc<Vec<Node>>,
+        limit: usize,
+    ) -> Result<Vec<Node>, EvalError> {
+        if query.is_empty() {
+            // Show all nodes when query is empty
+            return Ok(nodes.iter().take(limit).cloned().collect());
+        }
+
+        if self.values.len() <= idx {
+            self.values.resize_with(idx + 1, || None);
+        }
+
+        // Convert the nodes again only if the document was re-parsed
+        let entry = &mut self.values[idx];
+        if !entry
+            .as_ref()
+            .is_some_and(|(cached, _)| Arc::ptr_eq(cached, nodes))
+        {
+            *entry = None;
+        }
+        let (_, values) = entry.get_or_insert_with(|| {
+            let values = nodes.iter().cloned().map(RuntimeValue::from).collect();
+            (Arc::clone(nodes), values)
+        });
+
+        engine
+            .eval(query, values.iter().cloned())
+            .map(|results| {
+                results
+                    .into_iter()
+                    .take(limit)
+                    .map(|runtime_value| match runtime_value {
+                        RuntimeValue::Markdown(node, _) => node.clone(),
+                        _ => runtime_value.to_string().into(),
+                    })
+                    .collect()
+            })
+            .map_err(|err| {
+                let span = err
+                    .labels()
+                    .and_then(|mut labels| labels.next())
+                    .map(|label| label.offset()..label.offset() + label.len());
+                EvalError::Query(err.to_string(), span)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "# Title\n\nParagraph\n\n## Section\n";
+
+    #[test]
+    fn test_parse_is_cached_until_invalidated() {
+        let mut documents = ParsedDocuments::default();
+        assert!(!documents.is_parsed(1));
+
+        let nodes = documents.parse(1, CONTENT).unwrap();
+        assert!(documents.is_parsed(1));
+        assert!(!documents.is_parsed(0));
+
+        // The cached nodes are used even if different content is passed
+        let cached = documents.parse(1, "# Other\n").unwrap();
+        assert!(Arc::ptr_eq(&nodes, &cached));
+        assert_eq!(documents.nodes(1).unwrap().len(), 3);
+
+        documents.invalidate(1);
+        assert!(!documents.is_parsed(1));
+        documents.parse(1, "# Other\n").unwrap();
+        assert_eq!(documents.nodes(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_eval_caches_converted_nodes() {
+        let mut documents = ParsedDocuments::default();
+        let mut evaluator = Evaluator::default();
+        let mut engine = new_engine();
+        let nodes = documents.parse(0, CONTENT).unwrap();
+
+        assert_eq!(
+            evaluator
+                .eval(&mut engine, "", 0, &nodes, usize::MAX)
+                .unwrap()
+                .len(),
+            3
+        );
+        // Every input node yields a result, non-headings as empty values
+        assert_eq!(
+            evaluator
+                .eval(&mut engine, ".h", 0, &nodes, usize::MAX)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            evaluator
+                .eval(&mut engine, ".h", 0, &nodes, usize::MAX)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(matches!(
+            evaluator.eval(&mut engine, ".h | unknown_fn()", 0, &nodes, usize::MAX),
+            Err(EvalError::Query(_, _))
+        ));
+
+        // Only as many results as asked for are converted
+        assert_eq!(
+            evaluator
+                .eval(&mut engine, ".h", 0, &nodes, 2)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            evaluator.eval(&mut engine, "", 0, &nodes, 2).unwrap().len(),
+            2
+        );
+
+        // Re-parsed nodes replace the cached values
+        documents.invalidate(0);
+        let nodes = documents.parse(0, "# Other\n").unwrap();
+        assert_eq!(
+            evaluator
+                .eval(&mut engine, ".h", 0, &nodes, usize::MAX)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_eval_does_not_keep_definitions() {
+        let mut documents = ParsedDocuments::default();
+        let mut evaluator = Evaluator::default();
+        let nodes = documents.parse(0, CONTENT).unwrap();
+
+        assert!(
+            evaluator
+                .eval(
+                    &mut new_engine(),
+                    "def f(): 1; | f()",
+                    0,
+                    &nodes,
+                    usize::MAX
+                )
+                .is_ok()
+        );
+        // The definition is gone once it's removed from the query
+        assert!(matches!(
+            evaluator.eval(&mut new_engine(), "f()", 0, &nodes, usize::MAX),
+            Err(EvalError::Query(_, _))
+        ));
+    }
+}
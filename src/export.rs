@@ -0,0 +1,170 @@
+use miette::{IntoDiagnostic, miette};
+use mq_markdown::{Markdown, Node};
+use std::{fs, io::Write, path::Path};
+
+/// Formats results can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: &[ExportFormat] = &[ExportFormat::Markdown, ExportFormat::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Serialize results in the given format.
+///
+/// `sources` names the file each result came from (all files mode only).
+pub fn export_results(format: ExportFormat, results: &[Node], sources: &[&str]) -> String {
+    match format {
+        ExportFormat::Markdown => Markdown::new(results.to_vec()).to_string(),
+        ExportFormat::Json => results_to_json(results, sources),
+    }
+}
+
+/// Results as a JSON array with the type, text value and Markdown of each node
+fn results_to_json(results: &[Node], sources: &[&str]) -> String {
+    let values = results
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let mut value = serde_json::json!({
+                "type": node_type(node),
+                "value": node.value(),
+                "markdown": Markdown::new(vec![node.clone()]).to_string().trim_end(),
+            });
+            if let Some(source) = sources.get(i) {
+                value["file"] = serde_json::Value::from(*source);
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+
+    // Serializing plain JSON values can't fail
+    serde_json::to_string_pretty(&values).unwrap_or_default()
+}
+
+/// Name of the node's type, e.g. `heading` or `code`
+pub fn node_type(node: &Node) -> &'static str {
+    match node {
+        Node::Heading(_) => "heading",
+        Node::List(_) => "list",
+        Node::Code(_) => "code",
+        Node::Blockquote(_) => "blockquote",
+        Node::Strong(_) => "strong",
+        Node::Emphasis(_) => "emphasis",
+        Node::Link(_) => "link",
+        Node::Image(_) => "image",
+        Node::Text(_) => "text",
+        Node::HorizontalRule(_) => "horizontal_rule",
+        Node::TableHeader(_) => "table_header",
+        Node::TableRow(_) => "table_row",
+        Node::TableCell(_) => "table_cell",
+        Node::Break(_) => "break",
+        Node::Html(_) => "html",
+        Node::Math(_) => "math",
+        Node::MathInline(_) => "math_inline",
+        Node::CodeInline(_) => "code_inline",
+        Node::Delete(_) => "delete",
+        Node::Yaml(_) => "yaml",
+        Node::Toml(_) => "toml",
+        Node::Fragment(_) => "fragment",
+        Node::Footnote(_) => "footnote",
+        Node::FootnoteRef(_) => "footnote_ref",
+        Node::Definition(_) => "definition",
+        Node::ImageRef(_) => "image_ref",
+        Node::LinkRef(_) => "link_ref",
+        Node::MdxFlowExpression(_) => "mdx_flow_expression",
+        Node::MdxJsxFlowElement(_) => "mdx_jsx_flow_element",
+        Node::MdxJsxTextElement(_) => "mdx_jsx_text_element",
+        Node::MdxTextExpression(_) => "mdx_text_expression",
+        Node::MdxJsEsm(_) => "mdx_js_esm",
+        Node::Empty => "empty",
+    }
+}
+
+/// Write `content` to `path`. An existing file is only replaced with `overwrite`.
+pub fn write_file(path: &Path, content: &str, overwrite: bool) -> miette::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    let mut file = options.open(path).map_err(|err| {
+        if err.kind() == std::io::ErrorKind::AlreadyExists {
+            miette!("{} already exists (add ! to overwrite)", path.display())
+        } else {
+            miette!("Failed to write {}: {}", path.display(), err)
+        }
+    })?;
+    file.write_all(content.as_bytes()).into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> Vec<Node> {
+        Markdown::from_markdown_str("# Title\n\n```rust\nfn main() {}\n```\n")
+            .unwrap()
+            .nodes
+    }
+
+    #[test]
+    fn test_export_json() {
+        let json = export_results(ExportFormat::Json, &nodes(), &[]);
+        let values: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(values[0]["type"], "heading");
+        assert_eq!(values[0]["value"], "Title");
+        assert_eq!(values[0]["markdown"], "# Title");
+        assert_eq!(values[1]["type"], "code");
+        assert!(values[1].get("file").is_none());
+
+        let json = export_results(ExportFormat::Json, &nodes(), &["a.md", "b.md"]);
+        let values: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(values[1]["file"], "b.md");
+    }
+
+    #[test]
+    fn test_write_file_overwrite() {
+        let path = std::env::temp_dir().join(format!("mq-tui-export-{}.md", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        write_file(&path, "# One", false).unwrap();
+        assert!(write_file(&path, "# Two", false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# One");
+
+        write_file(&path, "# Two", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Two");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_format_names() {
+        for format in ExportFormat::ALL {
+            assert_eq!(ExportFormat::from_name(format.name()), Some(*format));
+        }
+        assert_eq!(ExportFormat::from_name("md"), Some(ExportFormat::Markdown));
+        assert!(ExportFormat::from_name("yaml").is_none());
+    }
+}
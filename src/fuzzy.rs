@@ -0,0 +1,68 @@
+/// Score how well `pattern` fuzzy-matches `candidate`.
+///
+/// Every character of the pattern must appear in the candidate in order
+/// (case-insensitive). Consecutive matches and matches at the start of a word
+/// score higher. Returns `None` when the pattern doesn't match.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let mut pattern_idx = 0;
+    let mut score = 0;
+    let mut prev_matched = false;
+    let mut prev_char: Option<char> = None;
+
+    for c in candidate.chars() {
+        if pattern_idx < pattern.len() && c.to_lowercase().eq(pattern[pattern_idx].to_lowercase()) {
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if prev_char.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 3;
+            }
+            pattern_idx += 1;
+            prev_matched = true;
+        } else {
+            prev_matched = false;
+        }
+        prev_char = Some(c);
+    }
+
+    if pattern_idx == pattern.len() {
+        // Prefer shorter candidates when scores are otherwise equal
+        Some(score * 100 - candidate.chars().count() as i64)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score_matches_subsequence() {
+        assert!(fuzzy_score("hd", ".h | select(.depth == 2)").is_some());
+        assert!(fuzzy_score("SEL", ".h | select(.depth == 2)").is_some());
+        assert!(fuzzy_score("", ".h").is_some());
+        assert!(fuzzy_score("xyz", ".h | select(.depth == 2)").is_none());
+        assert!(fuzzy_score("dh", ".h").is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_score_prefers_consecutive_matches() {
+        let consecutive = fuzzy_score("code", ".code").unwrap();
+        let scattered = fuzzy_score("code", ".c | .o | .d | .e").unwrap();
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn test_fuzzy_score_prefers_shorter_candidates() {
+        let short = fuzzy_score("code", ".code").unwrap();
+        let long = fuzzy_score("code", ".code | select(.lang == \"rust\")").unwrap();
+        assert!(short > long);
+    }
+}
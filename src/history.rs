@@ -0,0 +1,273 @@
+use miette::IntoDiagnostic;
+use std::{fs, path::PathBuf};
+
+use crate::{config, fuzzy::fuzzy_score};
+
+/// Maximum number of queries kept in the history file
+pub const MAX_HISTORY_ENTRIES: usize = 1000;
+
+/// Query history, optionally persisted to a file across sessions
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// Queries from oldest to newest, without duplicates
+    entries: Vec<String>,
+    /// File the history is loaded from and saved to (`None` disables persistence)
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Load the history from `path`. A missing or unreadable file yields an empty history.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| parse_entries(&content))
+            .unwrap_or_default();
+
+        let mut history = Self { entries, path };
+        history.truncate();
+        history
+    }
+
+    /// An in-memory history that is never written to disk
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Add a query as the newest entry, removing any older duplicate
+    pub fn push(&mut self, query: &str) {
+        if query.is_empty() {
+            return;
+        }
+
+        self.entries.retain(|entry| entry != query);
+        self.entries.push(query.to_string());
+        self.truncate();
+    }
+
+    /// Write the history to its file, creating the parent directory if needed
+    pub fn save(&self) -> miette::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_diagnostic()?;
+        }
+
+        let content = self
+            .entries
+            .iter()
+            .map(|entry| escape_entry(entry))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(path, content + "\n").into_diagnostic()
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn truncate(&mut self) {
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            let excess = self.entries.len() - MAX_HISTORY_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
+/// State of the reverse-incremental history search popup (Ctrl+R)
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearch {
+    /// Text typed into the search popup
+    pub input: String,
+    /// Index of the selected match
+    pub selected: usize,
+}
+
+impl HistorySearch {
+    /// History entries matching the input, best match first.
+    ///
+    /// Entries with equal scores are ordered newest first.
+    pub fn matches<'a>(&self, history: &'a History) -> Vec<&'a str> {
+        let mut matches = history
+            .entries()
+            .iter()
+            .rev()
+            .filter_map(|entry| fuzzy_score(&self.input, entry).map(|score| (score, entry)))
+            .collect::<Vec<_>>();
+
+        if !self.input.is_empty() {
+            // Stable sort keeps the newest-first order among equal scores
+            matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+        }
+
+        matches
+            .into_iter()
+            .map(|(_, entry)| entry.as_str())
+            .collect()
+    }
+
+    /// The currently selected match, if any
+    pub fn selected_match<'a>(&self, history: &'a History) -> Option<&'a str> {
+        self.matches(history).get(self.selected).copied()
+    }
+
+    pub fn move_down(&mut self, match_count: usize) {
+        if self.selected + 1 < match_count {
+            self.selected += 1;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+/// Default history file location (`$XDG_DATA_HOME/mq-tui/history`)
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(test) {
+        // Never touch the user's history from unit tests
+        return None;
+    }
+
+    config::xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("mq-tui").join("history"))
+}
+
+fn parse_entries(content: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let entry = unescape_entry(line);
+        entries.retain(|e| *e != entry);
+        entries.push(entry);
+    }
+
+    entries
+}
+
+/// Escape backslashes and newlines so each query fits on a single line
+fn escape_entry(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_entry(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("mq-tui-history-{}-{}", name, std::process::id()))
+            .join("history")
+    }
+
+    #[test]
+    fn test_push_deduplicates() {
+        let mut history = History::disabled();
+        history.push(".h");
+        history.push(".code");
+        history.push(".h");
+        history.push("");
+
+        assert_eq!(history.entries(), &[".code".to_string(), ".h".to_string()]);
+    }
+
+    #[test]
+    fn test_push_respects_size_limit() {
+        let mut history = History::disabled();
+        for i in 0..MAX_HISTORY_ENTRIES + 10 {
+            history.push(&format!(".h{}", i));
+        }
+
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history.entries()[0], ".h10");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_history_path("save");
+        let mut history = History::load(Some(path.clone()));
+        assert!(history.is_empty());
+
+        history.push(".h");
+        history.push("def f(x):\n  x;\n| f(\"a\\\\b\")");
+        history.save().unwrap();
+
+        let loaded = History::load(Some(path.clone()));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded.entries(), history.entries());
+    }
+
+    #[test]
+    fn test_disabled_history_is_not_saved() {
+        let mut history = History::disabled();
+        history.push(".h");
+        assert!(history.save().is_ok());
+    }
+
+    #[test]
+    fn test_history_search_matches() {
+        let mut history = History::disabled();
+        history.push(".h | select(.depth == 2)");
+        history.push(".code | select(.lang == \"rust\")");
+        history.push(".h");
+
+        let mut search = HistorySearch::default();
+        // Empty input lists every entry, newest first
+        assert_eq!(
+            search.matches(&history),
+            vec![
+                ".h",
+                ".code | select(.lang == \"rust\")",
+                ".h | select(.depth == 2)"
+            ]
+        );
+
+        search.input = "sel".to_string();
+        assert_eq!(search.matches(&history).len(), 2);
+
+        search.input = "rust".to_string();
+        assert_eq!(
+            search.selected_match(&history),
+            Some(".code | select(.lang == \"rust\")")
+        );
+
+        search.input = "nothing".to_string();
+        assert!(search.selected_match(&history).is_none());
+    }
+
+    #[test]
+    fn test_escape_roundtrip() {
+        let entry = "line1\nline2 \\n";
+        assert_eq!(escape_entry(entry), "line1\\nline2 \\\\n");
+        assert_eq!(unescape_entry(&escape_entry(entry)), entry);
+    }
+}
@@ -0,0 +1,512 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use miette::miette;
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    action::{Action, InspectAction, QueryAction, TreeAction},
+    config::KeysConfig,
+};
+
+/// A key together with its modifiers, e.g. `ctrl+p`.
+///
+/// Chords are normalized so they compare equal however the terminal reports
+/// them: Shift is dropped from symbols (`?` rather than `shift+?`) and kept
+/// as a modifier on lowercase letters (`shift+z` for `Z`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let (code, modifiers) = match code {
+            KeyCode::Char(c) if c.is_uppercase() => (
+                KeyCode::Char(c.to_lowercase().next().unwrap_or(c)),
+                modifiers | KeyModifiers::SHIFT,
+            ),
+            KeyCode::Char(c) if !c.is_alphabetic() => {
+                (code, modifiers.difference(KeyModifiers::SHIFT))
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                (KeyCode::BackTab, modifiers.difference(KeyModifiers::SHIFT))
+            }
+            KeyCode::BackTab => (code, modifiers.difference(KeyModifiers::SHIFT)),
+            _ => (code, modifiers),
+        };
+
+        Self { code, modifiers }
+    }
+
+    /// Parse a chord such as `q`, `ctrl+p`, `alt+enter` or `shift+tab`
+    pub fn parse(chord: &str) -> Result<Self, String> {
+        let mut parts = chord.split('+').collect::<Vec<_>>();
+        // `+` itself and chords ending in `++` name the plus key
+        if chord.ends_with('+') {
+            parts.truncate(parts.len().saturating_sub(2));
+            parts.push("+");
+        }
+        let invalid = || format!("Invalid key: {}", chord);
+        let (key, modifier_names) = parts.split_last().ok_or_else(invalid)?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(invalid()),
+            };
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            name => match (name.strip_prefix('f'), key.chars().count()) {
+                (_, 1) => KeyCode::Char(key.chars().next().ok_or_else(invalid)?),
+                (Some(number), _) => KeyCode::F(number.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            },
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // A lone Shift on a letter reads better as the uppercase letter
+        if let KeyCode::Char(c) = self.code
+            && c.is_alphabetic()
+            && self.modifiers == KeyModifiers::SHIFT
+        {
+            return write!(f, "{}", c.to_uppercase());
+        }
+
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) || self.code == KeyCode::BackTab {
+            write!(f, "Shift+")?;
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::BackTab | KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::Delete => write!(f, "Del"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Key bindings of one mode, kept in definition order for the help screen
+#[derive(Debug, Clone)]
+pub struct Bindings<A> {
+    keys: Vec<(KeyChord, A)>,
+}
+
+impl<A: Copy + PartialEq> Bindings<A> {
+    fn new(defaults: &[(&str, A)]) -> Self {
+        let mut bindings = Self { keys: Vec::new() };
+        for (chord, action) in defaults {
+            let chord = KeyChord::parse(chord).expect("default key bindings are valid");
+            bindings.bind(chord, Some(*action));
+        }
+        bindings
+    }
+
+    /// Action bound to a key.
+    ///
+    /// Shifted keys without a binding of their own fall back to the binding
+    /// of the unshifted key, so e.g. Shift+↓ moves down like ↓. Other
+    /// modifiers never fall back, so Ctrl+Q doesn't quit like q.
+    pub fn action(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<A> {
+        let chord = KeyChord::new(code, modifiers);
+        self.get(chord).or_else(|| {
+            (chord.modifiers == KeyModifiers::SHIFT)
+                .then(|| self.get(KeyChord::new(chord.code, KeyModifiers::NONE)))
+                .flatten()
+        })
+    }
+
+    fn get(&self, chord: KeyChord) -> Option<A> {
+        self.keys
+            .iter()
+            .find(|(key, _)| *key == chord)
+            .map(|(_, action)| *action)
+    }
+
+    /// Keys bound to an action, in definition order
+    pub fn keys(&self, action: A) -> Vec<KeyChord> {
+        self.keys
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Bind a key to an action, replacing its previous binding. `None` unbinds it.
+    fn bind(&mut self, chord: KeyChord, action: Option<A>) {
+        let existing = self.keys.iter().position(|(key, _)| *key == chord);
+        match (existing, action) {
+            (Some(idx), Some(action)) => self.keys[idx].1 = action,
+            (Some(idx), None) => {
+                self.keys.remove(idx);
+            }
+            (None, Some(action)) => self.keys.push((chord, action)),
+            (None, None) => {}
+        }
+    }
+
+    /// Apply bindings from the config file on top of these
+    fn configure(
+        &mut self,
+        mode: &str,
+        bindings: &HashMap<String, String>,
+        from_name: impl Fn(&str) -> Option<A>,
+    ) -> miette::Result<()> {
+        for (chord, name) in bindings {
+            let chord = KeyChord::parse(chord).map_err(|err| miette!("[keys.{}] {}", mode, err))?;
+            let action = match name.as_str() {
+                "" | "none" => None,
+                name => Some(
+                    from_name(name)
+                        .ok_or_else(|| miette!("[keys.{}] Unknown action: {}", mode, name))?,
+                ),
+            };
+            self.bind(chord, action);
+        }
+        Ok(())
+    }
+}
+
+/// Key bindings of every mode
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub normal: Bindings<Action>,
+    pub query: Bindings<QueryAction>,
+    pub tree_view: Bindings<TreeAction>,
+    pub inspect: Bindings<InspectAction>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            normal: Bindings::new(&[
+                ("q", Action::Quit),
+                ("esc", Action::Quit),
+                ("ctrl+o", Action::Accept),
+                ("d", Action::ToggleDetail),
+                ("m", Action::ToggleRendered),
+                (":", Action::EnterQuery),
+                ("ctrl+p", Action::EnterCommand),
+                (";", Action::EnterCommand),
+                ("?", Action::Help),
+                ("f1", Action::Help),
+                ("t", Action::TreeView),
+                ("i", Action::Inspect),
+                ("tab", Action::NextFile),
+                ("shift+tab", Action::PreviousFile),
+                ("f", Action::ToggleFileList),
+                ("a", Action::ToggleAllFiles),
+                ("r", Action::Reload),
+                ("o", Action::Open),
+                ("w", Action::Write),
+                ("s", Action::ToggleSidebar),
+                ("up", Action::Up),
+                ("k", Action::Up),
+                ("down", Action::Down),
+                ("j", Action::Down),
+                ("left", Action::ScrollLeft),
+                ("h", Action::ScrollLeft),
+                ("right", Action::ScrollRight),
+                ("l", Action::ScrollRight),
+                ("pageup", Action::PageUp),
+                ("pagedown", Action::PageDown),
+                ("home", Action::First),
+                ("end", Action::Last),
+                ("ctrl+l", Action::ClearQuery),
+                ("y", Action::Copy),
+            ]),
+            query: Bindings::new(&[
+                ("enter", QueryAction::Submit),
+                ("ctrl+s", QueryAction::Execute),
+                ("esc", QueryAction::Exit),
+                ("ctrl+o", QueryAction::Accept),
+                ("ctrl+r", QueryAction::SearchHistory),
+                ("tab", QueryAction::Complete),
+                ("up", QueryAction::Up),
+                ("down", QueryAction::Down),
+                ("ctrl+t", QueryAction::ToggleMultiline),
+                ("shift+enter", QueryAction::Newline),
+                ("alt+enter", QueryAction::Newline),
+                ("alt+up", QueryAction::ShrinkEditor),
+                ("alt+down", QueryAction::GrowEditor),
+                ("left", QueryAction::Left),
+                ("right", QueryAction::Right),
+                ("home", QueryAction::Home),
+                ("end", QueryAction::End),
+                ("ctrl+a", QueryAction::LineStart),
+                ("ctrl+e", QueryAction::LineEnd),
+                ("alt+b", QueryAction::WordLeft),
+                ("alt+f", QueryAction::WordRight),
+                ("backspace", QueryAction::Backspace),
+                ("delete", QueryAction::Delete),
+                ("ctrl+w", QueryAction::KillWhitespaceWord),
+                ("alt+backspace", QueryAction::KillWordBackward),
+                ("alt+d", QueryAction::KillWordForward),
+                ("ctrl+u", QueryAction::KillToLineStart),
+                ("ctrl+k", QueryAction::KillToLineEnd),
+                ("ctrl+y", QueryAction::Yank),
+                ("alt+y", QueryAction::YankPop),
+                ("ctrl+z", QueryAction::Undo),
+                // Ctrl+_ is reported as Ctrl+7 by some terminals
+                ("ctrl+_", QueryAction::Undo),
+                ("ctrl+7", QueryAction::Undo),
+                ("alt+z", QueryAction::Redo),
+                ("ctrl+shift+z", QueryAction::Redo),
+            ]),
+            tree_view: Bindings::new(&[
+                ("esc", TreeAction::Exit),
+                ("t", TreeAction::Exit),
+                ("q", TreeAction::Quit),
+                ("down", TreeAction::Down),
+                ("j", TreeAction::Down),
+                ("up", TreeAction::Up),
+                ("k", TreeAction::Up),
+                ("enter", TreeAction::Toggle),
+                ("space", TreeAction::Toggle),
+                ("?", TreeAction::Help),
+                ("f1", TreeAction::Help),
+            ]),
+            inspect: Bindings::new(&[
+                ("esc", InspectAction::Exit),
+                ("i", InspectAction::Exit),
+                ("q", InspectAction::Quit),
+                ("down", InspectAction::Down),
+                ("j", InspectAction::Down),
+                ("up", InspectAction::Up),
+                ("k", InspectAction::Up),
+                ("pagedown", InspectAction::PageDown),
+                ("ctrl+d", InspectAction::PageDown),
+                ("pageup", InspectAction::PageUp),
+                ("ctrl+u", InspectAction::PageUp),
+                ("enter", InspectAction::Toggle),
+                ("space", InspectAction::Toggle),
+                ("?", InspectAction::Help),
+                ("f1", InspectAction::Help),
+            ]),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default bindings with the ones from the `[keys]` tables of the
+    /// config file applied on top
+    pub fn from_config(config: &KeysConfig) -> miette::Result<Self> {
+        let mut keymap = Self::default();
+        keymap
+            .normal
+            .configure("normal", &config.normal, Action::from_name)?;
+        keymap
+            .query
+            .configure("query", &config.query, QueryAction::from_name)?;
+        keymap
+            .tree_view
+            .configure("tree", &config.tree, TreeAction::from_name)?;
+        keymap
+            .inspect
+            .configure("inspect", &config.inspect, InspectAction::from_name)?;
+        Ok(keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_chord() {
+        assert_eq!(
+            KeyChord::parse("ctrl+p"),
+            Ok(KeyChord::new(KeyCode::Char('p'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            KeyChord::parse("Alt+Enter"),
+            Ok(KeyChord::new(KeyCode::Enter, KeyModifiers::ALT))
+        );
+        assert_eq!(
+            KeyChord::parse("shift+tab"),
+            Ok(KeyChord::new(KeyCode::BackTab, KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            KeyChord::parse("F1"),
+            Ok(KeyChord::new(KeyCode::F(1), KeyModifiers::NONE))
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl++"),
+            Ok(KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL))
+        );
+        assert!(KeyChord::parse("hyper+x").is_err());
+        assert!(KeyChord::parse("ctrl+nope").is_err());
+    }
+
+    #[test]
+    fn test_key_chord_normalization() {
+        // Terminals report Shift along with symbols and uppercase letters
+        assert_eq!(
+            KeyChord::new(KeyCode::Char('?'), KeyModifiers::SHIFT),
+            KeyChord::new(KeyCode::Char('?'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyChord::new(
+                KeyCode::Char('Z'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT
+            ),
+            KeyChord::parse("ctrl+shift+z").unwrap()
+        );
+        assert_eq!(
+            KeyChord::parse("S").unwrap(),
+            KeyChord::parse("shift+s").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_display_key_chord() {
+        assert_eq!(KeyChord::parse("ctrl+r").unwrap().to_string(), "Ctrl+r");
+        assert_eq!(
+            KeyChord::parse("shift+tab").unwrap().to_string(),
+            "Shift+Tab"
+        );
+        assert_eq!(KeyChord::parse("S").unwrap().to_string(), "S");
+        assert_eq!(KeyChord::parse("alt+up").unwrap().to_string(), "Alt+↑");
+        assert_eq!(KeyChord::parse("esc").unwrap().to_string(), "Esc");
+        assert_eq!(KeyChord::parse("space").unwrap().to_string(), "Space");
+    }
+
+    #[test]
+    fn test_keymap_from_config() {
+        let config = KeysConfig {
+            normal: HashMap::from([
+                ("q".to_string(), "none".to_string()),
+                ("esc".to_string(), "".to_string()),
+                ("ctrl+q".to_string(), "quit".to_string()),
+                ("s".to_string(), "none".to_string()),
+                ("S".to_string(), "sidebar".to_string()),
+            ]),
+            query: HashMap::from([("ctrl+j".to_string(), "submit".to_string())]),
+            tree: HashMap::new(),
+            inspect: HashMap::new(),
+        };
+        let keymap = Keymap::from_config(&config).unwrap();
+
+        assert!(
+            keymap
+                .normal
+                .action(KeyCode::Char('q'), KeyModifiers::NONE)
+                .is_none()
+        );
+        assert!(
+            keymap
+                .normal
+                .action(KeyCode::Esc, KeyModifiers::NONE)
+                .is_none()
+        );
+        assert_eq!(
+            keymap
+                .normal
+                .action(KeyCode::Char('q'), KeyModifiers::CONTROL),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap
+                .normal
+                .action(KeyCode::Char('S'), KeyModifiers::SHIFT),
+            Some(Action::ToggleSidebar)
+        );
+        assert!(
+            keymap
+                .normal
+                .action(KeyCode::Char('s'), KeyModifiers::NONE)
+                .is_none()
+        );
+        assert_eq!(
+            keymap
+                .query
+                .action(KeyCode::Char('j'), KeyModifiers::CONTROL),
+            Some(QueryAction::Submit)
+        );
+        assert_eq!(
+            keymap.query.keys(QueryAction::Submit),
+            vec![
+                KeyChord::parse("enter").unwrap(),
+                KeyChord::parse("ctrl+j").unwrap()
+            ]
+        );
+
+        let config = KeysConfig {
+            normal: HashMap::from([("x".to_string(), "explode".to_string())]),
+            ..KeysConfig::default()
+        };
+        assert!(Keymap::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_default_bindings_fall_back_to_unshifted_key() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.normal.action(KeyCode::Down, KeyModifiers::SHIFT),
+            Some(Action::Down)
+        );
+        assert_eq!(
+            keymap.query.action(KeyCode::Up, KeyModifiers::ALT),
+            Some(QueryAction::ShrinkEditor)
+        );
+        // Only Shift falls back
+        assert_eq!(
+            keymap
+                .normal
+                .action(KeyCode::Char('q'), KeyModifiers::CONTROL),
+            None
+        );
+        assert_eq!(
+            keymap.normal.action(KeyCode::Char('j'), KeyModifiers::ALT),
+            None
+        );
+        assert_eq!(
+            keymap
+                .normal
+                .action(KeyCode::Char('r'), KeyModifiers::CONTROL),
+            None
+        );
+        assert_eq!(
+            keymap.normal.action(KeyCode::BackTab, KeyModifiers::SHIFT),
+            Some(Action::PreviousFile)
+        );
+    }
+}
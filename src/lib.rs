@@ -1,7 +1,30 @@
+mod action;
 mod app;
+mod clipboard;
+mod command;
+mod completion;
+mod config;
+mod document;
+mod editor;
+mod eval;
 mod event;
+mod export;
+mod fuzzy;
+mod history;
+mod keymap;
+mod node;
+mod picker;
+mod text;
+mod theme;
 mod ui;
 mod util;
+mod worker;
 
 pub use app::App;
 pub use app::Mode;
+pub use app::PrintTarget;
+pub use config::{Config, default_path as default_config_path};
+pub use document::{Document, collect_markdown_files};
+pub use keymap::Keymap;
+pub use theme::Theme;
+pub use worker::QueryLimits;
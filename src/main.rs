@@ -1,8 +1,12 @@
 use clap::Parser;
-use miette::{IntoDiagnostic, miette};
-use mq_tui::App;
-use std::fs;
+use miette::{IntoDiagnostic, WrapErr, miette};
+use mq_tui::{
+    App, Config, Document, Keymap, PrintTarget, QueryLimits, Theme, collect_markdown_files,
+    default_config_path,
+};
+use std::io::{self, IsTerminal, Read};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(name = "mq_tui")]
@@ -12,32 +16,201 @@ use std::path::PathBuf;
 #[command(after_help = "Examples:\n\n
     Open a Markdown file:\n
     $ mq_tui README.md\n\n
+    Read Markdown from stdin:\n
+    $ git show HEAD:README.md | mq_tui\n\n
+    Open every Markdown file in a directory:\n
+    $ mq_tui docs/ README.md\n\n
+    Filter interactively and pipe the results:\n
+    $ mq_tui --print-on-exit README.md | pbcopy\n\n
     Use with mq CLI:\n
     $ mq tui file.md")]
 struct Cli {
-    /// Path to the Markdown file to open
+    /// Markdown files or directories to open (reads from stdin if omitted or `-`)
     #[arg(value_name = "FILE")]
-    file_path: Option<PathBuf>,
+    file_paths: Vec<PathBuf>,
+
+    /// Print the query results as Markdown to stdout on exit
+    #[arg(long, conflicts_with = "print_query")]
+    print_on_exit: bool,
+
+    /// Print the final query string to stdout on exit
+    #[arg(long)]
+    print_query: bool,
+
+    /// Do not load or save the query history
+    #[arg(long)]
+    no_history: bool,
+
+    /// Stop waiting for queries running longer than this many seconds [default: 2]
+    ///
+    /// A query can't be interrupted, so it keeps running in the background until it
+    /// ends; only its results are discarded.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<f64>,
+
+    /// Stop queries that produce more result nodes than this [default: 100000]
+    #[arg(long, value_name = "COUNT")]
+    max_results: Option<usize>,
+
+    /// Reload files and re-run the query when they change on disk
+    #[arg(long)]
+    watch: bool,
+
+    /// Color theme: dark, light, monochrome or a theme from the config file [default: dark]
+    #[arg(long, value_name = "NAME")]
+    theme: Option<String>,
+
+    /// Disable colors (same as setting NO_COLOR)
+    #[arg(long)]
+    no_color: bool,
+
+    /// Config file to use instead of `~/.config/mq-tui/config.toml`
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
 }
 
+/// Title shown in the title bar when the document was read from stdin
+const STDIN_FILENAME: &str = "<stdin>";
+
 fn main() -> miette::Result<()> {
     let cli = Cli::parse();
+    let config = Config::load(cli.config.clone().or_else(default_config_path))?;
+    let limits = query_limits(&cli, &config)?;
+    let keymap = Keymap::from_config(&config.keys).wrap_err("Invalid key bindings")?;
+    let theme = theme(&cli, &config)?;
 
-    let file_path = cli.file_path.ok_or_else(|| {
-        miette!("No file path provided.\nUsage: mq_tui <FILE>\nFor more information, try '--help'")
-    })?;
+    let documents = if cli.file_paths.is_empty() {
+        vec![Document::with_file(
+            read_stdin()?,
+            STDIN_FILENAME.to_string(),
+        )]
+    } else {
+        load_documents(&cli.file_paths)?
+    };
 
-    // Read from file
-    let content = fs::read_to_string(&file_path).into_diagnostic()?;
-    let filename = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("file.md")
-        .to_string();
+    if documents.is_empty() {
+        return Err(miette!("No Markdown files found."));
+    }
 
     // Create and run the app
-    let mut app = App::with_file(content, filename);
+    let mut app = App::with_documents(documents);
+    app.set_query_limits(limits);
+    app.set_keymap(keymap);
+    app.set_theme(theme);
+    app.set_user_themes(config.themes);
+    app.set_clipboard_mode(config.clipboard);
+    app.set_watch(cli.watch);
+    if !cli.no_history {
+        app.load_history();
+    }
+    if cli.print_on_exit {
+        app.set_print_on_exit(Some(PrintTarget::Results));
+    } else if cli.print_query {
+        app.set_print_on_exit(Some(PrintTarget::Query));
+    }
     app.run()?;
 
+    // Terminal is restored at this point, so stdout can be piped
+    if let Some(output) = app.exit_output() {
+        if output.ends_with('\n') {
+            print!("{}", output);
+        } else {
+            println!("{}", output);
+        }
+    }
+
     Ok(())
 }
+
+/// Query limits from the command line, falling back to the config file and defaults.
+fn query_limits(cli: &Cli, config: &Config) -> miette::Result<QueryLimits> {
+    let defaults = QueryLimits::default();
+
+    let timeout = match cli.timeout.or(config.query_timeout) {
+        Some(secs) => Duration::try_from_secs_f64(secs)
+            .ok()
+            .filter(|timeout| !timeout.is_zero())
+            .ok_or_else(|| miette!("Invalid query timeout: {}", secs))?,
+        None => defaults.timeout,
+    };
+
+    Ok(QueryLimits {
+        timeout,
+        max_results: cli
+            .max_results
+            .or(config.max_results)
+            .unwrap_or(defaults.max_results),
+    })
+}
+
+/// Theme from the command line or the config file. Colors are turned off
+/// when `NO_COLOR` is set to a non-empty value (see https://no-color.org).
+fn theme(cli: &Cli, config: &Config) -> miette::Result<Theme> {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if cli.no_color || no_color {
+        return Ok(Theme::monochrome());
+    }
+
+    let name = cli
+        .theme
+        .as_deref()
+        .or(config.theme.as_deref())
+        .unwrap_or("dark");
+    Theme::load(name, &config.themes).wrap_err("Invalid theme")
+}
+
+/// Load every document named on the command line, expanding directories.
+fn load_documents(file_paths: &[PathBuf]) -> miette::Result<Vec<Document>> {
+    // Stdin can only be read once
+    if file_paths
+        .iter()
+        .filter(|file_path| file_path.as_os_str() == "-")
+        .count()
+        > 1
+    {
+        return Err(miette!("- (stdin) can only be given once"));
+    }
+
+    let mut documents = Vec::new();
+
+    for file_path in file_paths {
+        if file_path.as_os_str() == "-" {
+            documents.push(Document::with_file(
+                read_stdin()?,
+                STDIN_FILENAME.to_string(),
+            ));
+        } else if file_path.is_dir() {
+            for path in collect_markdown_files(file_path)? {
+                documents.push(Document::from_path(&path, path.display().to_string())?);
+            }
+        } else {
+            // Read from file
+            let filename = file_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file.md")
+                .to_string();
+            documents.push(Document::from_path(file_path, filename)?);
+        }
+    }
+
+    Ok(documents)
+}
+
+/// Read the whole Markdown document from stdin.
+///
+/// Keyboard input is read from the controlling terminal (`/dev/tty`), so the
+/// TUI keeps working while stdin is a pipe.
+fn read_stdin() -> miette::Result<String> {
+    let mut stdin = io::stdin();
+
+    if stdin.is_terminal() {
+        return Err(miette!(
+            "No file path provided and stdin is a terminal.\nUsage: mq_tui <FILE> or <command> | mq_tui\nFor more information, try '--help'"
+        ));
+    }
+
+    let mut content = String::new();
+    stdin.read_to_string(&mut content).into_diagnostic()?;
+    Ok(content)
+}
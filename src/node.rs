@@ -0,0 +1,36 @@
+use mq_markdown::Node;
+
+/// Child nodes of a Markdown node, as walked by the tree view and completion
+pub fn node_children(node: &Node) -> &[Node] {
+    match node {
+        Node::Heading(h) => &h.values,
+        Node::List(l) => &l.values,
+        Node::Blockquote(b) => &b.values,
+        Node::Strong(s) => &s.values,
+        Node::Emphasis(e) => &e.values,
+        Node::Link(l) => &l.values,
+        Node::Delete(d) => &d.values,
+        Node::Fragment(f) => &f.values,
+        Node::Footnote(f) => &f.values,
+        Node::TableRow(r) => &r.values,
+        Node::TableCell(c) => &c.values,
+        Node::MdxJsxFlowElement(e) => &e.children,
+        Node::MdxJsxTextElement(e) => &e.children,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::Markdown;
+
+    #[test]
+    fn test_node_children() {
+        let markdown =
+            Markdown::from_markdown_str("# Title *text*\n\n```rust\nfn f() {}\n```\n").unwrap();
+
+        assert_eq!(node_children(&markdown.nodes[0]).len(), 2);
+        assert!(node_children(&markdown.nodes[1]).is_empty());
+    }
+}
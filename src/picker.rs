@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use crate::{command, fuzzy::fuzzy_score};
+
+/// File picker popup for opening another Markdown file (normal mode only)
+#[derive(Debug, Clone, Default)]
+pub struct FilePicker {
+    /// Text typed into the picker, a fuzzy pattern or a path
+    pub input: String,
+    /// Index of the selected match
+    pub selected: usize,
+    /// Markdown files offered by the picker
+    files: Vec<PathBuf>,
+}
+
+impl FilePicker {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            ..Self::default()
+        }
+    }
+
+    /// Files matching the input, best match first
+    pub fn matches(&self) -> Vec<&Path> {
+        let mut matches = self
+            .files
+            .iter()
+            .filter_map(|file| {
+                fuzzy_score(&self.input, &file.to_string_lossy()).map(|score| (score, file))
+            })
+            .collect::<Vec<_>>();
+
+        if !self.input.is_empty() {
+            // Stable sort keeps the directory order among equal scores
+            matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+        }
+
+        matches
+            .into_iter()
+            .map(|(_, file)| file.as_path())
+            .collect()
+    }
+
+    /// The file to open: the selected match, or the input taken as a path
+    /// when nothing matches
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.matches()
+            .get(self.selected)
+            .map(|path| path.to_path_buf())
+            .or_else(|| (!self.input.is_empty()).then(|| PathBuf::from(&self.input)))
+    }
+
+    pub fn move_down(&mut self, match_count: usize) {
+        if self.selected + 1 < match_count {
+            self.selected += 1;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+/// Prompt for the file the results are written to (normal mode only)
+#[derive(Debug, Clone, Default)]
+pub struct SavePrompt {
+    /// Path typed into the prompt
+    pub input: String,
+    /// The typed path exists and the user is asked whether to overwrite it
+    pub confirm_overwrite: bool,
+}
+
+impl SavePrompt {
+    /// Complete the typed path as far as all matching paths agree
+    pub fn complete(&mut self) {
+        let candidates = command::complete_path(&self.input);
+        if let Some(first) = candidates.first() {
+            self.input = candidates
+                .iter()
+                .fold(first.as_str(), |prefix, candidate| {
+                    common_prefix(prefix, candidate)
+                })
+                .to_string();
+        }
+    }
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+    &a[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_picker_matches() {
+        let mut picker = FilePicker::new(vec![
+            PathBuf::from("README.md"),
+            PathBuf::from("docs/guide.md"),
+            PathBuf::from("docs/reference.md"),
+        ]);
+        assert_eq!(picker.matches().len(), 3);
+        assert_eq!(picker.selected_path(), Some(PathBuf::from("README.md")));
+
+        picker.input = "guide".to_string();
+        assert_eq!(picker.matches(), vec![Path::new("docs/guide.md")]);
+        assert_eq!(picker.selected_path(), Some(PathBuf::from("docs/guide.md")));
+
+        // Paths that aren't listed can be typed in full
+        picker.input = "/tmp/notes.md".to_string();
+        assert!(picker.matches().is_empty());
+        assert_eq!(picker.selected_path(), Some(PathBuf::from("/tmp/notes.md")));
+    }
+
+    #[test]
+    fn test_file_picker_selection() {
+        let mut picker = FilePicker::new(vec![PathBuf::from("a.md"), PathBuf::from("b.md")]);
+        picker.move_down(2);
+        picker.move_down(2);
+        assert_eq!(picker.selected_path(), Some(PathBuf::from("b.md")));
+        picker.move_up();
+        picker.move_up();
+        assert_eq!(picker.selected_path(), Some(PathBuf::from("a.md")));
+
+        let picker = FilePicker::default();
+        assert!(picker.selected_path().is_none());
+    }
+
+    #[test]
+    fn test_save_prompt_completes_common_prefix() {
+        let dir = std::env::temp_dir().join(format!("mq-tui-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("results-1.md"), "").unwrap();
+        std::fs::write(dir.join("results-2.md"), "").unwrap();
+        std::fs::write(dir.join("notes.md"), "").unwrap();
+
+        let mut prompt = SavePrompt {
+            input: format!("{}/r", dir.display()),
+            ..SavePrompt::default()
+        };
+        prompt.complete();
+        assert_eq!(prompt.input, format!("{}/results-", dir.display()));
+
+        prompt.input = format!("{}/n", dir.display());
+        prompt.complete();
+        assert_eq!(prompt.input, format!("{}/notes.md", dir.display()));
+
+        // Nothing matches
+        prompt.input = format!("{}/x", dir.display());
+        prompt.complete();
+        assert_eq!(prompt.input, format!("{}/x", dir.display()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix("résumé-1", "résumé-2"), "résumé-");
+        assert_eq!(common_prefix("abc", "xyz"), "");
+    }
+}
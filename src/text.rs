@@ -0,0 +1,142 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Byte offset of the grapheme cluster boundary before `pos`
+pub fn prev_grapheme_boundary(s: &str, pos: usize) -> usize {
+    s[..pos]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// Byte offset of the grapheme cluster boundary after `pos`
+pub fn next_grapheme_boundary(s: &str, pos: usize) -> usize {
+    s[pos..]
+        .graphemes(true)
+        .next()
+        .map_or(s.len(), |g| pos + g.len())
+}
+
+/// Byte offset of the start of the word before `pos` (Alt+B).
+///
+/// Words are runs of alphanumeric characters and underscores.
+pub fn prev_word_start(s: &str, pos: usize) -> usize {
+    let before = &s[..pos];
+    let end = before.trim_end_matches(|c| !is_word_char(c)).len();
+    before[..end].trim_end_matches(is_word_char).len()
+}
+
+/// Byte offset of the end of the word after `pos` (Alt+F)
+pub fn next_word_end(s: &str, pos: usize) -> usize {
+    let after = &s[pos..];
+    let rest = after.trim_start_matches(|c| !is_word_char(c));
+    let rest = rest.trim_start_matches(is_word_char);
+    s.len() - rest.len()
+}
+
+/// Byte offset of the start of the whitespace-delimited word before `pos` (Ctrl+W)
+pub fn prev_whitespace_word_start(s: &str, pos: usize) -> usize {
+    let before = s[..pos].trim_end_matches(char::is_whitespace);
+    before.trim_end_matches(|c: char| !c.is_whitespace()).len()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Largest char boundary that is not greater than `pos`
+pub fn floor_char_boundary(s: &str, pos: usize) -> usize {
+    let mut pos = pos.min(s.len());
+    while !s.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Number of terminal columns `s` occupies
+pub fn display_width(s: &str) -> usize {
+    s.width()
+}
+
+/// Byte offset of the grapheme boundary in `line` closest to display column
+/// `column` without going past it
+pub fn byte_offset_at_column(line: &str, column: usize) -> usize {
+    let mut width = 0;
+
+    for (i, grapheme) in line.grapheme_indices(true) {
+        width += grapheme.width();
+        if width > column {
+            return i;
+        }
+    }
+
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grapheme_boundaries() {
+        let s = "aé日👍🏽b";
+        let mut boundaries = vec![0];
+        let mut pos = 0;
+        while pos < s.len() {
+            pos = next_grapheme_boundary(s, pos);
+            boundaries.push(pos);
+        }
+        // The emoji with its skin tone modifier is a single cluster
+        assert_eq!(boundaries, vec![0, 1, 3, 6, 14, 15]);
+
+        let mut back = vec![s.len()];
+        let mut pos = s.len();
+        while pos > 0 {
+            pos = prev_grapheme_boundary(s, pos);
+            back.push(pos);
+        }
+        back.reverse();
+        assert_eq!(back, boundaries);
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let s = ".h | select(.depth == 2)";
+        assert_eq!(prev_word_start(s, s.len()), 22);
+        assert_eq!(prev_word_start(s, 22), 13);
+        assert_eq!(prev_word_start(s, 11), 5);
+        assert_eq!(prev_word_start(s, 1), 0);
+        assert_eq!(next_word_end(s, 0), 2);
+        assert_eq!(next_word_end(s, 2), 11);
+        assert_eq!(next_word_end(s, 18), 23);
+        assert_eq!(next_word_end(s, 23), s.len());
+        assert_eq!(prev_whitespace_word_start(s, s.len()), 22);
+        assert_eq!(prev_whitespace_word_start(s, 21), 19);
+        assert_eq!(prev_whitespace_word_start("日本 語", 10), 7);
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width(".h"), 2);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_byte_offset_at_column() {
+        let line = "a日本b";
+        assert_eq!(byte_offset_at_column(line, 0), 0);
+        assert_eq!(byte_offset_at_column(line, 1), 1);
+        // Column 2 is inside the first wide character
+        assert_eq!(byte_offset_at_column(line, 2), 1);
+        assert_eq!(byte_offset_at_column(line, 3), 4);
+        assert_eq!(byte_offset_at_column(line, 10), line.len());
+    }
+
+    #[test]
+    fn test_floor_char_boundary() {
+        assert_eq!(floor_char_boundary("日本", 2), 0);
+        assert_eq!(floor_char_boundary("日本", 3), 3);
+        assert_eq!(floor_char_boundary("日本", 10), 6);
+    }
+}
@@ -0,0 +1,444 @@
+use miette::miette;
+use ratatui::style::{Color, Modifier, Style};
+use std::{collections::HashMap, sync::LazyLock};
+use syntect::highlighting::{self, ThemeSet};
+
+/// Names of the built-in themes
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light", "monochrome"];
+
+/// Syntax themes bundled into the binary, which the `syntax` key picks from
+static SYNTAX_THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Declare the theme struct. Each field is a style that can be overridden
+/// in the config file by its name.
+macro_rules! theme {
+    ($($(#[$doc:meta])* $field:ident,)*) => {
+        /// Styles of every part of the UI
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Theme {
+            $($(#[$doc])* pub $field: Style,)*
+            /// Bundled syntax theme used for code blocks, `None` to draw them
+            /// in the `code` style
+            pub syntax: Option<String>,
+        }
+
+        impl Theme {
+            fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+theme! {
+    /// Hints, descriptions, line numbers and the status line
+    dim,
+    /// Query text, key names and other highlighted text
+    accent,
+    /// File names and section titles
+    title,
+    /// Markdown headers in the results
+    header,
+    /// The selected item of a list
+    selection,
+    /// Background of popups
+    popup,
+    /// The error popup
+    error,
+    heading,
+    list,
+    code,
+    link,
+    image,
+    math,
+    blockquote,
+    horizontal_rule,
+    /// Any other node in the tree view
+    node,
+    keyword,
+    number,
+    selector,
+    function,
+    string,
+    operator,
+    pipe,
+    comment,
+    punctuation,
+    ident,
+    /// The bracket matching the one under the cursor
+    matching_bracket,
+    /// The part of the query the parser reported an error for
+    query_error,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            dim: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::Yellow),
+            title: Style::default().fg(Color::Green),
+            header: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default().fg(Color::Black).bg(Color::White),
+            popup: Style::default().bg(Color::Black),
+            error: Style::default().fg(Color::White).bg(Color::Red),
+            heading: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            list: Style::default().fg(Color::Green),
+            code: Style::default().fg(Color::Cyan),
+            link: Style::default().fg(Color::Magenta),
+            image: Style::default().fg(Color::Yellow),
+            math: Style::default().fg(Color::Red),
+            blockquote: Style::default().fg(Color::LightBlue),
+            horizontal_rule: Style::default().fg(Color::DarkGray),
+            node: Style::default().fg(Color::Gray),
+            keyword: Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            number: Style::default().fg(Color::LightRed),
+            selector: Style::default().fg(Color::Cyan),
+            function: Style::default().fg(Color::LightBlue),
+            string: Style::default().fg(Color::Green),
+            operator: Style::default().fg(Color::LightMagenta),
+            pipe: Style::default()
+                .fg(Color::LightMagenta)
+                .add_modifier(Modifier::BOLD),
+            comment: Style::default().fg(Color::DarkGray),
+            punctuation: Style::default().fg(Color::White),
+            ident: Style::default().fg(Color::Yellow),
+            matching_bracket: Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            query_error: Style::default()
+                .add_modifier(Modifier::UNDERLINED)
+                .underline_color(Color::Red),
+            syntax: Some("base16-ocean.dark".to_string()),
+        }
+    }
+
+    /// Colors readable on a light terminal background
+    pub fn light() -> Self {
+        Self {
+            dim: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::Blue),
+            title: Style::default().fg(Color::Green),
+            header: Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default().fg(Color::White).bg(Color::Blue),
+            popup: Style::default().bg(Color::Reset),
+            error: Style::default().fg(Color::White).bg(Color::Red),
+            heading: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            list: Style::default().fg(Color::Green),
+            code: Style::default().fg(Color::Magenta),
+            link: Style::default().fg(Color::Blue),
+            image: Style::default().fg(Color::Red),
+            math: Style::default().fg(Color::Red),
+            blockquote: Style::default().fg(Color::Cyan),
+            horizontal_rule: Style::default().fg(Color::DarkGray),
+            node: Style::default().fg(Color::Black),
+            keyword: Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            number: Style::default().fg(Color::Red),
+            selector: Style::default().fg(Color::Blue),
+            function: Style::default().fg(Color::Cyan),
+            string: Style::default().fg(Color::Green),
+            operator: Style::default().fg(Color::Magenta),
+            pipe: Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            comment: Style::default().fg(Color::DarkGray),
+            punctuation: Style::default().fg(Color::Black),
+            ident: Style::default().fg(Color::Black),
+            matching_bracket: Style::default()
+                .bg(Color::Gray)
+                .add_modifier(Modifier::BOLD),
+            query_error: Style::default()
+                .add_modifier(Modifier::UNDERLINED)
+                .underline_color(Color::Red),
+            syntax: Some("InspiredGitHub".to_string()),
+        }
+    }
+
+    /// No colors at all, only text attributes. Used when `NO_COLOR` is set.
+    pub fn monochrome() -> Self {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let reversed = Style::default().add_modifier(Modifier::REVERSED);
+
+        Self {
+            dim: Style::default().add_modifier(Modifier::DIM),
+            accent: Style::default(),
+            title: bold,
+            header: bold,
+            selection: reversed,
+            popup: Style::default(),
+            error: reversed,
+            heading: bold,
+            list: Style::default(),
+            code: Style::default(),
+            link: Style::default().add_modifier(Modifier::UNDERLINED),
+            image: Style::default(),
+            math: Style::default(),
+            blockquote: Style::default().add_modifier(Modifier::ITALIC),
+            horizontal_rule: Style::default().add_modifier(Modifier::DIM),
+            node: Style::default(),
+            keyword: bold,
+            number: Style::default(),
+            selector: Style::default(),
+            function: Style::default(),
+            string: Style::default(),
+            operator: Style::default(),
+            pipe: bold,
+            comment: Style::default().add_modifier(Modifier::DIM),
+            punctuation: Style::default(),
+            ident: Style::default(),
+            matching_bracket: reversed,
+            query_error: Style::default().add_modifier(Modifier::UNDERLINED),
+            syntax: None,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// Load the theme called `name`, either built in or defined in the
+    /// config file's `[themes.<name>]` table.
+    ///
+    /// A user-defined theme starts from the built-in theme named by its
+    /// `base` key (the theme of the same name, or `dark`) and overrides
+    /// single styles, e.g. `selection = "black on yellow"`. `syntax` picks
+    /// the bundled syntax theme for code blocks, or `none`.
+    pub fn load(
+        name: &str,
+        themes: &HashMap<String, HashMap<String, String>>,
+    ) -> miette::Result<Self> {
+        let Some(styles) = themes.get(name) else {
+            return Self::builtin(name).ok_or_else(|| {
+                let mut names = BUILTIN_THEMES
+                    .iter()
+                    .copied()
+                    .chain(themes.keys().map(String::as_str))
+                    .collect::<Vec<_>>();
+                names.sort();
+                names.dedup();
+                miette!("Unknown theme: {} (available: {})", name, names.join(", "))
+            });
+        };
+
+        let base = match styles.get("base") {
+            Some(base) => base.as_str(),
+            None if BUILTIN_THEMES.contains(&name) => name,
+            None => "dark",
+        };
+        let mut theme = Self::builtin(base)
+            .ok_or_else(|| miette!("[themes.{}] Unknown base theme: {}", name, base))?;
+
+        match styles.get("syntax").map(String::as_str) {
+            Some("none") => theme.syntax = None,
+            Some(syntax_theme) if SYNTAX_THEMES.themes.contains_key(syntax_theme) => {
+                theme.syntax = Some(syntax_theme.to_string());
+            }
+            Some(syntax_theme) => {
+                return Err(miette!(
+                    "[themes.{}] Unknown syntax theme: {}",
+                    name,
+                    syntax_theme
+                ));
+            }
+            None => {}
+        }
+
+        for (key, value) in styles
+            .iter()
+            .filter(|(key, _)| *key != "base" && *key != "syntax")
+        {
+            let style = parse_style(value).map_err(|err| miette!("[themes.{}] {}", name, err))?;
+            *theme
+                .style_mut(key)
+                .ok_or_else(|| miette!("[themes.{}] Unknown style: {}", name, key))? = style;
+        }
+
+        Ok(theme)
+    }
+
+    /// The bundled syntax theme for code blocks, `None` if highlighting is off
+    pub fn syntax_theme(&self) -> Option<&'static highlighting::Theme> {
+        self.syntax
+            .as_deref()
+            .and_then(|name| SYNTAX_THEMES.themes.get(name))
+    }
+}
+
+/// Parse a style such as `yellow bold`, `black on white` or `#ff8800 italic`.
+///
+/// Colors are ANSI names (`red`, `light-blue`, `dark-gray`), indexes
+/// (`208`) or hex codes. `on` introduces the background color.
+pub fn parse_style(value: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    let mut words = value.split_whitespace();
+
+    while let Some(word) = words.next() {
+        if word == "on" {
+            let color = words
+                .next()
+                .ok_or_else(|| format!("Missing background color: {}", value))?;
+            style = style.bg(parse_color(color)?);
+        } else if let Some(modifier) = parse_modifier(word) {
+            style = style.add_modifier(modifier);
+        } else {
+            style = style.fg(parse_color(word)?);
+        }
+    }
+
+    Ok(style)
+}
+
+fn parse_color(color: &str) -> Result<Color, String> {
+    color
+        .parse::<Color>()
+        .map_err(|_| format!("Unknown color: {}", color))
+}
+
+fn parse_modifier(name: &str) -> Option<Modifier> {
+    match name {
+        "bold" => Some(Modifier::BOLD),
+        "dim" => Some(Modifier::DIM),
+        "italic" => Some(Modifier::ITALIC),
+        "underlined" => Some(Modifier::UNDERLINED),
+        "reversed" => Some(Modifier::REVERSED),
+        "crossed-out" => Some(Modifier::CROSSED_OUT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn themes(name: &str, styles: &[(&str, &str)]) -> HashMap<String, HashMap<String, String>> {
+        HashMap::from([(
+            name.to_string(),
+            styles
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )])
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            parse_style("black on white").unwrap(),
+            Style::default().fg(Color::Black).bg(Color::White)
+        );
+        assert_eq!(
+            parse_style("light-blue bold underlined").unwrap(),
+            Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+        );
+        assert_eq!(
+            parse_style("#ff8800 on 236").unwrap(),
+            Style::default()
+                .fg(Color::Rgb(0xff, 0x88, 0x00))
+                .bg(Color::Indexed(236))
+        );
+        assert_eq!(parse_style("").unwrap(), Style::default());
+        assert!(parse_style("blurple").is_err());
+        assert!(parse_style("red on").is_err());
+    }
+
+    #[test]
+    fn test_load_builtin_themes() {
+        let no_themes = HashMap::new();
+        for name in BUILTIN_THEMES {
+            assert!(Theme::load(name, &no_themes).is_ok());
+        }
+        assert_eq!(Theme::load("dark", &no_themes).unwrap(), Theme::default());
+        assert!(Theme::load("solarized", &no_themes).is_err());
+    }
+
+    #[test]
+    fn test_load_user_theme() {
+        let themes = themes(
+            "mine",
+            &[("base", "light"), ("selection", "black on yellow")],
+        );
+        let theme = Theme::load("mine", &themes).unwrap();
+        assert_eq!(
+            theme.selection,
+            Style::default().fg(Color::Black).bg(Color::Yellow)
+        );
+        assert_eq!(theme.header, Theme::light().header);
+
+        // Overriding a built-in theme keeps its other styles
+        let themes = self::themes("monochrome", &[("header", "underlined")]);
+        let theme = Theme::load("monochrome", &themes).unwrap();
+        assert_eq!(
+            theme.header,
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        );
+        assert_eq!(theme.selection, Theme::monochrome().selection);
+    }
+
+    #[test]
+    fn test_load_syntax_theme() {
+        let theme =
+            Theme::load("mine", &themes("mine", &[("syntax", "Solarized (dark)")])).unwrap();
+        assert_eq!(theme.syntax.as_deref(), Some("Solarized (dark)"));
+
+        let theme = Theme::load("mine", &themes("mine", &[("syntax", "none")])).unwrap();
+        assert_eq!(theme.syntax, None);
+        assert!(theme.syntax_theme().is_none());
+
+        // The syntax themes of the built-in themes are bundled
+        assert!(Theme::dark().syntax_theme().is_some());
+        assert!(Theme::light().syntax_theme().is_some());
+    }
+
+    #[test]
+    fn test_load_invalid_user_theme() {
+        assert!(Theme::load("mine", &themes("mine", &[("base", "solarized")])).is_err());
+        assert!(Theme::load("mine", &themes("mine", &[("syntax", "solarized")])).is_err());
+        assert!(Theme::load("mine", &themes("mine", &[("colour", "red")])).is_err());
+        assert!(Theme::load("mine", &themes("mine", &[("header", "blurple")])).is_err());
+    }
+
+    #[test]
+    fn test_monochrome_has_no_colors() {
+        let theme = Theme::monochrome();
+        let styles = [
+            theme.dim,
+            theme.accent,
+            theme.selection,
+            theme.error,
+            theme.heading,
+            theme.keyword,
+            theme.matching_bracket,
+            theme.query_error,
+        ];
+        for style in styles {
+            assert!(style.fg.is_none() && style.bg.is_none());
+        }
+    }
+}
@@ -1,29 +1,44 @@
+pub mod highlight;
+pub mod inspector;
+mod markdown;
+pub mod syntax;
+pub mod table;
 pub mod treeview;
 
 use ratatui::{
     Frame,
-    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
-    style::{Color, Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Position, Rect},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{
-        Block, BorderType, Borders, Clear, List, ListItem, ListState, Padding, Paragraph, Wrap,
-    },
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
 
-use crate::app::{App, Mode};
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::ops::Range;
+
+use crate::{
+    action::{Action, InspectAction, QueryAction, TreeAction},
+    app::{App, Mode},
+    command,
+    keymap::{Bindings, KeyChord},
+    text,
+    theme::Theme,
+};
 
 pub fn draw_ui(frame: &mut Frame, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3), // Query input
-            Constraint::Min(0),    // Results area
-            Constraint::Length(1), // Status line
+            Constraint::Length(app.query_input_height()), // Query input
+            Constraint::Min(0),                           // Results area
+            Constraint::Length(1),                        // Status line
         ])
         .split(frame.area());
 
     if app.mode() == Mode::Query {
         draw_query_input(frame, app, chunks[0]);
+    } else if app.mode() == Mode::Command {
+        draw_command_line(frame, app, chunks[0]);
     } else {
         draw_title_bar(frame, app, chunks[0]);
     }
@@ -31,10 +46,26 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
     match app.mode() {
         Mode::TreeView => {
             if let Some(tree_view) = app.tree_view() {
-                tree_view.render(frame, chunks[1]);
+                tree_view.render(frame, chunks[1], app.theme());
             }
         }
         _ => {
+            let mut content_area = chunks[1];
+
+            // Show file list if enabled
+            if app.show_file_list() {
+                let file_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Percentage(20), // File list
+                        Constraint::Percentage(80), // Everything else
+                    ])
+                    .split(content_area);
+
+                draw_file_list(frame, app, file_chunks[0]);
+                content_area = file_chunks[1];
+            }
+
             // Show sidebar if enabled
             if app.show_tree_sidebar() && app.sidebar_tree_view().is_some() {
                 let main_chunks = Layout::default()
@@ -43,11 +74,11 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
                         Constraint::Percentage(20), // Sidebar
                         Constraint::Percentage(80), // Main content
                     ])
-                    .split(chunks[1]);
+                    .split(content_area);
 
                 // Draw sidebar
                 if let Some(sidebar) = app.sidebar_tree_view() {
-                    sidebar.render_with_title(frame, main_chunks[0], "Headers");
+                    sidebar.render_with_title(frame, main_chunks[0], "Headers", app.theme());
                 }
 
                 // Draw main content area (results and/or detail)
@@ -74,12 +105,12 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
                             Constraint::Percentage(40), // Results list
                             Constraint::Percentage(60), // Detail view
                         ])
-                        .split(chunks[1]);
+                        .split(content_area);
 
                     draw_results_list(frame, app, detail_chunks[0]);
                     draw_detail_view(frame, app, detail_chunks[1]);
                 } else {
-                    draw_results_list(frame, app, chunks[1]);
+                    draw_results_list(frame, app, content_area);
                 }
             }
         }
@@ -87,38 +118,312 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
 
     draw_status_line(frame, app, chunks[2]);
 
+    if app.mode() == Mode::Query && app.completion().is_some() {
+        draw_completion_popup(frame, app, chunks[0]);
+    }
+
+    if app.mode() == Mode::Query && app.history_search().is_some() {
+        draw_history_search_popup(frame, app);
+    }
+
+    if app.mode() == Mode::Command && !app.command_line().candidates.is_empty() {
+        draw_command_candidates_popup(frame, app, chunks[0]);
+    }
+
+    if app.mode() == Mode::Normal && app.file_picker().is_some() {
+        draw_file_picker_popup(frame, app);
+    }
+
+    if app.mode() == Mode::Normal && app.save_prompt().is_some() {
+        draw_save_prompt_popup(frame, app);
+    }
+
     if let Some(error) = app.error_msg() {
-        draw_error_popup(frame, error);
+        draw_error_popup(frame, error, app.theme());
     }
 
     if app.mode() == Mode::Help {
-        draw_help_screen(frame);
+        draw_help_screen(frame, app);
     }
 }
 
+/// Width of the line number gutter in the multi-line query editor
+const QUERY_GUTTER_WIDTH: u16 = 6;
+
 fn draw_query_input(frame: &mut Frame, app: &App, area: Rect) {
+    let title = if app.multiline() {
+        let query_keys = &app.keymap().query;
+        let resize_keys = [QueryAction::ShrinkEditor, QueryAction::GrowEditor]
+            .into_iter()
+            .filter_map(|action| first_key(query_keys, action))
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        let hints = first_key(query_keys, QueryAction::Execute)
+            .map(|key| format!("{} run", key))
+            .into_iter()
+            .chain((!resize_keys.is_empty()).then(|| format!("{} resize", resize_keys)))
+            .collect::<Vec<_>>();
+
+        if hints.is_empty() {
+            "Query (multi-line)".to_string()
+        } else {
+            format!("Query (multi-line: {})", hints.join(", "))
+        }
+    } else {
+        "Query".to_string()
+    };
     let query_block = Block::default()
-        .title("Query")
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default());
+    let (gutter_area, text_area) = query_text_areas(app, query_block.inner(area));
+    let (scroll_y, scroll_x) = query_scroll(app, text_area);
+
+    frame.render_widget(query_block, area);
+
+    // Line numbers stay fixed while the query scrolls horizontally
+    if let Some(gutter_area) = gutter_area {
+        let line_count = app.query().split('\n').count();
+        let numbers = (1..=line_count)
+            .map(|i| Line::from(Span::styled(format!("{:>3} │ ", i), app.theme().dim)))
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(numbers).scroll((scroll_y, 0)), gutter_area);
+    }
+
+    let query_lines = highlight::highlight_query(
+        app.query(),
+        app.cursor_position(),
+        app.error_span(),
+        app.theme(),
+    );
+    let query_text = Paragraph::new(query_lines)
+        .style(app.theme().accent)
+        .scroll((scroll_y, scroll_x));
+
+    frame.render_widget(query_text, text_area);
+
+    let (cursor_line, cursor_col) = app.cursor_line_col();
+    frame.set_cursor_position(Position::new(
+        text_area.x + (cursor_col as u16).saturating_sub(scroll_x),
+        text_area.y + (cursor_line as u16).saturating_sub(scroll_y),
+    ));
+}
+
+fn draw_command_line(frame: &mut Frame, app: &App, area: Rect) {
+    let command_block = Block::default()
+        .title("Command (Tab to complete)")
         .borders(Borders::ALL)
         .style(Style::default());
+    let inner = command_block.inner(area);
 
-    let query_text = Paragraph::new(app.query())
-        .style(Style::default().fg(Color::Yellow))
-        .block(query_block);
+    frame.render_widget(command_block, area);
 
-    frame.render_widget(query_text, area);
+    let input = &app.command_line().input;
+    // Keep the end of long commands visible
+    let scroll_x = (text::display_width(input) + 2).saturating_sub(inner.width as usize) as u16;
+    let command_text = Paragraph::new(Line::from(vec![
+        Span::styled(":", app.theme().dim),
+        Span::styled(input.as_str(), app.theme().accent),
+    ]))
+    .scroll((0, scroll_x));
+
+    frame.render_widget(command_text, inner);
 
-    let cursor_x = app.cursor_position() as u16 + 1; // +1 for block border
     frame.set_cursor_position(Position::new(
-        area.x + cursor_x,
-        area.y + 1, // +1 for block border
+        inner.x + (text::display_width(input) as u16 + 1).saturating_sub(scroll_x),
+        inner.y,
     ));
 }
 
+/// List the completions of the command line below it
+fn draw_command_candidates_popup(frame: &mut Frame, app: &App, command_area: Rect) {
+    let command_line = app.command_line();
+    let theme = app.theme();
+    let frame_size = frame.area();
+
+    // Command names are listed with their usage or description
+    let lines = command_line
+        .candidates
+        .iter()
+        .map(|candidate| match command::describe(candidate) {
+            Some(description) => (candidate.as_str(), format!("  {}", description)),
+            None => (candidate.as_str(), String::new()),
+        })
+        .collect::<Vec<_>>();
+
+    let x = command_area.x + 1;
+    let y = command_area.y + command_area.height;
+    let width = lines
+        .iter()
+        .map(|(candidate, description)| {
+            (text::display_width(candidate) + text::display_width(description)) as u16
+        })
+        .max()
+        .unwrap_or(0)
+        .saturating_add(2)
+        .max(20)
+        .min(frame_size.width.saturating_sub(x));
+    let height = (lines.len() as u16 + 2).min(frame_size.height.saturating_sub(y));
+    if height < 3 || width < 3 {
+        return;
+    }
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, (candidate, description))| {
+            let line = Line::from(vec![
+                Span::raw(candidate.to_string()),
+                Span::styled(description, theme.dim),
+            ]);
+            ListItem::new(line).style(if Some(i) == command_line.selected {
+                theme.selection
+            } else {
+                theme.accent
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(theme.popup),
+    );
+
+    let mut state = ListState::default();
+    state.select(command_line.selected);
+
+    frame.render_stateful_widget(list, popup_area, &mut state);
+}
+
+/// Split the inside of the query input into the line number gutter (only in
+/// multi-line mode) and the query text
+fn query_text_areas(app: &App, inner: Rect) -> (Option<Rect>, Rect) {
+    if app.multiline() && inner.width > QUERY_GUTTER_WIDTH {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(QUERY_GUTTER_WIDTH), Constraint::Min(0)])
+            .split(inner);
+        (Some(chunks[0]), chunks[1])
+    } else {
+        (None, inner)
+    }
+}
+
+/// Vertical and horizontal scroll offsets that keep the query cursor visible
+fn query_scroll(app: &App, text_area: Rect) -> (u16, u16) {
+    let (cursor_line, cursor_col) = app.cursor_line_col();
+    let visible_lines = (text_area.height as usize).max(1);
+    // Leave room for the cursor after the last character
+    let visible_cols = (text_area.width as usize).max(1);
+
+    (
+        (cursor_line + 1).saturating_sub(visible_lines) as u16,
+        (cursor_col + 1).saturating_sub(visible_cols) as u16,
+    )
+}
+
+/// Lines of the results list entries, kept between frames because syntax
+/// highlighting and table layout are too slow to redo on every redraw.
+///
+/// The app clears the cache whenever the results, the theme or the rendered
+/// toggle change; a different pane width rebuilds it as well.
+#[derive(Default)]
+pub struct ResultsCache {
+    /// Width the lines were laid out for, `None` until they're built
+    width: Option<usize>,
+    /// Result indices of each entry
+    groups: Vec<Range<usize>>,
+    entries: Vec<CachedEntry>,
+    /// Furthest the tables can scroll before the widest one's right edge
+    /// leaves the right side of the list
+    max_table_scroll: usize,
+    /// Unfocused inspector of the selected result shown in the detail view
+    detail: Option<(usize, inspector::Inspector)>,
+}
+
+enum CachedEntry {
+    /// Consecutive table nodes, whose grid highlights the selected node
+    Table(table::Grid),
+    Lines(Vec<Line<'static>>),
+}
+
+impl ResultsCache {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Furthest the tables can scroll at the width they were last drawn at
+    pub fn max_table_scroll(&self) -> usize {
+        self.max_table_scroll
+    }
+
+    /// Inspector of the result at `idx`, kept while the same result is selected
+    fn detail(&mut self, results: &[mq_markdown::Node], idx: usize) -> &inspector::Inspector {
+        if self
+            .detail
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != idx)
+        {
+            self.detail = None;
+        }
+        let (_, inspector) = self
+            .detail
+            .get_or_insert_with(|| (idx, inspector::Inspector::new(results[idx].clone())));
+        inspector
+    }
+
+    /// Build the entries of the app's results unless they're cached for `width`
+    fn update(&mut self, app: &App, width: usize) {
+        if self.width == Some(width) {
+            return;
+        }
+
+        let (results, theme, rendered) = (app.results(), app.theme(), app.rendered_view());
+        self.groups = table::group_tables(results, app.result_sources());
+        self.entries = self
+            .groups
+            .iter()
+            .map(|group| {
+                let node = &results[group.start];
+                if table::is_table_node(node) {
+                    CachedEntry::Table(table::Grid::new(&results[group.clone()], theme, rendered))
+                } else if rendered {
+                    CachedEntry::Lines(markdown::render_node(node, theme, width))
+                } else {
+                    CachedEntry::Lines(source_lines(node, theme))
+                }
+            })
+            .collect();
+        self.max_table_scroll = self
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                CachedEntry::Table(grid) => grid.width().saturating_sub(width),
+                CachedEntry::Lines(_) => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        self.width = Some(width);
+    }
+}
+
 fn draw_results_list(frame: &mut Frame, app: &App, area: Rect) {
     let results = app.results();
+    let theme = app.theme();
 
-    let results_block = Block::default().title("Results").borders(Borders::ALL);
+    let title = if app.rendered_view() {
+        "Results (rendered)"
+    } else {
+        "Results"
+    };
+    let results_block = Block::default().title(title).borders(Borders::ALL);
 
     if results.is_empty() {
         let text = if app.query().is_empty() {
@@ -127,33 +432,65 @@ fn draw_results_list(frame: &mut Frame, app: &App, area: Rect) {
             "No results found"
         };
 
-        let empty_text = Paragraph::new(text)
-            .style(Style::default().fg(Color::DarkGray))
-            .block(results_block);
+        let empty_text = Paragraph::new(text).style(theme.dim).block(results_block);
 
         frame.render_widget(empty_text, area);
         return;
     }
 
-    let items: Vec<ListItem> = mq_markdown::Markdown::new(results.to_vec())
-        .to_string()
-        .lines()
+    // Each result node is one entry, so the selection always covers the
+    // whole node however many lines it renders to. Consecutive table nodes
+    // share one entry, a grid highlighting the selected node's cells.
+    let separator_width = results_block.inner(area).width as usize;
+    let mut cache = app.results_cache().borrow_mut();
+    cache.update(app, separator_width);
+    let selected_entry = cache
+        .groups
+        .iter()
+        .position(|group| group.contains(&app.selected_idx()))
+        .unwrap_or_default();
+    let items: Vec<ListItem> = cache
+        .groups
+        .iter()
+        .zip(&cache.entries)
         .enumerate()
-        .map(|(i, value)| {
-            let content = if is_markdown_header(value) {
-                // Apply header highlighting
-                Line::from(Span::styled(
-                    value.to_string(),
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                ))
-            } else {
-                Line::from(value.to_string())
-            };
+        .map(|(entry, (group, cached))| {
+            let i = group.start;
+            let selected = entry == selected_entry;
+            let mut lines = Vec::new();
+            if app.all_files_mode() {
+                // Tagged with the file the result came from
+                lines.push(Line::from(Span::styled(
+                    format!("[{}]", app.result_source(i).unwrap_or_default()),
+                    theme.title,
+                )));
+            }
+            match cached {
+                CachedEntry::Table(grid) => {
+                    // Scroll wide tables no further than their right edge
+                    let scroll = app
+                        .table_scroll()
+                        .min(grid.width().saturating_sub(separator_width));
+                    lines.extend(
+                        grid.lines(selected.then(|| app.selected_idx() - i), theme)
+                            .into_iter()
+                            .map(|line| table::skip_columns(line, scroll)),
+                    );
+                }
+                CachedEntry::Lines(cached) => lines.extend(cached.iter().cloned()),
+            }
+            if lines.is_empty() {
+                lines.push(Line::from(""));
+            }
+            if group.end < results.len() {
+                // The separator isn't part of the selection
+                lines.push(
+                    Line::from("─".repeat(separator_width)).style(Style::reset().patch(theme.dim)),
+                );
+            }
 
-            ListItem::new(content).style(if i == app.selected_idx() {
-                Style::default().fg(Color::Black).bg(Color::White)
+            ListItem::new(lines).style(if selected && !table::is_table_node(&results[i]) {
+                theme.selection
             } else {
                 Style::default()
             })
@@ -165,55 +502,138 @@ fn draw_results_list(frame: &mut Frame, app: &App, area: Rect) {
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
     let mut state = ListState::default();
-    state.select(Some(app.selected_idx()));
+    state.select(Some(selected_entry));
 
     frame.render_stateful_widget(list, area, &mut state);
 }
 
+/// Lines of a result node as Markdown source, with the contents of fenced
+/// code blocks syntax highlighted
+fn source_lines(node: &mq_markdown::Node, theme: &Theme) -> Vec<Line<'static>> {
+    let source = mq_markdown::Markdown::new(vec![node.clone()]).to_string();
+
+    if let mq_markdown::Node::Code(code) = node
+        && code.fence
+        && let [open, .., close] = source.lines().collect::<Vec<_>>()[..]
+    {
+        let mut lines = vec![Line::from(open.to_string())];
+        lines.extend(syntax::highlight_code(
+            &code.value,
+            code.lang.as_deref(),
+            theme,
+        ));
+        lines.push(Line::from(close.to_string()));
+        return lines;
+    }
+
+    source
+        .lines()
+        .map(|value| result_line(value, theme))
+        .collect()
+}
+
+/// Render a single line of result Markdown
+fn result_line(value: &str, theme: &Theme) -> Line<'static> {
+    if is_markdown_header(value) {
+        // Apply header highlighting
+        Line::from(Span::styled(value.to_string(), theme.header))
+    } else {
+        Line::from(value.to_string())
+    }
+}
+
 /// Check if a line is a markdown header (starts with #)
 fn is_markdown_header(line: &str) -> bool {
     let trimmed = line.trim_start();
     trimmed.starts_with('#') && trimmed.chars().nth(1).is_some_and(|c| c == ' ' || c == '#')
 }
 
+/// Frames of the spinner shown while a query is running
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
 /// Draw the status line at the bottom
 fn draw_status_line(frame: &mut Frame, app: &App, area: Rect) {
     let exec_time = app.last_exec_time();
     let results_count = app.results().len();
+    let parse_time = match app.last_parse_time() {
+        Some(parse_time) => format!("{:.2}ms", parse_time.as_secs_f64() * 1000.0),
+        None => "cached".to_string(),
+    };
+
+    let hint = match (app.query_running_time(), app.status_message()) {
+        (Some(running), _) => {
+            let frame_idx = (running.as_millis() / 80) as usize % SPINNER_FRAMES.len();
+            format!(
+                "{} Running query {:.1}s (Esc to cancel)",
+                SPINNER_FRAMES[frame_idx],
+                running.as_secs_f64()
+            )
+        }
+        (None, Some(message)) => message.to_string(),
+        (None, None) => first_key(&app.keymap().normal, Action::Quit)
+            .map(|key| format!("Press {} to quit", key))
+            .unwrap_or_default(),
+    };
 
     let status = format!(
-        "{} results | Execution time: {:.2}ms | Press q to quit",
+        "{} results | Parse: {} | Eval: {:.2}ms | {}",
         results_count,
-        exec_time.as_secs_f64() * 1000.0
+        parse_time,
+        exec_time.as_secs_f64() * 1000.0,
+        hint
     );
 
-    let status_text = Paragraph::new(status).style(Style::default().fg(Color::DarkGray));
+    let status_text = Paragraph::new(status).style(app.theme().dim);
 
     frame.render_widget(status_text, area);
 }
 
 fn draw_title_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let title = app.filename().unwrap_or("None");
+    let documents = app.documents();
+    let title = if app.all_files_mode() {
+        format!("All files ({})", documents.len())
+    } else if documents.len() > 1 {
+        format!(
+            "{} ({}/{})",
+            app.filename().unwrap_or("None"),
+            app.active_document() + 1,
+            documents.len()
+        )
+    } else {
+        app.filename().unwrap_or("None").to_string()
+    };
     let title_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded);
 
-    let title_spans = vec![
-        Span::styled(title, Style::default().fg(Color::Green).bold()),
+    let theme = app.theme();
+    let mut title_spans = vec![
+        Span::styled(title, theme.title.add_modifier(Modifier::BOLD)),
         Span::raw(" | "),
         Span::styled(
             app.mode().to_string(),
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        ),
-        Span::raw(" | "),
-        Span::styled(
-            "Press 's' for sidebar, 't' for tree view, '?' for help",
-            Style::default().fg(Color::Gray),
+            theme.accent.add_modifier(Modifier::BOLD),
         ),
     ];
 
+    let hints = [
+        (Action::ToggleSidebar, "sidebar"),
+        (Action::TreeView, "tree view"),
+        (Action::Help, "help"),
+    ]
+    .into_iter()
+    .filter_map(|(action, label)| {
+        first_key(&app.keymap().normal, action).map(|key| format!("'{}' for {}", key, label))
+    })
+    .collect::<Vec<_>>();
+    if !hints.is_empty() {
+        title_spans.push(Span::raw(" | "));
+        title_spans.push(Span::styled(
+            format!("Press {}", hints.join(", ")),
+            theme.dim,
+        ));
+    }
+
     let title_text = Paragraph::new(Line::from(title_spans))
         .block(title_block)
         .alignment(Alignment::Center);
@@ -221,30 +641,53 @@ fn draw_title_bar(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(title_text, area);
 }
 
+fn draw_file_list(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .documents()
+        .iter()
+        .enumerate()
+        .map(|(i, document)| {
+            ListItem::new(document.name().to_string()).style(if i == app.active_document() {
+                app.theme().selection
+            } else {
+                app.theme().title
+            })
+        })
+        .collect();
+
+    let title = if app.all_files_mode() {
+        "Files (all)"
+    } else {
+        "Files"
+    };
+    let list = List::new(items).block(Block::default().title(title).borders(Borders::ALL));
+
+    let mut state = ListState::default();
+    state.select(Some(app.active_document()));
+
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
 fn draw_detail_view(frame: &mut Frame, app: &App, area: Rect) {
+    if app.mode() == Mode::Inspect
+        && let Some(inspector) = app.inspector()
+    {
+        inspector.render(frame, area, app.theme(), true);
+        return;
+    }
+
     let results = app.results();
     if results.is_empty() || app.selected_idx() >= results.len() {
         return;
     }
 
-    let selected_item = &results[app.selected_idx()];
-    let detail_block = Block::default()
-        .title("Detail View")
-        .borders(Borders::ALL)
-        .border_type(BorderType::Plain)
-        .padding(Padding::new(1, 1, 1, 1));
-
-    let detailed_content = format!("{:#?}", selected_item);
-
-    let detail_text = Paragraph::new(detailed_content)
-        .style(Style::default())
-        .block(detail_block)
-        .wrap(Wrap { trim: false });
-
-    frame.render_widget(detail_text, area);
+    app.results_cache()
+        .borrow_mut()
+        .detail(results, app.selected_idx())
+        .render(frame, area, app.theme(), false);
 }
 
-fn draw_help_screen(frame: &mut Frame) {
+fn draw_help_screen(frame: &mut Frame, app: &App) {
     let area = frame.area();
 
     let width = area.width.clamp(20, 60);
@@ -260,118 +703,127 @@ fn draw_help_screen(frame: &mut Frame) {
         .title("Keyboard Controls")
         .borders(Borders::ALL)
         .border_type(BorderType::Double)
-        .style(Style::default().bg(Color::Black));
-
-    let help_text = vec![
-        Line::from(vec![Span::styled(
-            "Navigation",
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::UNDERLINED),
-        )]),
-        Line::from(""),
-        Line::from(vec![
-            Span::styled("↑/k", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Move up"),
-        ]),
-        Line::from(vec![
-            Span::styled("↓/j", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Move down"),
-        ]),
-        Line::from(vec![
-            Span::styled("PgUp", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Page up"),
-        ]),
-        Line::from(vec![
-            Span::styled("PgDn", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Page down"),
-        ]),
-        Line::from(""),
-        Line::from(vec![Span::styled(
-            "Query Mode",
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::UNDERLINED),
-        )]),
-        Line::from(""),
-        Line::from(vec![
-            Span::styled(":", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Enter query mode"),
-        ]),
-        Line::from(vec![
-            Span::styled("Enter", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Execute query"),
-        ]),
-        Line::from(vec![
-            Span::styled("Esc", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Exit query mode"),
-        ]),
-        Line::from(vec![
-            Span::styled("↑/↓", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Navigate query history"),
-        ]),
-        Line::from(""),
-        Line::from(vec![Span::styled(
-            "Other Commands",
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::UNDERLINED),
-        )]),
-        Line::from(""),
-        Line::from(vec![
-            Span::styled("d", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Toggle detail view"),
-        ]),
-        Line::from(vec![
-            Span::styled("y", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Copy result to clipboard"),
-        ]),
-        Line::from(vec![
-            Span::styled("q/Esc", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Quit application"),
-        ]),
-        Line::from(vec![
-            Span::styled("?", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Show this help"),
-        ]),
-        Line::from(vec![
-            Span::styled("Ctrl+l", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Clear query"),
-        ]),
-        Line::from(""),
-        Line::from(vec![Span::styled(
-            "Tree View Mode",
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::UNDERLINED),
-        )]),
-        Line::from(""),
-        Line::from(vec![
-            Span::styled("t", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Toggle tree view"),
-        ]),
-        Line::from(vec![
-            Span::styled("s", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Toggle sidebar (headers)"),
-        ]),
-        Line::from(vec![
-            Span::styled("↑/k", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Move up in tree"),
-        ]),
-        Line::from(vec![
-            Span::styled("↓/j", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Move down in tree"),
-        ]),
-        Line::from(vec![
-            Span::styled("Enter/Space", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Expand/collapse node"),
-        ]),
-        Line::from(vec![
-            Span::styled("Esc", Style::default().fg(Color::Yellow)),
-            Span::raw(" - Exit tree view"),
-        ]),
+        .style(app.theme().popup);
+
+    let keymap = app.keymap();
+    let query_mode_actions = [
+        QueryAction::Submit,
+        QueryAction::Exit,
+        QueryAction::Up,
+        QueryAction::SearchHistory,
+        QueryAction::Complete,
     ];
 
+    let mut help_text = Vec::new();
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Navigation",
+        [
+            Action::Up,
+            Action::Down,
+            Action::PageUp,
+            Action::PageDown,
+            Action::ScrollLeft,
+            Action::ScrollRight,
+        ]
+        .map(|action| (keymap.normal.keys(action), action.description())),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Query Mode",
+        std::iter::once((
+            keymap.normal.keys(Action::EnterQuery),
+            Action::EnterQuery.description(),
+        ))
+        .chain(
+            query_mode_actions
+                .iter()
+                .map(|&action| (keymap.query.keys(action), action.description())),
+        ),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Other Commands",
+        [
+            Action::ToggleDetail,
+            Action::ToggleRendered,
+            Action::Copy,
+            Action::Quit,
+            Action::Help,
+            Action::ClearQuery,
+            Action::Accept,
+            Action::EnterCommand,
+            Action::ToggleSidebar,
+            Action::First,
+            Action::Last,
+        ]
+        .map(|action| (keymap.normal.keys(action), action.description()))
+        .into_iter()
+        // Cancelling a running query works in every mode and can't be rebound
+        .chain(std::iter::once((
+            vec![
+                KeyChord::new(KeyCode::Esc, KeyModifiers::NONE),
+                KeyChord::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            ],
+            "Cancel running query",
+        ))),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Files",
+        [
+            Action::NextFile,
+            Action::PreviousFile,
+            Action::ToggleFileList,
+            Action::ToggleAllFiles,
+            Action::Open,
+            Action::Reload,
+            Action::Write,
+        ]
+        .map(|action| (keymap.normal.keys(action), action.description())),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Tree View Mode",
+        std::iter::once((
+            keymap.normal.keys(Action::TreeView),
+            Action::TreeView.description(),
+        ))
+        .chain(
+            TreeAction::ALL
+                .iter()
+                .map(|&action| (keymap.tree_view.keys(action), action.description())),
+        ),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Inspect Mode",
+        std::iter::once((
+            keymap.normal.keys(Action::Inspect),
+            Action::Inspect.description(),
+        ))
+        .chain(
+            InspectAction::ALL
+                .iter()
+                .map(|&action| (keymap.inspect.keys(action), action.description())),
+        ),
+    );
+    help_section(
+        &mut help_text,
+        app.theme(),
+        "Query Editing",
+        QueryAction::ALL
+            .iter()
+            .filter(|action| !query_mode_actions.contains(action))
+            .map(|&action| (keymap.query.keys(action), action.description())),
+    );
+
     let help_paragraph = Paragraph::new(help_text)
         .block(help_block)
         .style(Style::default())
@@ -380,11 +832,270 @@ fn draw_help_screen(frame: &mut Frame) {
     frame.render_widget(help_paragraph, help_area);
 }
 
-fn draw_error_popup(frame: &mut Frame, error: &str) {
+/// Append a help section listing the keys bound to each entry. Entries
+/// without keys are left out.
+/// The first key bound to `action`, for hints that name a single key
+fn first_key<A: Copy + PartialEq>(bindings: &Bindings<A>, action: A) -> Option<KeyChord> {
+    bindings.keys(action).into_iter().next()
+}
+
+fn help_section<'a>(
+    lines: &mut Vec<Line<'a>>,
+    theme: &Theme,
+    title: &'a str,
+    entries: impl IntoIterator<Item = (Vec<KeyChord>, &'a str)>,
+) {
+    if !lines.is_empty() {
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(vec![Span::styled(
+        title,
+        theme.title.add_modifier(Modifier::UNDERLINED),
+    )]));
+    lines.push(Line::from(""));
+
+    for (keys, description) in entries {
+        if keys.is_empty() {
+            continue;
+        }
+
+        let keys = keys.iter().map(ToString::to_string).collect::<Vec<_>>();
+        lines.push(Line::from(vec![
+            Span::styled(keys.join("/"), theme.accent),
+            Span::raw(format!(" - {}", description)),
+        ]));
+    }
+}
+
+/// Draw the completion popup just below the query input, aligned with the completed word
+fn draw_completion_popup(frame: &mut Frame, app: &App, query_area: Rect) {
+    let Some(completion) = app.completion() else {
+        return;
+    };
+    let theme = app.theme();
+
     let frame_size = frame.area();
+    let max_height = frame_size.height.saturating_sub(query_area.bottom());
+    let height = (completion.candidates.len() as u16 + 2)
+        .min(12)
+        .min(max_height);
+    let width = frame_size.width.clamp(20, 70);
+    let line_start = app.query()[..completion.start]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let start_col = text::display_width(&app.query()[line_start..completion.start]) as u16;
+    let (_, text_area) = query_text_areas(app, query_area.inner(Margin::new(1, 1)));
+    let (_, scroll_x) = query_scroll(app, text_area);
+    let x = (text_area.x + start_col.saturating_sub(scroll_x))
+        .min(frame_size.width.saturating_sub(width));
+
+    if height < 3 {
+        return;
+    }
 
-    let width = frame_size.width.clamp(20, 60);
+    let popup_area = Rect::new(x, query_area.bottom(), width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = completion
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let line = Line::from(vec![
+                Span::styled(candidate.signature.clone(), theme.accent),
+                Span::raw("  "),
+                Span::styled(candidate.description.clone(), theme.dim),
+            ]);
+
+            ListItem::new(line).style(if i == completion.selected {
+                theme.selection
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(theme.popup),
+    );
+
+    let mut state = ListState::default();
+    state.select(Some(completion.selected));
+
+    frame.render_stateful_widget(list, popup_area, &mut state);
+}
+
+fn draw_history_search_popup(frame: &mut Frame, app: &App) {
+    let Some(search) = app.history_search() else {
+        return;
+    };
+    let theme = app.theme();
+
+    let frame_size = frame.area();
+
+    let width = frame_size.width.clamp(20, 80);
+    let height = frame_size.height.clamp(5, 15);
+    let x = (frame_size.width.saturating_sub(width)) / 2;
+    let y = (frame_size.height.saturating_sub(height)) / 2;
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let matches = app.history_search_matches();
+    let items: Vec<ListItem> = if matches.is_empty() {
+        vec![ListItem::new("No matching queries").style(theme.dim)]
+    } else {
+        matches
+            .iter()
+            .enumerate()
+            .map(|(i, query)| {
+                ListItem::new(query.replace('\n', " ")).style(if i == search.selected {
+                    theme.selection
+                } else {
+                    theme.accent
+                })
+            })
+            .collect()
+    };
+
+    let search_block = Block::default()
+        .title(format!("History search: {}", search.input))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(theme.popup);
+
+    let list = List::new(items).block(search_block);
+
+    let mut state = ListState::default();
+    state.select(Some(search.selected));
+
+    frame.render_stateful_widget(list, popup_area, &mut state);
+}
+
+fn draw_file_picker_popup(frame: &mut Frame, app: &App) {
+    let Some(picker) = app.file_picker() else {
+        return;
+    };
+    let theme = app.theme();
+
+    let frame_size = frame.area();
+
+    let width = frame_size.width.clamp(20, 80);
+    let height = frame_size.height.clamp(5, 15);
+    let x = (frame_size.width.saturating_sub(width)) / 2;
+    let y = (frame_size.height.saturating_sub(height)) / 2;
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let matches = picker.matches();
+    let items: Vec<ListItem> = if matches.is_empty() {
+        let hint = if picker.input.is_empty() {
+            "No Markdown files found"
+        } else {
+            "Press Enter to open the typed path"
+        };
+        vec![ListItem::new(hint).style(theme.dim)]
+    } else {
+        matches
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                ListItem::new(path.display().to_string()).style(if i == picker.selected {
+                    theme.selection
+                } else {
+                    theme.title
+                })
+            })
+            .collect()
+    };
+
+    let picker_block = Block::default()
+        .title(format!("Open file: {}", picker.input))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(theme.popup);
+
+    let list = List::new(items).block(picker_block);
+
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+
+    frame.render_stateful_widget(list, popup_area, &mut state);
+}
+
+fn draw_save_prompt_popup(frame: &mut Frame, app: &App) {
+    let Some(prompt) = app.save_prompt() else {
+        return;
+    };
+    let theme = app.theme();
+
+    let frame_size = frame.area();
+
+    let width = frame_size.width.clamp(20, 70);
     let height = 3;
+    let x = (frame_size.width.saturating_sub(width)) / 2;
+    let y = (frame_size.height.saturating_sub(height)) / 2;
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let (title, line) = if prompt.confirm_overwrite {
+        (
+            "Overwrite?",
+            Line::from(vec![
+                Span::raw(format!("{} exists. Overwrite? ", prompt.input.trim())),
+                Span::styled("(y/n)", theme.accent),
+            ]),
+        )
+    } else {
+        (
+            "Write results to (Tab to complete)",
+            Line::from(Span::styled(prompt.input.as_str(), theme.accent)),
+        )
+    };
+
+    let prompt_block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(theme.popup);
+    let inner = prompt_block.inner(popup_area);
+
+    // Keep the end of long paths visible
+    let scroll_x =
+        (text::display_width(&line.to_string()) + 1).saturating_sub(inner.width as usize) as u16;
+    frame.render_widget(
+        Paragraph::new(line)
+            .block(prompt_block)
+            .scroll((0, scroll_x)),
+        popup_area,
+    );
+
+    if !prompt.confirm_overwrite {
+        frame.set_cursor_position(Position::new(
+            inner.x + (text::display_width(&prompt.input) as u16).saturating_sub(scroll_x),
+            inner.y,
+        ));
+    }
+}
+
+fn draw_error_popup(frame: &mut Frame, error: &str, theme: &Theme) {
+    let frame_size = frame.area();
+
+    let width = frame_size.width.clamp(20, 60);
+    // Fit every line of the message, e.g. one per document that failed to reload
+    let lines = wrapped_line_count(error, width.saturating_sub(2) as usize);
+    let height = (lines as u16)
+        .saturating_add(2)
+        .clamp(3, frame_size.height.max(3));
 
     let x = (frame_size.width.saturating_sub(width)) / 2;
     let y = (frame_size.height.saturating_sub(height)) / 2;
@@ -396,16 +1107,42 @@ fn draw_error_popup(frame: &mut Frame, error: &str) {
     let error_block = Block::default()
         .title("Error")
         .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Red).fg(Color::White));
+        .style(theme.error);
 
     let error_text = Paragraph::new(error)
         .wrap(Wrap { trim: true })
-        .style(Style::default().bg(Color::Red).fg(Color::White))
+        .style(theme.error)
         .block(error_block);
 
     frame.render_widget(error_text, popup_area);
 }
 
+/// Number of rows `text` takes when word-wrapped to `width` columns
+fn wrapped_line_count(text: &str, width: usize) -> usize {
+    let width = width.max(1);
+    text.lines()
+        .map(|line| {
+            let mut rows = 1;
+            let mut used = 0;
+            for word in line.split_whitespace() {
+                let word_width = text::display_width(word);
+                if used > 0 && used + 1 + word_width <= width {
+                    used += 1 + word_width;
+                } else {
+                    if used > 0 {
+                        rows += 1;
+                    }
+                    // Words wider than the popup are broken across rows
+                    rows += word_width.saturating_sub(1) / width;
+                    used = word_width.saturating_sub(1) % width + 1;
+                }
+            }
+            rows
+        })
+        .sum::<usize>()
+        .max(1)
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -575,14 +1312,155 @@ mod tests {
     }
 
     #[test]
-    fn test_draw_title_bar_without_filename() {
-        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
-        let app = create_test_app();
+    fn test_results_list_selects_whole_nodes() {
+        let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+        let mut app = create_app_with_results();
+        app.perform_action(Action::Last);
 
         terminal
             .draw(|frame| {
                 let area = frame.area();
-                draw_title_bar(frame, &app, area);
+                draw_results_list(frame, &app, area);
+            })
+            .unwrap();
+
+        // Rows drawn with the selection background
+        let buffer = terminal.backend().buffer();
+        let selected_rows = (0..buffer.area.height)
+            .filter(|&y| buffer[(1, y)].bg == app.theme().selection.bg.unwrap())
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .join("")
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(selected_rows.len(), 3);
+        assert!(selected_rows[0].contains("```rust"));
+        assert!(selected_rows[1].contains("fn main() {}"));
+        assert!(selected_rows[2].contains("```"));
+
+        // Entries are separated by a rule
+        let content = buffer.content().iter().map(|c| c.symbol()).join("");
+        assert!(content.contains("──────"));
+    }
+
+    #[test]
+    fn test_results_list_rendered_view() {
+        let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+        let mut app = create_app_with_results();
+        app.perform_action(Action::ToggleRendered);
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_results_list(frame, &app, area);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Results (rendered)"));
+        assert!(content.contains("Test Heading"));
+        assert!(!content.contains("# Test Heading"));
+        assert!(content.contains("fn main() {}"));
+        assert!(!content.contains("```"));
+    }
+
+    #[test]
+    fn test_results_list_draws_tables_as_grids() {
+        let mut app = App::new(String::new());
+        let table = "| Name | Value |\n|:-----|------:|\n| a | 1 |\n| some long name | 22 |\n";
+        app.set_results(
+            mq_markdown::Markdown::from_markdown_str(table)
+                .unwrap()
+                .nodes,
+        );
+
+        let draw = |terminal: &mut Terminal<TestBackend>, app: &App| {
+            terminal
+                .draw(|frame| {
+                    let area = frame.area();
+                    draw_results_list(frame, app, area);
+                })
+                .unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..buffer.area.height)
+                .map(|y| {
+                    (0..buffer.area.width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .join("")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let rows = draw(&mut Terminal::new(TestBackend::new(30, 12)).unwrap(), &app);
+        assert!(rows[2].contains("│ Name           │ Value │"));
+        assert!(rows[4].contains("│ a              │     1 │"));
+        assert!(rows[5].contains("│ some long name │    22 │"));
+
+        // Wide tables scroll instead of wrapping
+        let mut terminal = Terminal::new(TestBackend::new(20, 12)).unwrap();
+        draw(&mut terminal, &app);
+        app.perform_action(Action::ScrollRight);
+        let rows = draw(&mut terminal, &app);
+        assert!(rows[5].contains("ong name │    22 │"));
+        assert!(!rows[5].contains("some"));
+    }
+
+    #[test]
+    fn test_scroll_wide_tables() {
+        let mut app = App::new(String::new());
+        app.perform_action(Action::ScrollRight);
+        assert_eq!(app.table_scroll(), 0);
+
+        let table = format!("| {} |\n|---|\n| a |\n", "x".repeat(20));
+        app.set_results(
+            mq_markdown::Markdown::from_markdown_str(&table)
+                .unwrap()
+                .nodes,
+        );
+        let mut terminal = Terminal::new(TestBackend::new(12, 8)).unwrap();
+        let mut draw = |app: &App| {
+            terminal
+                .draw(|frame| {
+                    let area = frame.area();
+                    draw_results_list(frame, app, area);
+                })
+                .unwrap();
+        };
+        draw(&app);
+
+        app.perform_action(Action::ScrollRight);
+        assert_eq!(app.table_scroll(), 8);
+        // No further than the table's right edge at the list's right side
+        for _ in 0..5 {
+            app.perform_action(Action::ScrollRight);
+        }
+        assert_eq!(app.table_scroll(), 24 - 10);
+
+        app.perform_action(Action::ScrollLeft);
+        assert_eq!(app.table_scroll(), 24 - 10 - 8);
+        for _ in 0..5 {
+            app.perform_action(Action::ScrollLeft);
+        }
+        assert_eq!(app.table_scroll(), 0);
+    }
+
+    #[test]
+    fn test_draw_title_bar_without_filename() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let app = create_test_app();
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_title_bar(frame, &app, area);
             })
             .unwrap();
 
@@ -600,6 +1478,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hints_use_key_bindings() {
+        let mut terminal = Terminal::new(TestBackend::new(120, 24)).unwrap();
+        let mut app = create_test_app();
+        let config = crate::config::Config::parse(
+            "[keys.normal]\nq = \"none\"\nesc = \"none\"\nx = \"quit\"\ns = \"none\"\nb = \"sidebar\"\n",
+        )
+        .unwrap();
+        app.set_keymap(crate::keymap::Keymap::from_config(&config.keys).unwrap());
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Press 'b' for sidebar, 't' for tree view, '?' for help"));
+        assert!(content.contains("Press x to quit"));
+    }
+
     #[test]
     fn test_draw_detail_view_empty_results() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -625,26 +1530,69 @@ mod tests {
             })
             .unwrap();
 
-        let backend = terminal.backend();
-        let buffer = backend.buffer();
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Detail View"));
+        assert!(content.contains("heading"));
+        assert!(content.contains("depth     1"));
+        assert!(content.contains("Rendered"));
+        assert!(content.contains("Children"));
+        assert!(content.contains("Text: Test Heading"));
+        assert!(!content.contains("position"));
+    }
 
-        assert!(
-            buffer
+    #[test]
+    fn test_draw_detail_view_in_inspect_mode() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 10)).unwrap();
+        let mut app = create_app_with_results();
+        app.perform_action(Action::Inspect);
+        assert_eq!(app.mode(), Mode::Inspect);
+
+        let draw = |terminal: &mut Terminal<TestBackend>, app: &App| {
+            terminal
+                .draw(|frame| {
+                    let area = frame.area();
+                    draw_detail_view(frame, app, area);
+                })
+                .unwrap();
+            terminal
+                .backend()
+                .buffer()
                 .content()
                 .iter()
                 .map(|c| c.symbol())
                 .join("")
-                .contains("Detail View")
-        );
+        };
+
+        let content = draw(&mut terminal, &app);
+        assert!(content.contains("heading"));
+        assert!(!content.contains("Text: Test Heading"));
+
+        // The pane scrolls to the selected line
+        for _ in 0..10 {
+            app.handle_event(crossterm::event::Event::Key(
+                crossterm::event::KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
+            ))
+            .unwrap();
+        }
+        let content = draw(&mut terminal, &app);
+        assert!(content.contains("Text: Test Heading"));
+        assert_eq!(app.selected_idx(), 0);
     }
 
     #[test]
     fn test_draw_help_screen_content() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let app = create_test_app();
 
         terminal
             .draw(|frame| {
-                draw_help_screen(frame);
+                draw_help_screen(frame, &app);
             })
             .unwrap();
 
@@ -679,6 +1627,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_draw_help_screen_uses_key_bindings() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        let config =
+            crate::config::Config::parse("[keys.normal]\nk = \"none\"\n\"ctrl+n\" = \"down\"\n")
+                .unwrap();
+        app.set_keymap(crate::keymap::Keymap::from_config(&config.keys).unwrap());
+
+        terminal
+            .draw(|frame| {
+                draw_help_screen(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("↓/j/Ctrl+n - Move down"));
+        assert!(content.contains("↑ - Move up"));
+    }
+
     #[test]
     fn test_draw_error_popup_content() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -686,7 +1661,7 @@ mod tests {
 
         terminal
             .draw(|frame| {
-                draw_error_popup(frame, error_msg);
+                draw_error_popup(frame, error_msg, &Theme::default());
             })
             .unwrap();
 
@@ -698,6 +1673,41 @@ mod tests {
         assert!(content.contains(error_msg));
     }
 
+    #[test]
+    fn test_draw_error_popup_fits_every_line() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let error_msg = "Failed to reload a.md: permission denied\n\
+                         Failed to reload b.md: permission denied\n\
+                         Failed to reload c.md: a message long enough to wrap onto another row";
+
+        terminal
+            .draw(|frame| {
+                draw_error_popup(frame, error_msg, &Theme::default());
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        for name in ["a.md", "b.md", "c.md", "another row"] {
+            assert!(content.contains(name), "{name} is not shown");
+        }
+    }
+
+    #[test]
+    fn test_wrapped_line_count() {
+        assert_eq!(wrapped_line_count("", 10), 1);
+        assert_eq!(wrapped_line_count("short", 10), 1);
+        assert_eq!(wrapped_line_count("one two three", 10), 2);
+        assert_eq!(wrapped_line_count("a\nb\nc", 10), 3);
+        assert_eq!(wrapped_line_count("abcdefghijklmnopqrstuvwxy", 10), 3);
+    }
+
     #[test]
     fn test_draw_query_input_cursor_position() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -713,6 +1723,66 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_draw_query_input_multiline() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query(".h\n| select(.depth == 1)".to_string());
+        app.set_cursor_position(app.query().len());
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('t'),
+                crossterm::event::KeyModifiers::CONTROL,
+            ),
+        ))
+        .unwrap();
+        assert!(app.multiline());
+        assert_eq!(app.query_input_height(), app.editor_height() + 2);
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let content = buffer.content().iter().map(|c| c.symbol()).join("");
+        assert!(content.contains("Query (multi-line: Ctrl+s run, Alt+↑/Alt+↓ resize)"));
+        assert!(content.contains("  1 │ .h"));
+        assert!(content.contains("  2 │ | select(.depth == 1)"));
+        assert_eq!(
+            terminal.get_cursor_position().unwrap(),
+            Position::new(1 + 6 + 21, 2)
+        );
+    }
+
+    #[test]
+    fn test_draw_query_input_horizontal_scroll() {
+        let mut terminal = Terminal::new(TestBackend::new(20, 3)).unwrap();
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("\"日本語のテキスト\" | .h".to_string());
+        app.set_cursor_position(app.query().len());
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_query_input(frame, &app, area);
+            })
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let content = buffer.content().iter().map(|c| c.symbol()).join("");
+        // The end of the query is scrolled into view
+        assert!(content.contains("| .h"));
+        assert!(!content.contains("日"));
+        assert_eq!(
+            terminal.get_cursor_position().unwrap(),
+            Position::new(18, 1)
+        );
+    }
+
     #[test]
     fn test_ui_layout_constraints() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -791,6 +1861,290 @@ mod tests {
         assert!(content.contains("TREE VIEW"));
     }
 
+    #[test]
+    fn test_draw_file_list_and_all_files_results() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = App::with_documents(vec![
+            crate::Document::with_file("# First".to_string(), "first.md".to_string()),
+            crate::Document::with_file("# Second".to_string(), "second.md".to_string()),
+        ]);
+        app.toggle_file_list();
+        app.toggle_all_files_mode();
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("Files (all)"));
+        assert!(content.contains("All files (2)"));
+        assert!(content.contains("[first.md]"));
+        assert!(content.contains("[second.md]"));
+    }
+
+    #[test]
+    fn test_draw_completion_popup() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = App::new("# Title\n\n## Section\n".to_string());
+        app.set_mode(Mode::Query);
+        app.set_query(".h".to_string());
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Tab,
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ))
+        .unwrap();
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("Level 1 headings"));
+        assert!(content.contains("Level 2 headings"));
+    }
+
+    #[test]
+    fn test_draw_history_search_popup() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('r'),
+                crossterm::event::KeyModifiers::CONTROL,
+            ),
+        ))
+        .unwrap();
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("History search"));
+        assert!(content.contains("No matching queries"));
+    }
+
+    #[test]
+    fn test_draw_file_picker_popup() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('o'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ))
+        .unwrap();
+        for c in "zz-missing".chars() {
+            app.handle_event(crossterm::event::Event::Key(
+                crossterm::event::KeyEvent::new(
+                    crossterm::event::KeyCode::Char(c),
+                    crossterm::event::KeyModifiers::NONE,
+                ),
+            ))
+            .unwrap();
+        }
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("Open file: zz-missing"));
+        assert!(content.contains("Press Enter to open the typed path"));
+    }
+
+    #[test]
+    fn test_draw_save_prompt_popup() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_app_with_results();
+        app.perform_action(Action::Write);
+        for c in "out.md".chars() {
+            app.handle_event(crossterm::event::Event::Key(
+                crossterm::event::KeyEvent::new(
+                    crossterm::event::KeyCode::Char(c),
+                    crossterm::event::KeyModifiers::NONE,
+                ),
+            ))
+            .unwrap();
+        }
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("Write results to"));
+        assert!(content.contains("out.md"));
+    }
+
+    #[test]
+    fn test_draw_command_line() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        for (code, modifiers) in [
+            (
+                crossterm::event::KeyCode::Char(';'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+            (
+                crossterm::event::KeyCode::Char('s'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+            (
+                crossterm::event::KeyCode::Tab,
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ] {
+            app.handle_event(crossterm::event::Event::Key(
+                crossterm::event::KeyEvent::new(code, modifiers),
+            ))
+            .unwrap();
+        }
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+
+        assert!(content.contains("Command (Tab to complete)"));
+        assert!(content.contains(":set"));
+        assert!(content.contains("sidebar"));
+        assert!(content.contains("set <OPTION> <VALUE>"));
+        // The command line takes the place of the title bar
+        assert!(!content.contains("NORMAL"));
+        assert_eq!(terminal.get_cursor_position().unwrap(), Position::new(5, 1));
+    }
+
+    #[test]
+    fn test_draw_ui_with_theme() {
+        use ratatui::style::Color;
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_app_with_results();
+
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert!(
+            terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .any(|c| c.bg == Color::White)
+        );
+
+        app.set_theme(Theme::monochrome());
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert!(
+            terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .all(|c| c.fg == Color::Reset && c.bg == Color::Reset)
+        );
+    }
+
+    #[test]
+    fn test_results_cache_follows_changes() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_app_with_results();
+        let content = |terminal: &Terminal<TestBackend>| {
+            terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|c| c.symbol())
+                .join("")
+        };
+
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert!(content(&terminal).contains("# Test Heading"));
+        assert_eq!(
+            app.results_cache().borrow().entries.len(),
+            app.results().len()
+        );
+
+        // The rendered view drops the Markdown markup
+        app.perform_action(Action::ToggleRendered);
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert!(!content(&terminal).contains("# Test Heading"));
+        assert!(content(&terminal).contains("Test Heading"));
+
+        app.set_results(
+            mq_markdown::Markdown::from_markdown_str("```rust\nfn main() {}\n```\n")
+                .unwrap()
+                .nodes,
+        );
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert!(content(&terminal).contains("fn main() {}"));
+        assert!(!content(&terminal).contains("Test Heading"));
+        assert_eq!(app.results_cache().borrow().entries.len(), 1);
+
+        // A narrower pane lays the entries out again
+        terminal.backend_mut().resize(40, 24);
+        terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+        assert_eq!(app.results_cache().borrow().width, Some(38));
+    }
+
     #[test]
     fn test_is_markdown_header() {
         // Valid headers
@@ -0,0 +1,490 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use std::ops::Range;
+
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Comment,
+    Keyword,
+    Constant,
+    Selector,
+    Function,
+    Ident,
+    /// Environment variable such as `$HOME`
+    Env,
+    String,
+    Number,
+    Operator,
+    Pipe,
+    Bracket,
+    Punctuation,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range of the token in the query
+    pub range: Range<usize>,
+}
+
+/// Split an mq query into tokens for highlighting.
+///
+/// Tokens and trivia come from mq-lang's error-recovering parser. Text it
+/// can't recover, such as an unterminated string, is covered by `Unknown`
+/// tokens, so the tokens always cover the whole input.
+pub fn tokenize(query: &str) -> Vec<Token> {
+    let line_starts = std::iter::once(0)
+        .chain(query.match_indices('\n').map(|(i, _)| i + 1))
+        .collect::<Vec<_>>();
+    let offset = |position: mq_lang::Position| {
+        let line = (position.line as usize).saturating_sub(1);
+        let Some(&line_start) = line_starts.get(line) else {
+            return query.len();
+        };
+        query[line_start..]
+            .char_indices()
+            .nth(position.column.saturating_sub(1))
+            .map_or(query.len(), |(i, _)| line_start + i)
+    };
+
+    let (nodes, _) = mq_lang::parse_recovery(query);
+    let mut lexed = Vec::new();
+    for node in &nodes {
+        collect_tokens(node, &mut lexed);
+    }
+    let mut lexed = lexed
+        .into_iter()
+        .filter_map(|token| {
+            let kind = token_kind(&token.kind)?;
+            let mut start = offset(token.range.start);
+            // The ranges of comments and environment variables start after `#` and `$`
+            let sigil = match kind {
+                TokenKind::Comment => Some('#'),
+                TokenKind::Env => Some('$'),
+                _ => None,
+            };
+            if sigil.is_some_and(|sigil| query[..start].ends_with(sigil)) {
+                start -= 1;
+            }
+            Some((start..offset(token.range.end), kind))
+        })
+        .filter(|(range, _)| !range.is_empty())
+        .collect::<Vec<_>>();
+    lexed.sort_by_key(|(range, _)| range.start);
+
+    let mut tokens: Vec<Token> = Vec::new();
+    let gap = |tokens: &mut Vec<Token>, range: Range<usize>| {
+        if !range.is_empty() {
+            let kind = if query[range.clone()].trim().is_empty() {
+                TokenKind::Whitespace
+            } else {
+                TokenKind::Unknown
+            };
+            tokens.push(Token { kind, range });
+        }
+    };
+    let mut end = 0;
+    for (range, kind) in lexed {
+        if range.start < end {
+            continue;
+        }
+        gap(&mut tokens, end..range.start);
+        end = range.end;
+        tokens.push(Token { kind, range });
+    }
+    gap(&mut tokens, end..query.len());
+
+    // A name followed by an argument list is a function
+    for i in 1..tokens.len() {
+        if tokens[i - 1].kind == TokenKind::Ident && &query[tokens[i].range.clone()] == "(" {
+            tokens[i - 1].kind = TokenKind::Function;
+        }
+    }
+
+    tokens
+}
+
+/// Collect the tokens of a CST node and its children, including trivia
+fn collect_tokens(node: &mq_lang::CstNode, tokens: &mut Vec<mq_lang::Token>) {
+    let trivia_token = |trivia: &mq_lang::CstTrivia| match trivia {
+        mq_lang::CstTrivia::Whitespace(token)
+        | mq_lang::CstTrivia::Tab(token)
+        | mq_lang::CstTrivia::Comment(token) => Some(mq_lang::Token::clone(token)),
+        mq_lang::CstTrivia::NewLine => None,
+    };
+
+    tokens.extend(node.leading_trivia.iter().filter_map(trivia_token));
+    tokens.extend(node.token.as_deref().cloned());
+    tokens.extend(node.trailing_trivia.iter().filter_map(trivia_token));
+    for child in &node.children {
+        collect_tokens(child, tokens);
+    }
+}
+
+fn token_kind(kind: &mq_lang::TokenKind) -> Option<TokenKind> {
+    use mq_lang::TokenKind as Mq;
+
+    Some(match kind {
+        Mq::Whitespace(_) | Mq::Tab(_) | Mq::NewLine => TokenKind::Whitespace,
+        Mq::Comment(_) => TokenKind::Comment,
+        Mq::Break
+        | Mq::Catch
+        | Mq::Continue
+        | Mq::Def
+        | Mq::Do
+        | Mq::Elif
+        | Mq::Else
+        | Mq::End
+        | Mq::Fn
+        | Mq::Foreach
+        | Mq::If
+        | Mq::Import
+        | Mq::Include
+        | Mq::Let
+        | Mq::Match
+        | Mq::Module
+        | Mq::Nodes
+        | Mq::Self_
+        | Mq::Try
+        | Mq::Var
+        | Mq::While => TokenKind::Keyword,
+        Mq::BoolLiteral(_) | Mq::None => TokenKind::Constant,
+        Mq::Selector(_) => TokenKind::Selector,
+        Mq::Ident(_) => TokenKind::Ident,
+        Mq::Env(_) => TokenKind::Env,
+        Mq::StringLiteral(_) | Mq::InterpolatedString(_) => TokenKind::String,
+        Mq::NumberLiteral(_) => TokenKind::Number,
+        Mq::Pipe => TokenKind::Pipe,
+        Mq::LParen | Mq::RParen | Mq::LBracket | Mq::RBracket | Mq::LBrace | Mq::RBrace => {
+            TokenKind::Bracket
+        }
+        Mq::Comma | Mq::SemiColon | Mq::Colon | Mq::DoubleColon => TokenKind::Punctuation,
+        Mq::And
+        | Mq::Or
+        | Mq::Not
+        | Mq::Asterisk
+        | Mq::Coalesce
+        | Mq::Equal
+        | Mq::EqEq
+        | Mq::NeEq
+        | Mq::Gt
+        | Mq::Gte
+        | Mq::Lt
+        | Mq::Lte
+        | Mq::Minus
+        | Mq::Plus
+        | Mq::Percent
+        | Mq::Question
+        | Mq::RangeOp
+        | Mq::Slash => TokenKind::Operator,
+        Mq::Eof => return None,
+    })
+}
+
+/// Find the bracket matching the one at or just before `cursor`.
+///
+/// Returns the byte offsets of both brackets.
+pub fn matching_bracket(query: &str, cursor: usize) -> Option<(usize, usize)> {
+    let mut stack: Vec<(usize, char)> = Vec::new();
+    let mut pairs = Vec::new();
+
+    for token in tokenize(query)
+        .into_iter()
+        .filter(|t| t.kind == TokenKind::Bracket)
+    {
+        let c = query[token.range.clone()].chars().next()?;
+        match c {
+            '(' | '[' | '{' => stack.push((token.range.start, c)),
+            _ => {
+                let open = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                if let Some(pos) = stack.iter().rposition(|(_, o)| *o == open) {
+                    let (start, _) = stack.remove(pos);
+                    pairs.push((start, token.range.start));
+                }
+            }
+        }
+    }
+
+    let find = |offset: usize| {
+        pairs.iter().find_map(|&(open, close)| {
+            if open == offset {
+                Some((open, close))
+            } else if close == offset {
+                Some((close, open))
+            } else {
+                None
+            }
+        })
+    };
+
+    find(cursor).or_else(|| cursor.checked_sub(1).and_then(find))
+}
+
+fn token_style(kind: TokenKind, theme: &Theme) -> Style {
+    match kind {
+        TokenKind::Keyword => theme.keyword,
+        TokenKind::Constant | TokenKind::Env | TokenKind::Number => theme.number,
+        TokenKind::Selector => theme.selector,
+        TokenKind::Function => theme.function,
+        TokenKind::String => theme.string,
+        TokenKind::Operator => theme.operator,
+        TokenKind::Pipe => theme.pipe,
+        TokenKind::Comment => theme.comment,
+        TokenKind::Bracket | TokenKind::Punctuation => theme.punctuation,
+        TokenKind::Ident | TokenKind::Whitespace | TokenKind::Unknown => theme.ident,
+    }
+}
+
+/// Highlight a query for display in the query input.
+///
+/// The bracket matching the one under the cursor is highlighted, and the
+/// `error_span` reported by the parser is underlined. Returns one line per
+/// line of the query.
+pub fn highlight_query(
+    query: &str,
+    cursor: usize,
+    error_span: Option<Range<usize>>,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    let tokens = tokenize(query);
+    let brackets = matching_bracket(query, cursor);
+
+    let mut lines = Vec::new();
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut current: Option<(String, Style)> = None;
+    let mut token_idx = 0;
+
+    for (offset, c) in query.char_indices() {
+        if c == '\n' {
+            if let Some((text, style)) = current.take() {
+                spans.push(Span::styled(text, style));
+            }
+            lines.push(Line::from(std::mem::take(&mut spans)));
+            continue;
+        }
+
+        while tokens.get(token_idx).is_some_and(|t| t.range.end <= offset) {
+            token_idx += 1;
+        }
+
+        let mut style = tokens
+            .get(token_idx)
+            .map(|t| token_style(t.kind, theme))
+            .unwrap_or_default();
+        if brackets.is_some_and(|(a, b)| offset == a || offset == b) {
+            style = style.patch(theme.matching_bracket);
+        }
+        if error_span
+            .as_ref()
+            .is_some_and(|span| span.contains(&offset))
+        {
+            style = style.patch(theme.query_error);
+        }
+
+        match &mut current {
+            Some((text, current_style)) if *current_style == style => text.push(c),
+            _ => {
+                if let Some((text, style)) = current.take() {
+                    spans.push(Span::styled(text, style));
+                }
+                current = Some((c.to_string(), style));
+            }
+        }
+    }
+
+    if let Some((text, style)) = current.take() {
+        spans.push(Span::styled(text, style));
+    }
+    lines.push(Line::from(spans));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Modifier;
+
+    fn kinds(query: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(query)
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .map(|t| (t.kind, &query[t.range]))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_pipeline() {
+        assert_eq!(
+            kinds(r#".code | select(.lang == "rust")"#),
+            vec![
+                (TokenKind::Selector, ".code"),
+                (TokenKind::Pipe, "|"),
+                (TokenKind::Function, "select"),
+                (TokenKind::Bracket, "("),
+                (TokenKind::Selector, ".lang"),
+                (TokenKind::Operator, "=="),
+                (TokenKind::String, "\"rust\""),
+                (TokenKind::Bracket, ")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_keywords_and_modules() {
+        assert_eq!(
+            kinds(r#"import "section" | section::split(2) # comment"#),
+            vec![
+                (TokenKind::Keyword, "import"),
+                (TokenKind::String, "\"section\""),
+                (TokenKind::Pipe, "|"),
+                (TokenKind::Ident, "section"),
+                (TokenKind::Punctuation, "::"),
+                (TokenKind::Function, "split"),
+                (TokenKind::Bracket, "("),
+                (TokenKind::Number, "2"),
+                (TokenKind::Bracket, ")"),
+                (TokenKind::Comment, "# comment"),
+            ]
+        );
+        assert_eq!(
+            kinds("let x = true"),
+            vec![
+                (TokenKind::Keyword, "let"),
+                (TokenKind::Ident, "x"),
+                (TokenKind::Operator, "="),
+                (TokenKind::Constant, "true"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_mq_keywords() {
+        assert_eq!(
+            kinds("try: do var x = None | x end catch: 1"),
+            vec![
+                (TokenKind::Keyword, "try"),
+                (TokenKind::Punctuation, ":"),
+                (TokenKind::Keyword, "do"),
+                (TokenKind::Keyword, "var"),
+                (TokenKind::Ident, "x"),
+                (TokenKind::Operator, "="),
+                (TokenKind::Constant, "None"),
+                (TokenKind::Pipe, "|"),
+                (TokenKind::Ident, "x"),
+                (TokenKind::Keyword, "end"),
+                (TokenKind::Keyword, "catch"),
+                (TokenKind::Punctuation, ":"),
+                (TokenKind::Number, "1"),
+            ]
+        );
+        // `and` and `or` are functions in mq, not keywords
+        assert_eq!(kinds("and(a, b)")[0], (TokenKind::Function, "and"));
+        assert_eq!(
+            kinds(r#"$HOME ?? to-text"#),
+            vec![
+                (TokenKind::Env, "$HOME"),
+                (TokenKind::Operator, "??"),
+                (TokenKind::Ident, "to-text"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_strings_like_mq() {
+        // Escaped quotes and `#` inside strings don't end the string
+        let query = r#"let s = "say \"hi\" # not a comment \\" | s"#;
+        let mut engine = mq_lang::DefaultEngine::default();
+        engine.load_builtin_module();
+        assert!(
+            engine
+                .eval(query, mq_lang::null_input().into_iter())
+                .is_ok()
+        );
+
+        assert_eq!(
+            kinds(query),
+            vec![
+                (TokenKind::Keyword, "let"),
+                (TokenKind::Ident, "s"),
+                (TokenKind::Operator, "="),
+                (TokenKind::String, r#""say \"hi\" # not a comment \\""#),
+                (TokenKind::Pipe, "|"),
+                (TokenKind::Ident, "s"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_mq_sources() {
+        // Every token of real mq code is recognized
+        let loader = mq_lang::DefaultModuleLoader::default();
+        let sources = ["csv", "json", "section", "test", "toml", "xml", "yaml"]
+            .iter()
+            .map(|name| loader.resolve(name).unwrap())
+            .chain([mq_lang::BUILTIN_MODULE_FILE.to_string()]);
+
+        for source in sources {
+            let unknown = tokenize(&source)
+                .into_iter()
+                .filter(|t| t.kind == TokenKind::Unknown)
+                .map(|t| &source[t.range])
+                .collect::<Vec<_>>();
+            assert!(unknown.is_empty(), "unknown tokens: {:?}", unknown);
+        }
+    }
+
+    #[test]
+    fn test_tokenize_covers_input() {
+        let query = "select(\"unterminated \\\" 日本語 ||";
+        let tokens = tokenize(query);
+        assert_eq!(tokens.first().unwrap().range.start, 0);
+        assert_eq!(tokens.last().unwrap().range.end, query.len());
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].range.end, pair[1].range.start);
+        }
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let query = "select(contains(\"(\"))";
+        // Cursor on the outer opening paren
+        assert_eq!(matching_bracket(query, 6), Some((6, 20)));
+        // Cursor just after the inner closing paren
+        assert_eq!(matching_bracket(query, 20), Some((20, 6)));
+        assert_eq!(matching_bracket(query, 19), Some((19, 15)));
+        // Parens inside strings are ignored
+        assert_eq!(matching_bracket(query, 17), None);
+        assert_eq!(matching_bracket("select(", 7), None);
+    }
+
+    #[test]
+    fn test_highlight_query_lines() {
+        let lines = highlight_query(".h\n| select(.depth == 1)", 0, None, &Theme::default());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].to_string(), ".h");
+        assert_eq!(lines[1].to_string(), "| select(.depth == 1)");
+    }
+
+    #[test]
+    fn test_highlight_query_error_span() {
+        let lines = highlight_query(".h | sel(", 0, Some(5..8), &Theme::default());
+        let underlined = lines[0]
+            .spans
+            .iter()
+            .filter(|span| span.style.add_modifier.contains(Modifier::UNDERLINED))
+            .map(|span| span.content.to_string())
+            .collect::<String>();
+        assert_eq!(underlined, "sel");
+    }
+}
@@ -0,0 +1,343 @@
+use std::cell::{Ref, RefCell};
+
+use mq_markdown::Node;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Modifier,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Padding},
+};
+
+use super::{
+    markdown,
+    treeview::{TreeItem, TreeView},
+};
+use crate::{export, theme::Theme};
+
+/// Lines moved by page up and page down
+const PAGE_SIZE: usize = 10;
+
+/// Structured view of a result node: its type, attributes, source position,
+/// rendered Markdown and a tree of its children that can be expanded
+pub struct Inspector {
+    node: Node,
+    children: TreeView,
+    /// Selected line. Moving it scrolls the view.
+    selected: usize,
+    /// Lines built for the last theme and width, dropped when a child is
+    /// expanded or collapsed
+    rows: RefCell<Option<CachedRows>>,
+}
+
+struct CachedRows {
+    theme: Theme,
+    width: usize,
+    rows: Vec<Row>,
+}
+
+/// A line of the inspector
+struct Row {
+    line: Line<'static>,
+    /// Index of the item in the children tree shown on the line
+    child: Option<usize>,
+}
+
+impl Inspector {
+    pub fn new(node: Node) -> Self {
+        let children = TreeItem::new(node.clone(), 0, 0).get_children();
+
+        Self {
+            node,
+            children: TreeView::new(children),
+            selected: 0,
+            rows: RefCell::default(),
+        }
+    }
+
+    /// The inspected node
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    #[cfg(test)]
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn move_down(&mut self, theme: &Theme) {
+        self.select(self.selected + 1, theme);
+    }
+
+    pub fn move_up(&mut self, theme: &Theme) {
+        self.select(self.selected.saturating_sub(1), theme);
+    }
+
+    pub fn page_down(&mut self, theme: &Theme) {
+        self.select(self.selected + PAGE_SIZE, theme);
+    }
+
+    pub fn page_up(&mut self, theme: &Theme) {
+        self.select(self.selected.saturating_sub(PAGE_SIZE), theme);
+    }
+
+    /// Expand or collapse the child on the selected line
+    pub fn toggle(&mut self, theme: &Theme) {
+        let child = self
+            .rows(theme, None)
+            .get(self.selected)
+            .and_then(|row| row.child);
+        if let Some(child) = child {
+            self.children.select(child);
+            self.children.toggle_expand();
+            *self.rows.get_mut() = None;
+        }
+    }
+
+    fn select(&mut self, index: usize, theme: &Theme) {
+        let len = self.rows(theme, None).len();
+        self.selected = index.min(len.saturating_sub(1));
+    }
+
+    /// Lines of the inspector, built again only when the theme, the width or
+    /// the expanded children changed. `width` is the width of horizontal
+    /// rules in the rendered Markdown; `None` takes the lines of any width,
+    /// as the number of lines doesn't depend on it.
+    fn rows(&self, theme: &Theme, width: Option<usize>) -> Ref<'_, [Row]> {
+        let cached = self.rows.borrow().as_ref().is_some_and(|cached| {
+            cached.theme == *theme && width.is_none_or(|width| cached.width == width)
+        });
+        if !cached {
+            let width = width.unwrap_or_default();
+            *self.rows.borrow_mut() = Some(CachedRows {
+                theme: theme.clone(),
+                width,
+                rows: self.build_rows(theme, width),
+            });
+        }
+
+        Ref::map(self.rows.borrow(), |rows| {
+            rows.as_ref()
+                .map_or(&[][..], |cached| cached.rows.as_slice())
+        })
+    }
+
+    fn build_rows(&self, theme: &Theme, width: usize) -> Vec<Row> {
+        let section = |title: &str| Row {
+            line: Line::from(Span::styled(
+                title.to_string(),
+                theme.title.add_modifier(Modifier::UNDERLINED),
+            )),
+            child: None,
+        };
+        let blank = || Row {
+            line: Line::from(""),
+            child: None,
+        };
+
+        let mut rows = vec![Row {
+            line: Line::from(Span::styled(export::node_type(&self.node), theme.header)),
+            child: None,
+        }];
+
+        let position = self.node.position().map(|position| {
+            format!(
+                "{}:{}-{}:{}",
+                position.start.line, position.start.column, position.end.line, position.end.column
+            )
+        });
+        rows.extend(
+            attributes(&self.node)
+                .into_iter()
+                .chain(position.map(|position| ("position", position)))
+                .map(|(name, value)| Row {
+                    line: Line::from(vec![
+                        Span::styled(format!("{:<10}", name), theme.dim),
+                        Span::styled(value, theme.accent),
+                    ]),
+                    child: None,
+                }),
+        );
+
+        rows.push(blank());
+        rows.push(section("Rendered"));
+        rows.extend(
+            markdown::render_node(&self.node, theme, width)
+                .into_iter()
+                .map(|line| Row { line, child: None }),
+        );
+
+        if !self.children.items().is_empty() {
+            rows.push(blank());
+            rows.push(section("Children"));
+            rows.extend(self.children.items().iter().enumerate().map(|(i, item)| {
+                let icon = match (item.has_children, item.is_expanded) {
+                    (true, true) => "▼ ",
+                    (true, false) => "▶ ",
+                    (false, _) => "  ",
+                };
+                Row {
+                    line: Line::from(vec![
+                        Span::raw("  ".repeat(item.depth)),
+                        Span::raw(icon),
+                        Span::styled(
+                            item.display_text.clone(),
+                            TreeView::get_node_style(&item.node, theme),
+                        ),
+                    ]),
+                    child: Some(i),
+                }
+            }));
+        }
+
+        rows
+    }
+
+    /// Draw the inspector. Only a `focused` inspector shows its selection.
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme, focused: bool) {
+        let block = Block::default()
+            .title("Detail View")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain)
+            .padding(Padding::new(1, 1, 1, 1));
+        let width = block.inner(area).width as usize;
+
+        let items = self
+            .rows(theme, Some(width))
+            .iter()
+            .map(|row| ListItem::new(row.line.clone()))
+            .collect::<Vec<_>>();
+
+        let mut state = ListState::default();
+        let mut list = List::new(items).block(block);
+        if focused {
+            list = list.highlight_style(theme.selection);
+            state.select(Some(self.selected));
+        }
+
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+}
+
+/// Attributes of the node worth showing, by name
+fn attributes(node: &Node) -> Vec<(&'static str, String)> {
+    match node {
+        Node::Heading(heading) => vec![("depth", heading.depth.to_string())],
+        Node::Code(code) => code
+            .lang
+            .iter()
+            .map(|lang| ("lang", lang.clone()))
+            .collect(),
+        Node::List(list) => {
+            let mut attributes = vec![("ordered", list.ordered.to_string())];
+            if let Some(checked) = list.checked {
+                attributes.push(("checked", checked.to_string()));
+            }
+            attributes
+        }
+        Node::Link(link) => {
+            let mut attributes = vec![("url", link.url.as_str().to_string())];
+            if let Some(title) = &link.title {
+                attributes.push(("title", title.to_value()));
+            }
+            attributes
+        }
+        Node::Image(image) => {
+            let mut attributes = vec![("url", image.url.to_string())];
+            if let Some(title) = &image.title {
+                attributes.push(("title", title.to_string()));
+            }
+            attributes
+        }
+        Node::Definition(definition) => {
+            let mut attributes = vec![
+                ("ident", definition.ident.to_string()),
+                ("url", definition.url.as_str().to_string()),
+            ];
+            if let Some(title) = &definition.title {
+                attributes.push(("title", title.to_value()));
+            }
+            attributes
+        }
+        Node::FootnoteRef(footnote) => vec![("ident", footnote.ident.to_string())],
+        Node::ImageRef(image) => vec![("ident", image.ident.to_string())],
+        Node::LinkRef(link) => vec![("ident", link.ident.to_string())],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_node(markdown: &str) -> Node {
+        mq_markdown::Markdown::from_markdown_str(markdown)
+            .unwrap()
+            .nodes
+            .remove(0)
+    }
+
+    fn text(inspector: &Inspector) -> Vec<String> {
+        inspector
+            .rows(&Theme::default(), Some(10))
+            .iter()
+            .map(|row| row.line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_inspect_heading() {
+        let inspector = Inspector::new(first_node("## Hello *world*\n"));
+        let lines = text(&inspector);
+
+        assert_eq!(lines[0], "heading");
+        assert_eq!(lines[1], "depth     2");
+        assert_eq!(lines[2], "position  1:1-1:17");
+        assert!(lines.contains(&"Rendered".to_string()));
+        assert!(lines.contains(&"Hello world".to_string()));
+        assert!(lines.contains(&"Children".to_string()));
+        assert!(lines.contains(&"  Text: Hello".to_string()));
+        assert!(lines.contains(&"▶ Emphasis".to_string()));
+    }
+
+    #[test]
+    fn test_inspect_attributes() {
+        let lines = text(&Inspector::new(first_node("```rust\nfn main() {}\n```\n")));
+        assert_eq!(lines[1], "lang      rust");
+
+        let lines = text(&Inspector::new(first_node("- [x] done\n")));
+        assert_eq!(lines[1], "ordered   false");
+        assert_eq!(lines[2], "checked   true");
+    }
+
+    #[test]
+    fn test_expand_children() {
+        let theme = Theme::default();
+        let mut inspector = Inspector::new(first_node("## Hello *world*\n"));
+        let emphasis = text(&inspector)
+            .iter()
+            .position(|line| line == "▶ Emphasis")
+            .unwrap();
+
+        // Lines without a child don't expand
+        inspector.toggle(&theme);
+        assert_eq!(text(&inspector).len(), emphasis + 1);
+
+        for _ in 0..emphasis {
+            inspector.move_down(&theme);
+        }
+        inspector.toggle(&theme);
+        let lines = text(&inspector);
+        assert_eq!(lines[emphasis], "▼ Emphasis");
+        assert_eq!(lines[emphasis + 1], "    Text: world");
+        assert_eq!(inspector.selected, emphasis);
+
+        // The selection stops at the last line
+        inspector.page_down(&theme);
+        inspector.page_down(&theme);
+        assert_eq!(inspector.selected, lines.len() - 1);
+        inspector.page_up(&theme);
+        inspector.page_up(&theme);
+        assert_eq!(inspector.selected, 0);
+    }
+}
@@ -0,0 +1,304 @@
+use mq_markdown::{Markdown, Node};
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+};
+
+use super::{syntax, table::Grid};
+use crate::theme::Theme;
+
+/// Render a node for reading rather than as Markdown source: emphasis,
+/// links and code are styled instead of marked up, lists get bullets and
+/// blockquotes a gutter. `width` is the width of horizontal rules.
+pub fn render_node(node: &Node, theme: &Theme, width: usize) -> Vec<Line<'static>> {
+    let mut renderer = Renderer {
+        theme,
+        width,
+        lines: Vec::new(),
+        spans: Vec::new(),
+    };
+    renderer.block(node);
+    renderer.end_line();
+    renderer.lines
+}
+
+struct Renderer<'a> {
+    theme: &'a Theme,
+    width: usize,
+    lines: Vec<Line<'static>>,
+    /// Spans of the line being built
+    spans: Vec<Span<'static>>,
+}
+
+impl Renderer<'_> {
+    fn block(&mut self, node: &Node) {
+        match node {
+            Node::Heading(heading) => {
+                self.end_line();
+                for child in &heading.values {
+                    self.inline(child, self.theme.header);
+                }
+                self.end_line();
+            }
+            Node::List(list) => {
+                self.end_line();
+                let bullet = if list.ordered {
+                    format!("{}. ", list.index + 1)
+                } else {
+                    "• ".to_string()
+                };
+                self.spans.push(Span::raw("  ".repeat(list.level as usize)));
+                self.spans.push(Span::styled(bullet, self.theme.list));
+                match list.checked {
+                    Some(true) => self.spans.push(Span::styled("☑ ", self.theme.list)),
+                    Some(false) => self.spans.push(Span::styled("☐ ", self.theme.list)),
+                    None => {}
+                }
+                for child in &list.values {
+                    self.inline(child, Style::default());
+                }
+                self.end_line();
+            }
+            Node::Blockquote(quote) => {
+                self.end_line();
+                let start = self.lines.len();
+                for child in &quote.values {
+                    self.inline(child, Style::default());
+                }
+                self.end_line();
+
+                for line in &mut self.lines[start..] {
+                    line.spans
+                        .insert(0, Span::styled("│ ", self.theme.blockquote));
+                }
+            }
+            Node::HorizontalRule(_) => {
+                self.end_line();
+                self.lines.push(Line::from(Span::styled(
+                    "─".repeat(self.width.max(3)),
+                    self.theme.horizontal_rule,
+                )));
+            }
+            Node::Code(code) => {
+                self.end_line();
+                if let Some(lang) = &code.lang {
+                    self.lines
+                        .push(Line::from(Span::styled(lang.clone(), self.theme.dim)));
+                }
+                self.lines.extend(syntax::highlight_code(
+                    &code.value,
+                    code.lang.as_deref(),
+                    self.theme,
+                ));
+            }
+            Node::Fragment(fragment) => {
+                for child in &fragment.values {
+                    self.block(child);
+                }
+            }
+            Node::TableHeader(_) | Node::TableRow(_) | Node::TableCell(_) => {
+                self.end_line();
+                self.lines.extend(
+                    Grid::new(std::slice::from_ref(node), self.theme, true).lines(None, self.theme),
+                );
+            }
+            // Shown as Markdown source
+            Node::Math(_)
+            | Node::Footnote(_)
+            | Node::Definition(_)
+            | Node::Yaml(_)
+            | Node::Toml(_)
+            | Node::MdxFlowExpression(_)
+            | Node::MdxJsxFlowElement(_)
+            | Node::MdxJsEsm(_) => {
+                self.end_line();
+                self.lines.extend(
+                    Markdown::new(vec![node.clone()])
+                        .to_string()
+                        .lines()
+                        .map(|line| Line::from(line.to_string())),
+                );
+            }
+            _ => self.inline(node, Style::default()),
+        }
+    }
+
+    fn inline(&mut self, node: &Node, style: Style) {
+        match node {
+            Node::Text(text) => self.text(&text.value, style),
+            Node::Strong(strong) => {
+                for child in &strong.values {
+                    self.inline(child, style.add_modifier(Modifier::BOLD));
+                }
+            }
+            Node::Emphasis(emphasis) => {
+                for child in &emphasis.values {
+                    self.inline(child, style.add_modifier(Modifier::ITALIC));
+                }
+            }
+            Node::Delete(delete) => {
+                for child in &delete.values {
+                    self.inline(child, style.add_modifier(Modifier::CROSSED_OUT));
+                }
+            }
+            Node::CodeInline(code) => self.text(&code.value, style.patch(self.theme.code)),
+            Node::MathInline(math) => self.text(&math.value, style.patch(self.theme.math)),
+            Node::Link(link) => {
+                let link_style = style
+                    .patch(self.theme.link)
+                    .add_modifier(Modifier::UNDERLINED);
+                for child in &link.values {
+                    self.inline(child, link_style);
+                }
+                self.spans.push(Span::styled(
+                    format!(" ({})", link.url.as_str()),
+                    self.theme.dim,
+                ));
+            }
+            Node::Image(image) => {
+                self.text(
+                    &format!("[image: {}]", image.alt),
+                    style.patch(self.theme.image),
+                );
+                self.spans
+                    .push(Span::styled(format!(" ({})", image.url), self.theme.dim));
+            }
+            Node::Break(_) => self.end_line(),
+            Node::Html(html) => self.text(&html.value, style.patch(self.theme.dim)),
+            Node::Empty => {}
+            // Block nodes nested in inline content, e.g. a list in a blockquote
+            Node::Heading(_)
+            | Node::List(_)
+            | Node::Blockquote(_)
+            | Node::HorizontalRule(_)
+            | Node::Code(_)
+            | Node::Fragment(_)
+            | Node::Math(_)
+            | Node::TableHeader(_)
+            | Node::TableRow(_)
+            | Node::TableCell(_)
+            | Node::Footnote(_)
+            | Node::Definition(_)
+            | Node::Yaml(_)
+            | Node::Toml(_)
+            | Node::MdxFlowExpression(_)
+            | Node::MdxJsxFlowElement(_)
+            | Node::MdxJsEsm(_) => self.block(node),
+            // References and inline MDX keep their source form
+            Node::FootnoteRef(_)
+            | Node::ImageRef(_)
+            | Node::LinkRef(_)
+            | Node::MdxJsxTextElement(_)
+            | Node::MdxTextExpression(_) => {
+                let source = Markdown::new(vec![node.clone()]).to_string();
+                self.text(source.trim_end(), style);
+            }
+        }
+    }
+
+    /// Add text to the current line, starting new lines at newlines
+    fn text(&mut self, text: &str, style: Style) {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.end_line();
+            }
+            if !part.is_empty() {
+                self.spans.push(Span::styled(part.to_string(), style));
+            }
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.spans.is_empty() {
+            self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str) -> Vec<Line<'static>> {
+        Markdown::from_markdown_str(markdown)
+            .unwrap()
+            .nodes
+            .iter()
+            .flat_map(|node| render_node(node, &Theme::default(), 10))
+            .collect()
+    }
+
+    fn text(lines: &[Line]) -> String {
+        lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Text of the spans with the given modifier
+    fn with_modifier(lines: &[Line], modifier: Modifier) -> String {
+        lines
+            .iter()
+            .flat_map(|line| &line.spans)
+            .filter(|span| span.style.add_modifier.contains(modifier))
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_render_inline_markup() {
+        let lines = render("Some **bold**, *emphasis* and `code`\n");
+        let rendered = text(&lines);
+        assert!(rendered.contains("bold"));
+        assert!(!rendered.contains("**"));
+        assert!(!rendered.contains('`'));
+        assert_eq!(with_modifier(&lines, Modifier::BOLD), "bold");
+        assert_eq!(with_modifier(&lines, Modifier::ITALIC), "emphasis");
+        assert!(
+            lines
+                .iter()
+                .flat_map(|line| &line.spans)
+                .any(|span| span.content == "code" && span.style == Theme::default().code)
+        );
+    }
+
+    #[test]
+    fn test_render_link_with_dimmed_url() {
+        let lines = render("[mq](https://mqlang.org)\n");
+        assert_eq!(with_modifier(&lines, Modifier::UNDERLINED), "mq");
+        let url = lines
+            .iter()
+            .flat_map(|line| &line.spans)
+            .find(|span| span.content.contains("https://mqlang.org"))
+            .unwrap();
+        assert_eq!(url.style, Theme::default().dim);
+        assert!(!text(&lines).contains("]("));
+    }
+
+    #[test]
+    fn test_render_lists_and_tasks() {
+        let rendered = text(&render("- one\n- [x] done\n- [ ] todo\n\n1. first\n"));
+        assert!(rendered.contains("• one"));
+        assert!(rendered.contains("☑ done"));
+        assert!(rendered.contains("☐ todo"));
+        assert!(rendered.contains("1. first"));
+        assert!(!rendered.contains("[x]"));
+    }
+
+    #[test]
+    fn test_render_blockquote_and_rule() {
+        let lines = render("> quoted\n\n---\n");
+        let rendered = text(&lines);
+        assert!(rendered.contains("│ quoted"));
+        assert!(rendered.contains("──────────"));
+        assert!(!rendered.contains("---"));
+    }
+
+    #[test]
+    fn test_render_heading_and_code() {
+        let lines = render("# Title\n\n```rust\nfn main() {}\n```\n");
+        assert_eq!(text(&lines), "Title\nrust\nfn main() {}");
+        assert_eq!(lines[0].spans[0].style, Theme::default().header);
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::LazyLock;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{self, FontStyle},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use crate::theme::Theme;
+
+/// Grammars bundled into the binary, so highlighting works offline
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Highlight the lines of a code block written in `lang`, which is a name
+/// or file extension such as `rust` or `py`. Code in an unknown language,
+/// or when the theme turns highlighting off, is drawn in the code style.
+pub fn highlight_code(code: &str, lang: Option<&str>, theme: &Theme) -> Vec<Line<'static>> {
+    // Info strings like `rust,ignore` carry more than the language
+    let syntax = lang
+        .and_then(|lang| lang.split([',', ' ', '{']).next())
+        .filter(|lang| !lang.is_empty())
+        .and_then(|lang| SYNTAXES.find_syntax_by_token(lang));
+    let syntax_theme = theme.syntax_theme();
+
+    let (Some(syntax), Some(syntax_theme)) = (syntax, syntax_theme) else {
+        return code
+            .lines()
+            .map(|line| Line::from(Span::styled(line.to_string(), theme.code)))
+            .collect();
+    };
+
+    let mut highlighter = HighlightLines::new(syntax, syntax_theme);
+    LinesWithEndings::from(code)
+        .map(|line| {
+            let Ok(regions) = highlighter.highlight_line(line, &SYNTAXES) else {
+                return Line::from(Span::styled(
+                    line.trim_end_matches(['\r', '\n']).to_string(),
+                    theme.code,
+                ));
+            };
+
+            regions
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\r', '\n'])))
+                .filter(|(_, text)| !text.is_empty())
+                .map(|(style, text)| Span::styled(text.to_string(), to_style(style)))
+                .collect()
+        })
+        .collect()
+}
+
+/// The foreground and font style of a syntect style. The background is left
+/// to the terminal so highlighted code doesn't clash with the selection.
+fn to_style(style: highlighting::Style) -> Style {
+    let mut result = Style::default().fg(Color::Rgb(
+        style.foreground.r,
+        style.foreground.g,
+        style.foreground.b,
+    ));
+    if style.font_style.contains(FontStyle::BOLD) {
+        result = result.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        result = result.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        result = result.add_modifier(Modifier::UNDERLINED);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_known_language() {
+        let lines = highlight_code(
+            "fn main() {\n    let x = 1;\n}",
+            Some("rust"),
+            &Theme::default(),
+        );
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].to_string(), "    let x = 1;");
+        assert!(lines[0].spans.len() > 1);
+        assert!(
+            lines[0]
+                .spans
+                .iter()
+                .all(|span| matches!(span.style.fg, Some(Color::Rgb(..))))
+        );
+
+        // By extension, and with extra info after the language
+        assert!(
+            highlight_code("x = 1", Some("py"), &Theme::default())[0]
+                .spans
+                .len()
+                > 1
+        );
+        assert!(
+            highlight_code("fn f() {}", Some("rust,ignore"), &Theme::default())[0]
+                .spans
+                .len()
+                > 1
+        );
+    }
+
+    #[test]
+    fn test_highlight_fallback() {
+        let theme = Theme::default();
+        for lang in [None, Some(""), Some("no-such-language")] {
+            let lines = highlight_code("a\nb", lang, &theme);
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0].spans.len(), 1);
+            assert_eq!(lines[0].spans[0].style, theme.code);
+        }
+
+        // Themes without a syntax theme don't highlight
+        let theme = Theme::monochrome();
+        let lines = highlight_code("fn main() {}", Some("rust"), &theme);
+        assert_eq!(lines[0].spans.len(), 1);
+        assert_eq!(lines[0].spans[0].style, theme.code);
+    }
+}
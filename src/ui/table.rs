@@ -0,0 +1,415 @@
+use std::ops::Range;
+
+use mq_markdown::{Markdown, Node, TableAlignKind};
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::UnicodeWidthChar;
+
+use super::markdown;
+use crate::theme::Theme;
+
+/// Check if a node is part of a table
+pub fn is_table_node(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::TableHeader(_) | Node::TableRow(_) | Node::TableCell(_)
+    )
+}
+
+/// Split results into entries of the results list: consecutive table nodes
+/// of the same table are drawn together as one grid, every other node on its
+/// own. `sources` holds the document of each node in all files mode and is
+/// empty otherwise; tables never span documents.
+pub fn group_tables(nodes: &[Node], sources: &[usize]) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = Vec::new();
+
+    for (i, node) in nodes.iter().enumerate() {
+        match groups.last_mut() {
+            Some(group)
+                if is_table_node(node)
+                    && is_table_node(&nodes[group.start])
+                    && sources.get(group.start) == sources.get(i)
+                    && !starts_table(&nodes[group.clone()], node) =>
+            {
+                group.end = i + 1;
+            }
+            _ => groups.push(i..i + 1),
+        }
+    }
+
+    groups
+}
+
+/// Whether `node` begins another table rather than continuing the table
+/// nodes in `group`
+fn starts_table(group: &[Node], node: &Node) -> bool {
+    let last_cell = group.iter().rev().find_map(|node| match node {
+        Node::TableCell(cell) => Some(cell),
+        _ => None,
+    });
+
+    match node {
+        // Cells of one table come in row by row, left to right
+        Node::TableCell(cell) => last_cell.is_some_and(|last| {
+            last.last_cell_of_in_table || (cell.row, cell.column) <= (last.row, last.column)
+        }),
+        // A table has a single header
+        Node::TableHeader(_) => {
+            last_cell.is_some_and(|last| last.last_cell_of_in_table)
+                || group
+                    .iter()
+                    .any(|node| matches!(node, Node::TableHeader(_)))
+        }
+        _ => false,
+    }
+}
+
+struct Cell {
+    column: usize,
+    content: Line<'static>,
+    /// Index of the node the cell was built from
+    node: usize,
+}
+
+enum Row {
+    /// `row` is the row number of the cells in the original table
+    Cells {
+        row: Option<usize>,
+        cells: Vec<Cell>,
+    },
+    /// The line below the header, from a `TableHeader` node
+    Separator { node: usize },
+}
+
+/// Table nodes laid out as a grid with aligned columns
+pub struct Grid {
+    rows: Vec<Row>,
+    align: Vec<TableAlignKind>,
+    /// Display width of each column
+    widths: Vec<usize>,
+}
+
+impl Grid {
+    /// Lay out consecutive table nodes. Cells show their Markdown source,
+    /// or their rendered content when `rendered` is set.
+    pub fn new(nodes: &[Node], theme: &Theme, rendered: bool) -> Self {
+        let mut rows = Vec::new();
+        let mut align = Vec::new();
+
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::TableHeader(header) => {
+                    align = header.align.clone();
+                    rows.push(Row::Separator { node: i });
+                }
+                Node::TableRow(table_row) => {
+                    let cells = table_row
+                        .values
+                        .iter()
+                        .enumerate()
+                        .map(|(column, value)| match value {
+                            Node::TableCell(cell) => Cell {
+                                column: cell.column,
+                                content: cell_content(&cell.values, theme, rendered),
+                                node: i,
+                            },
+                            _ => Cell {
+                                column,
+                                content: cell_content(std::slice::from_ref(value), theme, rendered),
+                                node: i,
+                            },
+                        })
+                        .collect();
+                    rows.push(Row::Cells { row: None, cells });
+                }
+                Node::TableCell(cell) => {
+                    let new_cell = Cell {
+                        column: cell.column,
+                        content: cell_content(&cell.values, theme, rendered),
+                        node: i,
+                    };
+                    // Cells of the same row follow each other
+                    match rows.last_mut() {
+                        Some(Row::Cells {
+                            row: Some(row),
+                            cells,
+                        }) if *row == cell.row => cells.push(new_cell),
+                        _ => rows.push(Row::Cells {
+                            row: Some(cell.row),
+                            cells: vec![new_cell],
+                        }),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // The header's line goes below the header row
+        if matches!(rows.first(), Some(Row::Separator { .. })) && rows.len() > 1 {
+            rows.swap(0, 1);
+        }
+
+        let mut widths = vec![0; align.len()];
+        for cell in rows.iter().flat_map(|row| match row {
+            Row::Cells { cells, .. } => cells.as_slice(),
+            Row::Separator { .. } => &[],
+        }) {
+            if widths.len() <= cell.column {
+                widths.resize(cell.column + 1, 0);
+            }
+            widths[cell.column] = widths[cell.column].max(cell.content.width());
+        }
+        // Room for at least a short rule in every column
+        for width in &mut widths {
+            *width = (*width).max(3);
+        }
+
+        Self {
+            rows,
+            align,
+            widths,
+        }
+    }
+
+    /// Display width of the grid's lines
+    pub fn width(&self) -> usize {
+        self.widths.iter().map(|width| width + 3).sum::<usize>() + 1
+    }
+
+    /// Lines of the grid, highlighting the cells of the node at index
+    /// `selected` of the nodes the grid was built from
+    pub fn lines(&self, selected: Option<usize>, theme: &Theme) -> Vec<Line<'static>> {
+        let mut lines = vec![self.rule(["┌", "┬", "┐"], theme.dim)];
+
+        for row in &self.rows {
+            match row {
+                Row::Cells { cells, .. } => lines.push(self.cells_line(cells, selected, theme)),
+                Row::Separator { node } => lines.push(self.rule(
+                    ["├", "┼", "┤"],
+                    if Some(*node) == selected {
+                        theme.dim.patch(theme.selection)
+                    } else {
+                        theme.dim
+                    },
+                )),
+            }
+        }
+
+        lines.push(self.rule(["└", "┴", "┘"], theme.dim));
+        lines
+    }
+
+    fn rule(&self, [left, middle, right]: [&str; 3], style: Style) -> Line<'static> {
+        let segments = self
+            .widths
+            .iter()
+            .map(|width| "─".repeat(width + 2))
+            .collect::<Vec<_>>();
+        Line::from(Span::styled(
+            format!("{}{}{}", left, segments.join(middle), right),
+            style,
+        ))
+    }
+
+    fn cells_line(&self, cells: &[Cell], selected: Option<usize>, theme: &Theme) -> Line<'static> {
+        let mut spans = Vec::new();
+
+        for (column, width) in self.widths.iter().enumerate() {
+            spans.push(Span::styled("│", theme.dim));
+
+            let cell = cells.iter().find(|cell| cell.column == column);
+            let content = cell.map(|cell| cell.content.clone()).unwrap_or_default();
+            let padding = width - content.width();
+            let (left, right) = match self.align.get(column) {
+                Some(TableAlignKind::Right) => (padding, 0),
+                Some(TableAlignKind::Center) => (padding / 2, padding - padding / 2),
+                _ => (0, padding),
+            };
+
+            let style = if cell.is_some_and(|cell| Some(cell.node) == selected) {
+                theme.selection
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(" ".repeat(left + 1), style));
+            spans.extend(
+                content
+                    .spans
+                    .into_iter()
+                    .map(|span| span.patch_style(style)),
+            );
+            spans.push(Span::styled(" ".repeat(right + 1), style));
+        }
+
+        spans.push(Span::styled("│", theme.dim));
+        Line::from(spans)
+    }
+}
+
+/// Content of a cell on a single line
+fn cell_content(values: &[Node], theme: &Theme, rendered: bool) -> Line<'static> {
+    if rendered {
+        values
+            .iter()
+            .flat_map(|value| markdown::render_node(value, theme, 3))
+            .flat_map(|line| line.spans)
+            .collect()
+    } else {
+        let source = Markdown::new(values.to_vec()).to_string();
+        Line::from(source.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Drop the first `offset` display columns of a line, for scrolling wide
+/// tables horizontally. Half of a wide character becomes a space.
+pub fn skip_columns(line: Line<'static>, offset: usize) -> Line<'static> {
+    let mut skipped = 0;
+    let style = line.style;
+
+    let spans = line
+        .spans
+        .into_iter()
+        .filter_map(|span| {
+            if skipped >= offset {
+                return Some(span);
+            }
+
+            let mut content = String::new();
+            for c in span.content.chars() {
+                if skipped < offset {
+                    skipped += c.width().unwrap_or(0);
+                    if skipped > offset {
+                        content.push(' ');
+                    }
+                } else {
+                    content.push(c);
+                }
+            }
+            (!content.is_empty()).then(|| Span::styled(content, span.style))
+        })
+        .collect::<Vec<_>>();
+
+    Line::from(spans).style(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_nodes(markdown: &str) -> Vec<Node> {
+        Markdown::from_markdown_str(markdown)
+            .unwrap()
+            .nodes
+            .into_iter()
+            .filter(is_table_node)
+            .collect()
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_grid_alignment() {
+        let nodes = table_nodes(
+            "| Left | Center | Right |\n|:-----|:------:|------:|\n| a | b | c |\n| long cell | 漢字 | 1 |\n",
+        );
+        let grid = Grid::new(&nodes, &Theme::default(), false);
+        let lines = text(&grid.lines(None, &Theme::default()));
+
+        assert_eq!(
+            lines,
+            vec![
+                "┌───────────┬────────┬───────┐",
+                "│ Left      │ Center │ Right │",
+                "├───────────┼────────┼───────┤",
+                "│ a         │   b    │     c │",
+                "│ long cell │  漢字  │     1 │",
+                "└───────────┴────────┴───────┘",
+            ]
+        );
+        assert_eq!(grid.width(), 30);
+    }
+
+    #[test]
+    fn test_grid_highlights_selected_node() {
+        let theme = Theme::default();
+        let nodes = table_nodes("| a | b |\n|---|---|\n| c | d |\n");
+        let selected = nodes
+            .iter()
+            .position(
+                |node| matches!(node, Node::TableCell(cell) if cell.row == 1 && cell.column == 1),
+            )
+            .unwrap();
+
+        let lines = Grid::new(&nodes, &theme, false).lines(Some(selected), &theme);
+        let highlighted = lines
+            .iter()
+            .flat_map(|line| &line.spans)
+            .filter(|span| span.style == theme.selection)
+            .map(|span| span.content.to_string())
+            .collect::<String>();
+        assert_eq!(highlighted.trim(), "d");
+    }
+
+    #[test]
+    fn test_group_tables() {
+        let nodes =
+            Markdown::from_markdown_str("# Title\n\n| a | b |\n|---|---|\n| c | d |\n\ntext\n")
+                .unwrap()
+                .nodes;
+        let groups = group_tables(&nodes, &[]);
+
+        assert_eq!(groups.first(), Some(&(0..1)));
+        let tables = groups
+            .iter()
+            .filter(|group| is_table_node(&nodes[group.start]))
+            .collect::<Vec<_>>();
+        assert_eq!(tables.len(), 1);
+        assert!(nodes[tables[0].clone()].iter().all(is_table_node));
+    }
+
+    #[test]
+    fn test_group_adjacent_tables() {
+        let nodes =
+            Markdown::from_markdown_str("| a | b |\n|---|---|\n| 1 | 2 |\n\n| c |\n|---|\n| 3 |\n")
+                .unwrap()
+                .nodes;
+        assert!(nodes.iter().all(is_table_node));
+
+        // Each table is its own grid
+        assert_eq!(group_tables(&nodes, &[]), vec![0..5, 5..8]);
+
+        // Cells selected from both tables, e.g. their first rows
+        let cells = [&nodes[0..2], &nodes[5..6]].concat();
+        assert_eq!(group_tables(&cells, &[]), vec![0..2, 2..3]);
+    }
+
+    #[test]
+    fn test_group_tables_from_different_sources() {
+        let table = Markdown::from_markdown_str("| a |\n|---|\n| 1 |\n")
+            .unwrap()
+            .nodes;
+        // The same cells from two documents, without the header
+        let nodes = [&table[2..], &table[2..]].concat();
+        assert_eq!(group_tables(&nodes, &[]), vec![0..1, 1..2]);
+
+        let rows = Markdown::from_markdown_str("| a |\n|---|\n| 1 |\n| 2 |\n")
+            .unwrap()
+            .nodes;
+        let nodes = [&rows[2..3], &rows[3..4]].concat();
+        assert_eq!(group_tables(&nodes, &[]), vec![0..2]);
+        assert_eq!(group_tables(&nodes, &[0, 1]), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn test_skip_columns() {
+        let line = Line::from(vec![Span::raw("ab"), Span::raw("漢字c")]);
+        assert_eq!(skip_columns(line.clone(), 0).to_string(), "ab漢字c");
+        assert_eq!(skip_columns(line.clone(), 3).to_string(), " 字c");
+        assert_eq!(skip_columns(line.clone(), 4).to_string(), "字c");
+        assert_eq!(skip_columns(line, 10).to_string(), "");
+    }
+}
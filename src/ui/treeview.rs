@@ -2,12 +2,14 @@ use mq_markdown::Node;
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, ListState},
 };
 use std::collections::HashMap;
 
+use crate::{node::node_children, theme::Theme};
+
 #[derive(Debug, Clone)]
 pub struct TreeItem {
     pub node: Node,
@@ -126,41 +128,11 @@ impl TreeItem {
     }
 
     fn has_children(node: &Node) -> bool {
-        match node {
-            Node::Heading(h) => !h.values.is_empty(),
-            Node::List(l) => !l.values.is_empty(),
-            Node::Blockquote(b) => !b.values.is_empty(),
-            Node::Strong(s) => !s.values.is_empty(),
-            Node::Emphasis(e) => !e.values.is_empty(),
-            Node::Link(l) => !l.values.is_empty(),
-            Node::Delete(d) => !d.values.is_empty(),
-            Node::Fragment(f) => !f.values.is_empty(),
-            Node::Footnote(f) => !f.values.is_empty(),
-            Node::TableRow(r) => !r.values.is_empty(),
-            Node::TableCell(c) => !c.values.is_empty(),
-            Node::MdxJsxFlowElement(e) => !e.children.is_empty(),
-            Node::MdxJsxTextElement(e) => !e.children.is_empty(),
-            _ => false,
-        }
+        !node_children(node).is_empty()
     }
 
     pub fn get_children(&self) -> Vec<Node> {
-        match &self.node {
-            Node::Heading(h) => h.values.clone(),
-            Node::List(l) => l.values.clone(),
-            Node::Blockquote(b) => b.values.clone(),
-            Node::Strong(s) => s.values.clone(),
-            Node::Emphasis(e) => e.values.clone(),
-            Node::Link(l) => l.values.clone(),
-            Node::Delete(d) => d.values.clone(),
-            Node::Fragment(f) => f.values.clone(),
-            Node::Footnote(f) => f.values.clone(),
-            Node::TableRow(r) => r.values.clone(),
-            Node::TableCell(c) => c.values.clone(),
-            Node::MdxJsxFlowElement(e) => e.children.clone(),
-            Node::MdxJsxTextElement(e) => e.children.clone(),
-            _ => vec![],
-        }
+        node_children(&self.node).to_vec()
     }
 }
 
@@ -258,15 +230,28 @@ impl TreeView {
         self.selected_index
     }
 
+    /// Select the item at `index`, clamped to the last item
+    pub fn select(&mut self, index: usize) {
+        self.selected_index = index.min(self.items.len().saturating_sub(1));
+    }
+
+    /// Replace the nodes, e.g. after the document was reloaded, keeping the
+    /// expanded items and the selection where possible
+    pub fn set_nodes(&mut self, nodes: Vec<Node>) {
+        self.original_nodes = nodes;
+        self.rebuild_items();
+        self.select(self.selected_index);
+    }
+
     pub fn items(&self) -> &[TreeItem] {
         &self.items
     }
 
-    pub fn render(&self, frame: &mut Frame, area: Rect) {
-        self.render_with_title(frame, area, "Document Tree");
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        self.render_with_title(frame, area, "Document Tree", theme);
     }
 
-    pub fn render_with_title(&self, frame: &mut Frame, area: Rect, title: &str) {
+    pub fn render_with_title(&self, frame: &mut Frame, area: Rect, title: &str, theme: &Theme) {
         let items: Vec<ListItem> = self
             .items
             .iter()
@@ -277,11 +262,9 @@ impl TreeView {
                     let line = Line::from(vec![Span::styled(
                         tree_item.display_text.clone(),
                         if i == self.selected_index {
-                            Style::default().fg(Color::Black).bg(Color::White)
+                            theme.selection
                         } else {
-                            Style::default()
-                                .fg(Color::Cyan)
-                                .add_modifier(Modifier::BOLD)
+                            theme.header
                         },
                     )]);
                     return ListItem::new(line);
@@ -320,9 +303,9 @@ impl TreeView {
                 let line = Line::from(vec![Span::styled(
                     full_content,
                     if i == self.selected_index {
-                        Style::default().fg(Color::Black).bg(Color::White)
+                        theme.selection
                     } else {
-                        Self::get_node_style(&tree_item.node)
+                        Self::get_node_style(&tree_item.node, theme)
                     },
                 )]);
 
@@ -340,21 +323,19 @@ impl TreeView {
         frame.render_stateful_widget(list, area, &mut state);
     }
 
-    fn get_node_style(node: &Node) -> Style {
+    pub fn get_node_style(node: &Node, theme: &Theme) -> Style {
         match node {
-            Node::Heading(_) => Style::default()
-                .fg(Color::Blue)
-                .add_modifier(Modifier::BOLD),
-            Node::List(_) => Style::default().fg(Color::Green),
-            Node::Code(_) | Node::CodeInline(_) => Style::default().fg(Color::Cyan),
-            Node::Link(_) | Node::LinkRef(_) => Style::default().fg(Color::Magenta),
+            Node::Heading(_) => theme.heading,
+            Node::List(_) => theme.list,
+            Node::Code(_) | Node::CodeInline(_) => theme.code,
+            Node::Link(_) | Node::LinkRef(_) => theme.link,
             Node::Strong(_) => Style::default().add_modifier(Modifier::BOLD),
             Node::Emphasis(_) => Style::default().add_modifier(Modifier::ITALIC),
-            Node::Image(_) | Node::ImageRef(_) => Style::default().fg(Color::Yellow),
-            Node::Math(_) | Node::MathInline(_) => Style::default().fg(Color::Red),
-            Node::Blockquote(_) => Style::default().fg(Color::LightBlue),
-            Node::HorizontalRule(_) => Style::default().fg(Color::DarkGray),
-            _ => Style::default().fg(Color::Gray),
+            Node::Image(_) | Node::ImageRef(_) => theme.image,
+            Node::Math(_) | Node::MathInline(_) => theme.math,
+            Node::Blockquote(_) => theme.blockquote,
+            Node::HorizontalRule(_) => theme.horizontal_rule,
+            _ => theme.node,
         }
     }
 }
@@ -414,6 +395,24 @@ mod tests {
         assert_eq!(tree_view.selected_index, 0);
     }
 
+    #[test]
+    fn test_set_nodes_keeps_selection() {
+        let nodes = vec![create_test_heading(), create_test_text()];
+        let mut tree_view = TreeView::new(nodes);
+        tree_view.toggle_expand();
+        tree_view.move_down();
+        let expanded_count = tree_view.items.len();
+
+        tree_view.set_nodes(vec![create_test_heading(), create_test_text()]);
+        assert_eq!(tree_view.items.len(), expanded_count);
+        assert_eq!(tree_view.selected_index, 1);
+
+        // The selection is clamped when the document shrinks
+        tree_view.select(10);
+        tree_view.set_nodes(vec![create_test_text()]);
+        assert_eq!(tree_view.selected_index, 0);
+    }
+
     #[test]
     fn test_toggle_expand() {
         let nodes = vec![create_test_heading()];
@@ -1097,7 +1096,7 @@ mod tests {
         // Test rendering
         let result = terminal.draw(|frame| {
             let area = Rect::new(0, 0, 80, 10);
-            tree_view.render(frame, area);
+            tree_view.render(frame, area, &Theme::default());
         });
 
         assert!(result.is_ok());
@@ -1137,7 +1136,7 @@ mod tests {
         // Test rendering with expanded items
         let result = terminal.draw(|frame| {
             let area = Rect::new(0, 0, 80, 10);
-            tree_view.render(frame, area);
+            tree_view.render(frame, area, &Theme::default());
         });
 
         assert!(result.is_ok());
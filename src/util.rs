@@ -5,14 +5,27 @@ use crossterm::{
 };
 use miette::IntoDiagnostic;
 use ratatui::prelude::*;
-use std::io;
+use std::io::{self, IsTerminal, Write};
 
-pub fn setup_terminal() -> miette::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+/// Stream the TUI is drawn to
+pub type TerminalWriter = Box<dyn Write>;
+
+/// Draw to stdout, or to stderr when stdout is redirected so that output
+/// printed on exit can be piped into other commands.
+fn terminal_writer() -> TerminalWriter {
+    if io::stdout().is_terminal() {
+        Box::new(io::stdout())
+    } else {
+        Box::new(io::stderr())
+    }
+}
+
+pub fn setup_terminal() -> miette::Result<Terminal<CrosstermBackend<TerminalWriter>>> {
     enable_raw_mode().into_diagnostic()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture).into_diagnostic()?;
+    let mut writer = terminal_writer();
+    execute!(writer, EnterAlternateScreen, EnableMouseCapture).into_diagnostic()?;
 
-    let backend = CrosstermBackend::new(stdout);
+    let backend = CrosstermBackend::new(writer);
     let terminal = Terminal::new(backend).into_diagnostic()?;
 
     Ok(terminal)
@@ -21,8 +34,8 @@ pub fn setup_terminal() -> miette::Result<Terminal<CrosstermBackend<io::Stdout>>
 pub fn restore_terminal() -> miette::Result<()> {
     // Restore terminal
     disable_raw_mode().into_diagnostic()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, LeaveAlternateScreen, DisableMouseCapture).into_diagnostic()?;
+    let mut writer = terminal_writer();
+    execute!(writer, LeaveAlternateScreen, DisableMouseCapture).into_diagnostic()?;
 
     Ok(())
 }
@@ -0,0 +1,332 @@
+use mq_lang::Engine;
+use mq_markdown::Node;
+use std::{
+    sync::{Arc, mpsc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::eval::{self, EvalError, Evaluator};
+
+/// Abandoned queries allowed to keep running before new queries are refused,
+/// so runaway queries can't occupy every core
+pub const MAX_ABANDONED_WORKERS: usize = 2;
+
+/// Stack size of the worker thread, enough for the engine's maximum call depth
+const WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Limits that protect the UI against runaway queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// How long to wait for a query before giving up on its results
+    pub timeout: Duration,
+    /// Maximum number of result nodes kept for a query
+    pub max_results: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            max_results: 100_000,
+        }
+    }
+}
+
+impl QueryLimits {
+    /// Error reported when a query hits one of the limits
+    pub fn exceeded_message(&self) -> String {
+        format!(
+            "query exceeded {} / {} results",
+            format_duration(self.timeout),
+            format_count(self.max_results)
+        )
+    }
+}
+
+/// Format a duration compactly, e.g. `2s`, `1.5s` or `500ms`
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else if duration.subsec_millis() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// Format a count compactly, e.g. `100k` or `1M`
+fn format_count(count: usize) -> String {
+    if count >= 1_000_000 && count.is_multiple_of(1_000_000) {
+        format!("{}M", count / 1_000_000)
+    } else if count >= 1_000 && count.is_multiple_of(1_000) {
+        format!("{}k", count / 1_000)
+    } else {
+        count.to_string()
+    }
+}
+
+/// A document to run a query against
+#[derive(Debug, Clone)]
+pub struct JobDocument {
+    /// Index of the document in the app
+    pub idx: usize,
+    /// Name prefixed to error messages (only set when querying several documents)
+    pub name: Option<String>,
+    pub nodes: Arc<Vec<Node>>,
+}
+
+/// A query to evaluate on the worker
+#[derive(Debug, Clone)]
+pub struct QueryJob {
+    /// Identifies the job, so results of superseded queries can be discarded
+    pub id: u64,
+    pub query: String,
+    pub documents: Vec<JobDocument>,
+    /// Results beyond this count stop the query with [`EvalError::LimitExceeded`]
+    pub max_results: usize,
+}
+
+/// Results of a query, tagged with the id of the job that produced them
+#[derive(Debug)]
+pub struct QueryOutcome {
+    pub id: u64,
+    /// Result nodes and the index of the document each one came from
+    pub result: Result<(Vec<Node>, Vec<usize>), EvalError>,
+    pub eval_time: Duration,
+}
+
+/// Evaluate a query against each document in turn, stopping at the first error.
+///
+/// `engine` must be fresh from [`eval::new_engine`], it's used up by the query.
+pub fn run_job(evaluator: &mut Evaluator, mut engine: Engine, job: QueryJob) -> QueryOutcome {
+    let start = Instant::now();
+    let mut results = Vec::new();
+    let mut sources = Vec::new();
+
+    for document in &job.documents {
+        // One result more than fits tells the query went over the cap
+        let remaining = job.max_results - results.len();
+        match evaluator.eval(
+            &mut engine,
+            &job.query,
+            document.idx,
+            &document.nodes,
+            remaining.saturating_add(1),
+        ) {
+            Ok(nodes) if nodes.len() > remaining => {
+                return QueryOutcome {
+                    id: job.id,
+                    result: Err(EvalError::LimitExceeded),
+                    eval_time: start.elapsed(),
+                };
+            }
+            Ok(nodes) => {
+                sources.extend(std::iter::repeat_n(document.idx, nodes.len()));
+                results.extend(nodes);
+            }
+            Err(err) => {
+                let err = match &document.name {
+                    Some(name) => err.with_source(name),
+                    None => err,
+                };
+                return QueryOutcome {
+                    id: job.id,
+                    result: Err(err),
+                    eval_time: start.elapsed(),
+                };
+            }
+        }
+    }
+
+    QueryOutcome {
+        id: job.id,
+        result: Ok((results, sources)),
+        eval_time: start.elapsed(),
+    }
+}
+
+/// Background thread that evaluates queries so slow ones don't block the UI.
+///
+/// Queries can't be interrupted inside the engine, so cancelling a query
+/// abandons the worker; the thread exits once the query ends.
+pub struct QueryWorker {
+    jobs: mpsc::Sender<QueryJob>,
+    outcomes: mpsc::Receiver<QueryOutcome>,
+    thread: JoinHandle<()>,
+}
+
+impl QueryWorker {
+    pub fn spawn() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<QueryJob>();
+        let (outcome_sender, outcomes) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || {
+                let mut evaluator = Evaluator::default();
+
+                loop {
+                    // Load the engine for the next query while waiting for it
+                    let engine = eval::new_engine();
+                    let Ok(mut job) = job_receiver.recv() else {
+                        break;
+                    };
+                    // Only the newest query matters, skip the ones typed in between
+                    while let Ok(newer) = job_receiver.try_recv() {
+                        job = newer;
+                    }
+
+                    if outcome_sender
+                        .send(run_job(&mut evaluator, engine, job))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the query worker");
+
+        Self {
+            jobs,
+            outcomes,
+            thread,
+        }
+    }
+
+    /// Give up on the running query. The returned thread finishes once the
+    /// query ends.
+    pub fn abandon(self) -> JoinHandle<()> {
+        self.thread
+    }
+
+    /// Queue a query for evaluation
+    pub fn submit(&self, job: QueryJob) {
+        // The worker only stops when this handle is dropped
+        let _ = self.jobs.send(job);
+    }
+
+    /// Next finished query, if any
+    pub fn try_recv(&self) -> Option<QueryOutcome> {
+        self.outcomes.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next finished query
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<QueryOutcome> {
+        self.outcomes.recv_timeout(timeout).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::ParsedDocuments;
+
+    fn job(id: u64, query: &str) -> QueryJob {
+        let mut documents = ParsedDocuments::default();
+        QueryJob {
+            id,
+            query: query.to_string(),
+            max_results: 100,
+            documents: vec![
+                JobDocument {
+                    idx: 0,
+                    name: Some("one.md".to_string()),
+                    nodes: documents.parse(0, "# One\n").unwrap(),
+                },
+                JobDocument {
+                    idx: 1,
+                    name: Some("two.md".to_string()),
+                    nodes: documents.parse(1, "# Two\n\n## Three\n").unwrap(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_run_job_tags_sources() {
+        let mut evaluator = Evaluator::default();
+        let outcome = run_job(&mut evaluator, eval::new_engine(), job(1, ".h"));
+
+        assert_eq!(outcome.id, 1);
+        let (results, sources) = outcome.result.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(sources, vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn test_run_job_result_limit() {
+        let mut evaluator = Evaluator::default();
+        let mut job = job(1, ".h");
+        job.max_results = 2;
+
+        let outcome = run_job(&mut evaluator, eval::new_engine(), job);
+        assert!(matches!(outcome.result, Err(EvalError::LimitExceeded)));
+    }
+
+    #[test]
+    fn test_exceeded_message() {
+        assert_eq!(
+            QueryLimits::default().exceeded_message(),
+            "query exceeded 2s / 100k results"
+        );
+        let limits = QueryLimits {
+            timeout: Duration::from_millis(1500),
+            max_results: 2500,
+        };
+        assert_eq!(
+            limits.exceeded_message(),
+            "query exceeded 1.5s / 2500 results"
+        );
+        let limits = QueryLimits {
+            timeout: Duration::from_millis(250),
+            max_results: 1_000_000,
+        };
+        assert_eq!(
+            limits.exceeded_message(),
+            "query exceeded 250ms / 1M results"
+        );
+    }
+
+    #[test]
+    fn test_run_job_error_names_document() {
+        let mut evaluator = Evaluator::default();
+        let outcome = run_job(
+            &mut evaluator,
+            eval::new_engine(),
+            job(1, ".h | unknown_fn()"),
+        );
+
+        match outcome.result {
+            Err(EvalError::Query(msg, _)) => assert!(msg.starts_with("one.md: ")),
+            _ => panic!("expected a query error"),
+        }
+    }
+
+    #[test]
+    fn test_worker_stops_runaway_recursion() {
+        let worker = QueryWorker::spawn();
+        worker.submit(job(3, "def f(x): f(x); | f(1)"));
+
+        let outcome = worker.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(outcome.id, 3);
+        assert!(matches!(outcome.result, Err(EvalError::Query(_, _))));
+    }
+
+    #[test]
+    fn test_worker_returns_outcome() {
+        let worker = QueryWorker::spawn();
+        worker.submit(job(7, ".h"));
+
+        let start = Instant::now();
+        let outcome = loop {
+            if let Some(outcome) = worker.try_recv() {
+                break outcome;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(outcome.id, 7);
+        assert!(outcome.result.is_ok());
+    }
+}